@@ -12,6 +12,11 @@ endif
 
 syn keyword   resynthImport      import nextgroup=resynthModPath skipwhite skipempty
 syn keyword   resynthKeyword     let nextgroup=resynthVariable skipWhite
+syn keyword   resynthKeyword     fn nextgroup=resynthFuncName skipWhite
+syn keyword   resynthKeyword     as
+syn keyword   resynthKeyword     mut nextgroup=resynthVariable skipWhite
+syn keyword   resynthRepeat      for in
+syn keyword   resynthConditional if else
 
 syn match     resynthModPath     "\w\(\w\)*::[^<]"he=e-3,me=e-3
 syn match     resynthModPathSep  "::"
@@ -32,8 +37,8 @@ syn match     resynthOperator    display "\%(+\|-\|/\|*\|=\|\^\|&\||\|!\|>\|<\|%
 syn match     resynthEscape      display contained "|\([0-9a-fA-F]\|\s\)*|"
 syn region    resynthString      start=+"+ end=+"+ contains=resynthEscape,@Spell
 
-syn match     resynthDecNumber   display "\<[0-9][0-9_]*"
-syn match     resynthHexNumber   display "\<0x[a-fA-F0-9_]\+"
+syn match     resynthDecNumber   display "\<[0-9][0-9_]*\%([ui]\%(8\|16\|32\|64\)\)\="
+syn match     resynthHexNumber   display "\<0x[a-fA-F0-9_]\+\%([ui]\%(8\|16\|32\|64\)\)\="
 
 syn match     resynthShebang        /\%^#![^[].*/
 syn region    resynthCommentLine    start="#"   end="$"   contains=resynthTodo,@Spell
@@ -57,6 +62,8 @@ hi def link resynthBoolean       Boolean
 hi def link resynthConstant      Constant
 hi def link resynthOperator      Operator
 hi def link resynthKeyword       Keyword
+hi def link resynthRepeat        Repeat
+hi def link resynthConditional   Conditional
 hi def link resynthIdentifier    Identifier
 hi def link resynthModPath       Include
 hi def link resynthModPathSep    Delimiter
@@ -26,7 +26,7 @@ impl GreFrame {
         extra: usize,
     ) -> Self {
         let pkt = if raw {
-            Packet::with_capacity(Self::RAW_OVERHEAD + extra)
+            Packet::with_capacity(Self::RAW_OVERHEAD + extra).with_raw(true)
         } else {
             let pkt = Packet::with_capacity(Self::OVERHEAD + extra);
 
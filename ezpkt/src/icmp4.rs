@@ -31,7 +31,7 @@ impl IcmpDgram {
 
     fn new(src: Ipv4Addr, dst: Ipv4Addr, raw: bool) -> Self {
         let pkt = if raw {
-            Packet::with_capacity(Self::RAW_OVERHEAD)
+            Packet::with_capacity(Self::RAW_OVERHEAD).with_raw(true)
         } else {
             let pkt = Packet::with_capacity(Self::OVERHEAD);
 
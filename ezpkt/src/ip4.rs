@@ -16,7 +16,7 @@ pub struct IpDgram {
 impl IpDgram {
     #[must_use]
     pub fn new(mut iph: ip_hdr, payload: &[u8], raw: bool) -> Self {
-        let pkt = Packet::with_capacity(IP_DGRAM_OVERHEAD + payload.len());
+        let pkt = Packet::with_capacity(IP_DGRAM_OVERHEAD + payload.len()).with_raw(raw);
 
         if !raw {
             pkt.push(eth_hdr::new(
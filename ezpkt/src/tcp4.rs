@@ -2,7 +2,7 @@ use std::net::SocketAddrV4;
 
 use pkt::eth::{eth_hdr, ethertype};
 use pkt::ipv4::{ip_csum_fold, ip_csum_partial, ip_hdr, ip_pseudo_hdr, proto, tcp_hdr};
-use pkt::{Hdr, Packet};
+use pkt::{Direction, Hdr, Packet};
 
 #[derive(Debug, PartialEq, Eq)]
 struct TcpState {
@@ -30,7 +30,7 @@ impl TcpSeg {
         let daddr = *dst.ip();
 
         let pkt = if raw {
-            Packet::with_capacity(Self::RAW_OVERHEAD)
+            Packet::with_capacity(Self::RAW_OVERHEAD).with_raw(true)
         } else {
             let pkt = Packet::with_capacity(Self::OVERHEAD);
 
@@ -141,6 +141,11 @@ impl TcpSeg {
         self
     }
 
+    fn direction(mut self, dir: Direction) -> Self {
+        self.pkt.meta_mut().direction = Some(dir);
+        self
+    }
+
     fn frag_off(self, frag_off: u16) -> Self {
         {
             let mut iph = self.ip.get_mut(&self.pkt);
@@ -256,19 +261,19 @@ impl TcpFlow {
     }
 
     fn cl(&self) -> TcpSeg {
-        TcpSeg::new(self.cl, self.sv, self.cl_state(), self.raw)
+        TcpSeg::new(self.cl, self.sv, self.cl_state(), self.raw).direction(Direction::Outbound)
     }
 
     fn sv(&self) -> TcpSeg {
-        TcpSeg::new(self.sv, self.cl, self.sv_state(), self.raw)
+        TcpSeg::new(self.sv, self.cl, self.sv_state(), self.raw).direction(Direction::Inbound)
     }
 
     fn cl_update(&mut self, bytes: u32) {
-        self.cl_seq += bytes;
+        self.cl_seq = self.cl_seq.wrapping_add(bytes);
     }
 
     fn sv_update(&mut self, bytes: u32) {
-        self.sv_seq += bytes;
+        self.sv_seq = self.sv_seq.wrapping_add(bytes);
     }
 
     fn cl_tx(&mut self, seg: TcpSeg) {
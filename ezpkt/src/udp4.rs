@@ -3,7 +3,7 @@ use std::rc::Rc;
 
 use pkt::eth::{eth_hdr, ethertype};
 use pkt::ipv4::{ip_csum_fold, ip_csum_partial, ip_hdr, ip_pseudo_hdr, proto, udp_hdr};
-use pkt::{Hdr, Packet};
+use pkt::{Direction, Hdr, Packet};
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct UdpFlow {
@@ -28,7 +28,7 @@ impl UdpDgram {
     #[must_use]
     pub fn with_capacity(payload_sz: usize, raw: bool) -> Self {
         let pkt = if raw {
-            Packet::with_capacity(Self::RAW_OVERHEAD + payload_sz)
+            Packet::with_capacity(Self::RAW_OVERHEAD + payload_sz).with_raw(true)
         } else {
             Packet::with_capacity(Self::OVERHEAD + payload_sz)
         };
@@ -77,6 +77,12 @@ impl UdpDgram {
         self
     }
 
+    #[must_use]
+    pub fn direction(mut self, dir: Direction) -> Self {
+        self.pkt.meta_mut().direction = Some(dir);
+        self
+    }
+
     #[must_use]
     pub fn src(self, src: SocketAddrV4) -> Self {
         if let Some(eth) = self.eth {
@@ -196,11 +202,17 @@ impl UdpFlow {
     }
 
     fn clnt(&self) -> UdpDgram {
-        UdpDgram::of_type(self.raw).src(self.cl).dst(self.sv)
+        UdpDgram::of_type(self.raw)
+            .src(self.cl)
+            .dst(self.sv)
+            .direction(Direction::Outbound)
     }
 
     fn srvr(&self) -> UdpDgram {
-        UdpDgram::of_type(self.raw).src(self.sv).dst(self.cl)
+        UdpDgram::of_type(self.raw)
+            .src(self.sv)
+            .dst(self.cl)
+            .direction(Direction::Inbound)
     }
 
     pub fn client_dgram(&mut self, bytes: &[u8]) -> UdpDgram {
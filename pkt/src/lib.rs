@@ -12,11 +12,20 @@ pub mod vxlan;
 mod util;
 pub use util::{AsBytes, Serialize};
 
+mod link;
+pub use link::LinkType;
+
 mod pcap;
-pub use pcap::{LinkType, PcapWriter};
+pub use pcap::{CaptureWriter, PcapWriter};
+
+mod pcapng;
+pub use pcapng::PcapNgWriter;
+
+mod reader;
+pub use reader::{PcapReader, Record};
 
 mod packet;
-pub use packet::{Hdr, Packet, PktSlice, Ref, RefMut, SliceRef, SliceRefMut};
+pub use packet::{Direction, Hdr, Meta, Packet, PktSlice, Ref, RefMut, SliceRef, SliceRefMut};
 
 #[cfg(test)]
 mod test;
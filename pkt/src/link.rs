@@ -0,0 +1,298 @@
+use std::convert::TryInto;
+use std::fmt;
+use std::io;
+use std::net::Ipv4Addr;
+
+use super::eth::{eth_addr, eth_hdr, ethertype};
+use super::{Direction, Packet, Serialize};
+
+/// The link-layer header type of a capture file, which all of its packets must share
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LinkType {
+    /// BSD loopback: a 4-byte address family in host byte-order
+    Null = 0,
+    Ethernet = 1,
+    /// IPv4 or IPv6 with no link-layer header at all
+    Raw = 101,
+    /// Linux "cooked" capture, as from `tcpdump -i any`
+    LinuxSll = 113,
+    /// Linux "cooked" capture v2, which also records the interface index
+    LinuxSll2 = 276,
+}
+
+const AF_INET: u32 = 2;
+/// There's no portable value for this, so use the darwin one, which wireshark understands
+const AF_INET6: u32 = 30;
+
+const ARPHRD_ETHER: u16 = 1;
+
+const PACKET_HOST: u8 = 0;
+const PACKET_OUTGOING: u8 = 4;
+
+#[repr(C, packed(1))]
+#[derive(Debug, Copy, Clone, Default)]
+struct sll_hdr {
+    pkttype: u16,
+    hatype: u16,
+    halen: u16,
+    addr: [u8; 8],
+    proto: u16,
+}
+
+impl Serialize for sll_hdr {}
+
+#[repr(C, packed(1))]
+#[derive(Debug, Copy, Clone, Default)]
+struct sll2_hdr {
+    proto: u16,
+    reserved: u16,
+    ifindex: u32,
+    hatype: u16,
+    pkttype: u8,
+    halen: u8,
+    addr: [u8; 8],
+}
+
+impl Serialize for sll2_hdr {}
+
+/// The parts of a packet which every link-layer header is built from
+struct Frame<'a> {
+    src: eth_addr,
+    dst: eth_addr,
+    proto: u16,
+    payload: &'a [u8],
+    /// There was no link-layer header to begin with
+    raw: bool,
+}
+
+impl<'a> Frame<'a> {
+    /// Packets are ethernet frames, unless they were created with `raw` and so start with an IP
+    /// header, which is recorded in their [metadata](crate::Meta)
+    fn parse(buf: &'a [u8], raw: bool) -> Result<Self, io::Error> {
+        match buf.first().map(|b| b >> 4) {
+            Some(4) if raw && buf.len() >= 20 => Ok(Self {
+                src: Ipv4Addr::new(buf[12], buf[13], buf[14], buf[15]).into(),
+                dst: Ipv4Addr::new(buf[16], buf[17], buf[18], buf[19]).into(),
+                proto: ethertype::IPV4,
+                payload: buf,
+                raw: true,
+            }),
+            Some(6) if raw && buf.len() >= 40 => Ok(Self {
+                src: eth_addr::default(),
+                dst: eth_addr::default(),
+                proto: ethertype::IPV6,
+                payload: buf,
+                raw: true,
+            }),
+            _ if raw => Err(invalid(format!(
+                "{} byte packet is not a valid IP packet",
+                buf.len()
+            ))),
+            _ if buf.len() >= 14 => Ok(Self {
+                dst: eth_addr::new(buf[0..6].try_into().unwrap()),
+                src: eth_addr::new(buf[6..12].try_into().unwrap()),
+                proto: u16::from_be_bytes([buf[12], buf[13]]),
+                payload: &buf[14..],
+                raw: false,
+            }),
+            _ => Err(invalid(format!(
+                "{} byte packet is too short for a link-layer header",
+                buf.len()
+            ))),
+        }
+    }
+
+    /// Only IP can be carried without a link-layer header to say what the protocol is
+    fn address_family(&self, linktype: LinkType) -> Result<u32, io::Error> {
+        match self.proto {
+            ethertype::IPV4 => Ok(AF_INET),
+            ethertype::IPV6 => Ok(AF_INET6),
+            proto => Err(invalid(format!(
+                "{} captures can only contain IP packets, not ethertype {:#06x}",
+                linktype, proto
+            ))),
+        }
+    }
+
+    fn sll_addr(&self) -> [u8; 8] {
+        let mut ret = [0; 8];
+        ret[..6].copy_from_slice((&self.src).as_ref());
+        ret
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl LinkType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "null" => Some(LinkType::Null),
+            "ethernet" => Some(LinkType::Ethernet),
+            "raw" => Some(LinkType::Raw),
+            "linux_sll" => Some(LinkType::LinuxSll),
+            "linux_sll2" => Some(LinkType::LinuxSll2),
+            _ => None,
+        }
+    }
+
+    pub fn from_u32(val: u32) -> Option<Self> {
+        match val {
+            0 => Some(LinkType::Null),
+            1 => Some(LinkType::Ethernet),
+            101 => Some(LinkType::Raw),
+            113 => Some(LinkType::LinuxSll),
+            276 => Some(LinkType::LinuxSll2),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LinkType::Null => "null",
+            LinkType::Ethernet => "ethernet",
+            LinkType::Raw => "raw",
+            LinkType::LinuxSll => "linux_sll",
+            LinkType::LinuxSll2 => "linux_sll2",
+        }
+    }
+
+    /// Rewrite the link-layer header of a packet to suit this linktype. Returns [None] if the
+    /// packet can be written as it is, or an [InvalidData](io::ErrorKind::InvalidData) error if
+    /// it can't be represented at all, eg. ARP in a raw IP capture.
+    pub fn adapt(&self, pkt: &Packet) -> Result<Option<Packet>, io::Error> {
+        let buf = pkt.bytes();
+
+        /* Anything goes in ethernet captures, as long as it's not missing the header entirely */
+        if *self == LinkType::Ethernet && !pkt.meta().raw {
+            return Ok(None);
+        }
+
+        let frame = Frame::parse(&buf, pkt.meta().raw)?;
+
+        let mut ret = Packet::with_capacity(frame.payload.len() + 20);
+
+        match self {
+            LinkType::Ethernet if frame.raw => {
+                ret.push(eth_hdr::new(frame.src, frame.dst, frame.proto));
+            }
+            LinkType::Ethernet => return Ok(None),
+            LinkType::Raw => {
+                frame.address_family(*self)?;
+                if frame.raw {
+                    return Ok(None);
+                }
+            }
+            LinkType::Null => {
+                ret.push_bytes(frame.address_family(*self)?.to_ne_bytes());
+            }
+            LinkType::LinuxSll => {
+                ret.push(sll_hdr {
+                    pkttype: (pkttype(pkt) as u16).to_be(),
+                    hatype: ARPHRD_ETHER.to_be(),
+                    halen: 6u16.to_be(),
+                    addr: frame.sll_addr(),
+                    proto: frame.proto.to_be(),
+                });
+            }
+            LinkType::LinuxSll2 => {
+                ret.push(sll2_hdr {
+                    proto: frame.proto.to_be(),
+                    reserved: 0,
+                    ifindex: 0,
+                    hatype: ARPHRD_ETHER.to_be(),
+                    pkttype: pkttype(pkt),
+                    halen: 6,
+                    addr: frame.sll_addr(),
+                });
+            }
+        }
+
+        ret.push_bytes(frame.payload);
+        *ret.meta_mut() = pkt.meta().clone();
+        ret.meta_mut().raw = *self == LinkType::Raw;
+
+        Ok(Some(ret))
+    }
+}
+
+impl LinkType {
+    /// Turn a packet read from a capture of this linktype into the form which resynth builds
+    /// packets in: an ethernet frame, or a raw IP packet. This is the reverse of
+    /// [adapt](Self::adapt), but the destination address of cooked captures is lost.
+    pub fn normalize(&self, pkt: Packet) -> Result<Packet, io::Error> {
+        let buf = pkt.to_vec();
+        let short = || {
+            invalid(format!(
+                "{} byte packet is too short for {}",
+                buf.len(),
+                self
+            ))
+        };
+
+        let (hdr, src, proto, pkttype) = match self {
+            LinkType::Ethernet => return Ok(pkt),
+            LinkType::Raw => return Ok(pkt.with_raw(true)),
+            LinkType::Null if buf.len() >= 4 => {
+                return Ok(with_meta(&buf[4..], None, &pkt));
+            }
+            LinkType::LinuxSll if buf.len() >= 16 => (
+                16,
+                &buf[6..12],
+                u16::from_be_bytes([buf[14], buf[15]]),
+                buf[1],
+            ),
+            LinkType::LinuxSll2 if buf.len() >= 20 => (
+                20,
+                &buf[12..18],
+                u16::from_be_bytes([buf[0], buf[1]]),
+                buf[10],
+            ),
+            _ => return Err(short()),
+        };
+
+        let eth = eth_hdr::new(
+            eth_addr::new(src.try_into().unwrap()),
+            eth_addr::default(),
+            proto,
+        );
+        let mut ret = with_meta(&buf[hdr..], Some(eth), &pkt);
+
+        if ret.meta().direction.is_none() {
+            ret.meta_mut().direction = Some(match pkttype {
+                PACKET_OUTGOING => Direction::Outbound,
+                _ => Direction::Inbound,
+            });
+        }
+
+        Ok(ret)
+    }
+}
+
+fn with_meta(payload: &[u8], eth: Option<eth_hdr>, orig: &Packet) -> Packet {
+    let mut ret = Packet::with_capacity(payload.len() + 14);
+
+    if let Some(eth) = eth {
+        ret.push(eth);
+    }
+    ret.push_bytes(payload);
+    *ret.meta_mut() = orig.meta().clone();
+    ret.meta_mut().raw = eth.is_none();
+
+    ret
+}
+
+/// Linux packet types only distinguish outgoing packets from the rest
+fn pkttype(pkt: &Packet) -> u8 {
+    match pkt.meta().direction {
+        Some(Direction::Outbound) => PACKET_OUTGOING,
+        _ => PACKET_HOST,
+    }
+}
+
+impl fmt::Display for LinkType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
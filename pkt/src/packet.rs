@@ -303,10 +303,33 @@ impl PktSlice {
     }
 }
 
+/// Which way a packet was travelling, from the point of view of the host which captured it
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+/// Details of a packet which aren't part of its contents. Only some capture formats, such as
+/// pcapng, can record them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Meta {
+    /// The name of the interface which the packet was captured on, if not the default one
+    pub interface: Option<String>,
+    pub comments: Vec<String>,
+    pub direction: Option<Direction>,
+    /// When to send the packet, in nanoseconds after the start of the batch of packets it's
+    /// sent in. This is how packets replayed from a capture keep their original spacing.
+    pub offset: Option<u64>,
+    /// The packet starts with an IP header rather than an ethernet one
+    pub raw: bool,
+}
+
 #[derive(Clone, Eq, PartialEq)]
 pub struct Packet {
     buf: RefCell<Vec<u8>>,
     headroom: usize,
+    meta: Meta,
 }
 
 impl fmt::Debug for Packet {
@@ -337,6 +360,7 @@ impl Packet {
         let new: Self = Self {
             buf: RefCell::new(Vec::with_capacity(headroom + capacity)),
             headroom,
+            meta: Meta::default(),
         };
 
         new.expand(headroom);
@@ -356,6 +380,26 @@ impl Packet {
         self.headroom
     }
 
+    pub fn meta(&self) -> &Meta {
+        &self.meta
+    }
+
+    pub fn meta_mut(&mut self) -> &mut Meta {
+        &mut self.meta
+    }
+
+    #[must_use]
+    pub fn with_direction(mut self, dir: Direction) -> Self {
+        self.meta.direction = Some(dir);
+        self
+    }
+
+    #[must_use]
+    pub fn with_raw(mut self, raw: bool) -> Self {
+        self.meta.raw = raw;
+        self
+    }
+
     pub fn len(&self) -> usize {
         let buf = self.buf.borrow();
 
@@ -456,6 +500,15 @@ impl Packet {
         self.headroom += Hdr::<T>::size_of();
     }
 
+    /// Borrow the contents of the packet
+    pub fn bytes(&self) -> SliceRef<'_> {
+        SliceRef {
+            buf: self.buf.borrow(),
+            off: self.headroom,
+            len: self.len(),
+        }
+    }
+
     pub fn to_vec(&self) -> Vec<u8> {
         let buf = self.buf.borrow();
 
@@ -3,14 +3,26 @@ use std::io;
 use std::io::Write;
 use std::path::Path;
 
-use super::Packet;
-use super::Serialize;
+use super::{LinkType, Packet, Serialize};
 
 use crate::util::AsBytes;
 
-pub enum LinkType {
-    Null = 0,
-    Ethernet = 1,
+/// Something which packets can be written to, along with the time they were sent at, in
+/// nanoseconds since the epoch.
+pub trait CaptureWriter {
+    fn write_packet(&mut self, time: u64, pkt: &mut Packet) -> Result<(), io::Error>;
+
+    /// Change the link-layer type of the capture, which is only possible before any packets have
+    /// been written. Packets are [adapted](LinkType::adapt) to suit it as they're written.
+    fn set_linktype(&mut self, linktype: LinkType) -> Result<(), io::Error>;
+
+    /// Write anything which is still pending, such as the header of an empty capture, and flush
+    /// it, so that errors such as a broken pipe or a full disk aren't lost when it's dropped.
+    fn finish(&mut self) -> Result<(), io::Error>;
+}
+
+pub(crate) fn linktype_fixed() -> io::Error {
+    io::Error::other("the linktype can't be changed after packets have been written")
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -39,7 +51,7 @@ struct pcap_pkt {
 impl Serialize for pcap_pkt {}
 
 impl pcap_hdr {
-    pub fn new() -> Self {
+    pub fn new(linktype: LinkType) -> Self {
         Self {
             magic: 0xa1b23c4d, // nanosecond pcap
             ver_maj: 2,
@@ -47,29 +59,40 @@ impl pcap_hdr {
             gmt_off: 0,
             sig_fig: 0,
             mtu: 0,
-            linktype: LinkType::Ethernet as u32,
+            linktype: linktype as u32,
         }
     }
 }
 
-/// A [buffered file writer](std::io::BufWriter) for pcap files.
+/// A writer for pcap files, by default a [buffered file writer](std::io::BufWriter), but it can
+/// write to anything such as stdout or a pipe.
+///
+/// The file header isn't written until the first packet, or until the writer is finished or
+/// dropped, so that the [linktype](LinkType) can be chosen in the meantime.
 #[derive(Debug)]
-pub struct PcapWriter {
-    wr: io::BufWriter<File>,
+pub struct PcapWriter<W: Write = io::BufWriter<File>> {
+    wr: W,
     dbg: bool,
+    linktype: LinkType,
+    started: bool,
 }
 
 impl PcapWriter {
     pub fn create(p: &Path) -> Result<Self, io::Error> {
         let f = File::create(p)?;
-        let mut ret = Self {
-            wr: io::BufWriter::new(f),
-            dbg: false,
-        };
 
-        ret.write_header()?;
+        Ok(Self::new(io::BufWriter::new(f)))
+    }
+}
 
-        Ok(ret)
+impl<W: Write> PcapWriter<W> {
+    pub fn new(wr: W) -> Self {
+        Self {
+            wr,
+            dbg: false,
+            linktype: LinkType::Ethernet,
+            started: false,
+        }
     }
 
     #[inline(always)]
@@ -88,17 +111,35 @@ impl PcapWriter {
         self
     }
 
+    pub fn set_linktype(&mut self, linktype: LinkType) -> Result<(), io::Error> {
+        if self.started {
+            return Err(linktype_fixed());
+        }
+
+        self.linktype = linktype;
+
+        Ok(())
+    }
+
     fn write_header(&mut self) -> Result<(), io::Error> {
-        let hdr = pcap_hdr::new();
+        let hdr = pcap_hdr::new(self.linktype);
+
+        self.started = true;
         self.wr.write_all(hdr.as_bytes())
     }
 
     #[inline(always)]
     pub fn write_packet(&mut self, time: u64, pkt: &mut Packet) -> Result<(), io::Error> {
+        if !self.started {
+            self.write_header()?;
+        }
+
+        let mut adapted = self.linktype.adapt(pkt)?;
+        let pkt = adapted.as_mut().unwrap_or(pkt);
         let len = pkt.len() as u32;
 
         if self.dbg {
-            println!("pcap: writing {:#?}", pkt);
+            eprintln!("pcap: writing {:#?}", pkt);
         }
 
         let pkt_hdr = pcap_pkt {
@@ -119,4 +160,35 @@ impl PcapWriter {
 
         Ok(())
     }
+
+    pub fn finish(&mut self) -> Result<(), io::Error> {
+        if !self.started {
+            self.write_header()?;
+        }
+
+        self.wr.flush()
+    }
+}
+
+impl<W: Write> Drop for PcapWriter<W> {
+    fn drop(&mut self) {
+        /* An empty capture still needs a header, errors are ignored as in BufWriter */
+        if !self.started {
+            self.write_header().ok();
+        }
+    }
+}
+
+impl<W: Write> CaptureWriter for PcapWriter<W> {
+    fn write_packet(&mut self, time: u64, pkt: &mut Packet) -> Result<(), io::Error> {
+        PcapWriter::write_packet(self, time, pkt)
+    }
+
+    fn set_linktype(&mut self, linktype: LinkType) -> Result<(), io::Error> {
+        PcapWriter::set_linktype(self, linktype)
+    }
+
+    fn finish(&mut self) -> Result<(), io::Error> {
+        PcapWriter::finish(self)
+    }
 }
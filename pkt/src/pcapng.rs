@@ -0,0 +1,227 @@
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+
+use super::pcap::linktype_fixed;
+use super::{CaptureWriter, Direction, LinkType, Packet};
+
+const BLOCK_SHB: u32 = 0x0a0d_0d0a;
+const BLOCK_IDB: u32 = 0x0000_0001;
+const BLOCK_EPB: u32 = 0x0000_0006;
+
+const BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+
+const OPT_ENDOFOPT: u16 = 0;
+const OPT_COMMENT: u16 = 1;
+const IF_NAME: u16 = 2;
+const IF_TSRESOL: u16 = 9;
+const EPB_FLAGS: u16 = 2;
+
+/// if_tsresol: timestamps are in units of 10^-9 seconds
+const TSRESOL_NSEC: u8 = 9;
+
+const EPB_FLAG_INBOUND: u32 = 1;
+const EPB_FLAG_OUTBOUND: u32 = 2;
+
+/// The body of a pcapng block, which knows how to frame itself with the block type and lengths.
+/// Everything is written little-endian, as announced by the byte-order magic in the section
+/// header.
+#[derive(Default)]
+struct Block {
+    body: Vec<u8>,
+}
+
+impl Block {
+    fn u16(&mut self, val: u16) {
+        self.body.extend(val.to_le_bytes());
+    }
+
+    fn u32(&mut self, val: u32) {
+        self.body.extend(val.to_le_bytes());
+    }
+
+    fn padded(&mut self, bytes: &[u8]) {
+        self.body.extend(bytes);
+        self.body.resize(self.body.len().next_multiple_of(4), 0);
+    }
+
+    fn opt(&mut self, code: u16, val: &[u8]) -> Result<(), io::Error> {
+        let len = u16::try_from(val.len()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("pcapng: option of {} bytes is too long", val.len()),
+            )
+        })?;
+
+        self.u16(code);
+        self.u16(len);
+        self.padded(val);
+
+        Ok(())
+    }
+
+    fn end_opts(&mut self) {
+        self.u16(OPT_ENDOFOPT);
+        self.u16(0);
+    }
+
+    fn write<W: Write>(&self, wr: &mut W, block_type: u32) -> Result<(), io::Error> {
+        let len = (self.body.len() + 12) as u32;
+
+        wr.write_all(&block_type.to_le_bytes())?;
+        wr.write_all(&len.to_le_bytes())?;
+        wr.write_all(&self.body)?;
+        wr.write_all(&len.to_le_bytes())
+    }
+}
+
+/// A writer for pcapng files, by default a [buffered file writer](std::io::BufWriter), but it can
+/// write to anything such as stdout or a pipe.
+///
+/// Unlike a [PcapWriter](crate::PcapWriter) this records the [metadata](crate::Meta) of each
+/// packet: the interface it was sent on, comments and its direction. Interfaces are declared as
+/// packets which use them are written, the default one is unnamed.
+#[derive(Debug)]
+pub struct PcapNgWriter<W: Write = io::BufWriter<File>> {
+    wr: W,
+    dbg: bool,
+    linktype: LinkType,
+    interfaces: Vec<Option<String>>,
+}
+
+impl PcapNgWriter {
+    pub fn create(p: &Path) -> Result<Self, io::Error> {
+        let f = File::create(p)?;
+
+        Self::new(io::BufWriter::new(f))
+    }
+}
+
+impl<W: Write> PcapNgWriter<W> {
+    /// The section header is written straight away
+    pub fn new(wr: W) -> Result<Self, io::Error> {
+        let mut ret = Self {
+            wr,
+            dbg: false,
+            linktype: LinkType::Ethernet,
+            interfaces: Vec::new(),
+        };
+
+        ret.write_header()?;
+
+        Ok(ret)
+    }
+
+    #[must_use]
+    pub fn debug(mut self) -> Self {
+        self.dbg = true;
+        self
+    }
+
+    /// All interfaces have the same linktype, so it can't be changed once any are declared
+    pub fn set_linktype(&mut self, linktype: LinkType) -> Result<(), io::Error> {
+        if !self.interfaces.is_empty() {
+            return Err(linktype_fixed());
+        }
+
+        self.linktype = linktype;
+
+        Ok(())
+    }
+
+    fn write_header(&mut self) -> Result<(), io::Error> {
+        let mut shb = Block::default();
+
+        shb.u32(BYTE_ORDER_MAGIC);
+        shb.u16(1); // major version
+        shb.u16(0); // minor version
+        shb.body.extend((-1i64).to_le_bytes()); // section length is not known
+
+        shb.write(&mut self.wr, BLOCK_SHB)
+    }
+
+    fn add_interface(&mut self, name: Option<&str>) -> Result<u32, io::Error> {
+        let mut idb = Block::default();
+
+        idb.u16(self.linktype as u16);
+        idb.u16(0); // reserved
+        idb.u32(0); // snaplen: unlimited
+
+        if let Some(name) = name {
+            idb.opt(IF_NAME, name.as_bytes())?;
+        }
+        idb.opt(IF_TSRESOL, &[TSRESOL_NSEC])?;
+        idb.end_opts();
+
+        idb.write(&mut self.wr, BLOCK_IDB)?;
+
+        self.interfaces.push(name.map(str::to_owned));
+
+        Ok((self.interfaces.len() - 1) as u32)
+    }
+
+    fn interface_id(&mut self, name: Option<&str>) -> Result<u32, io::Error> {
+        match self.interfaces.iter().position(|i| i.as_deref() == name) {
+            Some(id) => Ok(id as u32),
+            None => self.add_interface(name),
+        }
+    }
+
+    pub fn write_packet(&mut self, time: u64, pkt: &mut Packet) -> Result<(), io::Error> {
+        let adapted = self.linktype.adapt(pkt)?;
+        let pkt = adapted.as_ref().unwrap_or(pkt);
+
+        if self.dbg {
+            eprintln!("pcapng: writing {:#?}", pkt);
+        }
+
+        let meta = pkt.meta();
+        let id = self.interface_id(meta.interface.as_deref())?;
+        let mut epb = Block::default();
+        let len = pkt.len() as u32;
+
+        epb.u32(id);
+        epb.u32((time >> 32) as u32);
+        epb.u32(time as u32);
+        epb.u32(len);
+        epb.u32(len);
+        epb.padded(&pkt.bytes());
+
+        for comment in meta.comments.iter() {
+            epb.opt(OPT_COMMENT, comment.as_bytes())?;
+        }
+
+        if let Some(dir) = meta.direction {
+            let flags = match dir {
+                Direction::Inbound => EPB_FLAG_INBOUND,
+                Direction::Outbound => EPB_FLAG_OUTBOUND,
+            };
+            epb.opt(EPB_FLAGS, &flags.to_le_bytes())?;
+        }
+
+        epb.end_opts();
+
+        epb.write(&mut self.wr, BLOCK_EPB)
+    }
+
+    /// The section header was written up front, so there's nothing left but to flush
+    pub fn finish(&mut self) -> Result<(), io::Error> {
+        self.wr.flush()
+    }
+}
+
+impl<W: Write> CaptureWriter for PcapNgWriter<W> {
+    fn write_packet(&mut self, time: u64, pkt: &mut Packet) -> Result<(), io::Error> {
+        PcapNgWriter::write_packet(self, time, pkt)
+    }
+
+    fn set_linktype(&mut self, linktype: LinkType) -> Result<(), io::Error> {
+        PcapNgWriter::set_linktype(self, linktype)
+    }
+
+    fn finish(&mut self) -> Result<(), io::Error> {
+        PcapNgWriter::finish(self)
+    }
+}
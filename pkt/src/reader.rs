@@ -0,0 +1,422 @@
+use std::convert::TryInto;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+
+use super::{Direction, Packet};
+
+const PCAP_USEC: u32 = 0xa1b2_c3d4;
+const PCAP_NSEC: u32 = 0xa1b2_3c4d;
+
+const BLOCK_SHB: u32 = 0x0a0d_0d0a;
+const BLOCK_IDB: u32 = 0x0000_0001;
+const BLOCK_SPB: u32 = 0x0000_0003;
+const BLOCK_EPB: u32 = 0x0000_0006;
+
+const BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+
+const OPT_ENDOFOPT: u16 = 0;
+const OPT_COMMENT: u16 = 1;
+const IF_NAME: u16 = 2;
+const IF_TSRESOL: u16 = 9;
+const EPB_FLAGS: u16 = 2;
+
+/// Don't trust lengths in the file any further than this
+const MAX_BLOCK: usize = 1 << 24;
+
+/// A packet read from a capture file
+#[derive(Debug)]
+pub struct Record {
+    /// Nanoseconds since the epoch
+    pub time: u64,
+    /// The [linktype](crate::LinkType) of the interface it was captured on
+    pub linktype: u32,
+    pub pkt: Packet,
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_owned())
+}
+
+/// Integers in the file are in the byte-order of the machine which wrote it
+#[derive(Debug, Copy, Clone)]
+struct Endian {
+    swap: bool,
+}
+
+impl Endian {
+    fn u16(&self, b: &[u8]) -> u16 {
+        let val = u16::from_ne_bytes(b[..2].try_into().unwrap());
+        if self.swap {
+            val.swap_bytes()
+        } else {
+            val
+        }
+    }
+
+    fn u32(&self, b: &[u8]) -> u32 {
+        let val = u32::from_ne_bytes(b[..4].try_into().unwrap());
+        if self.swap {
+            val.swap_bytes()
+        } else {
+            val
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Interface {
+    linktype: u32,
+    name: Option<String>,
+    /// if_tsresol, microseconds unless specified
+    tsresol: u8,
+}
+
+impl Interface {
+    fn to_nsecs(&self, ts: u64) -> u64 {
+        let exp = (self.tsresol & 0x7f) as u32;
+
+        if self.tsresol & 0x80 != 0 {
+            ((ts as u128 * 1_000_000_000) >> exp) as u64
+        } else if exp <= 9 {
+            ts.saturating_mul(10u64.pow(9 - exp))
+        } else {
+            ts / 10u64.saturating_pow(exp - 9)
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Format {
+    Pcap {
+        endian: Endian,
+        nsec: bool,
+        linktype: u32,
+    },
+    PcapNg {
+        endian: Endian,
+        interfaces: Vec<Interface>,
+    },
+}
+
+/// Reads packets from pcap or pcapng files, the format is detected from the file header.
+///
+/// For pcapng, the interface name, comments and direction of each packet are kept in its
+/// [metadata](crate::Meta). Blocks other than packets and interface descriptions are skipped.
+#[derive(Debug)]
+pub struct PcapReader<R> {
+    rd: R,
+    fmt: Format,
+}
+
+impl PcapReader<io::BufReader<File>> {
+    pub fn open(p: &Path) -> Result<Self, io::Error> {
+        Self::new(io::BufReader::new(File::open(p)?))
+    }
+}
+
+impl<R: Read> PcapReader<R> {
+    pub fn new(mut rd: R) -> Result<Self, io::Error> {
+        let mut magic = [0u8; 4];
+        rd.read_exact(&mut magic)?;
+
+        let fmt = match u32::from_le_bytes(magic) {
+            BLOCK_SHB => Format::PcapNg {
+                endian: Endian { swap: false },
+                interfaces: Vec::new(),
+            },
+            _ => Self::pcap_header(&mut rd, magic)?,
+        };
+
+        let mut ret = Self { rd, fmt };
+
+        if let Format::PcapNg { .. } = ret.fmt {
+            let body = ret.shb_body()?;
+            ret.block(BLOCK_SHB, &body)?;
+        }
+
+        Ok(ret)
+    }
+
+    fn pcap_header(rd: &mut R, magic: [u8; 4]) -> Result<Format, io::Error> {
+        let (swap, nsec) = match u32::from_ne_bytes(magic) {
+            PCAP_USEC => (false, false),
+            PCAP_NSEC => (false, true),
+            m if m.swap_bytes() == PCAP_USEC => (true, false),
+            m if m.swap_bytes() == PCAP_NSEC => (true, true),
+            _ => return Err(invalid("not a pcap or pcapng file")),
+        };
+
+        let mut hdr = [0u8; 20];
+        rd.read_exact(&mut hdr)?;
+
+        let endian = Endian { swap };
+
+        Ok(Format::Pcap {
+            endian,
+            nsec,
+            linktype: endian.u32(&hdr[16..]),
+        })
+    }
+
+    /// Read exactly `buf.len()` bytes, returning false at a clean end of file
+    fn fill(&mut self, buf: &mut [u8]) -> Result<bool, io::Error> {
+        let mut off = 0;
+
+        while off < buf.len() {
+            match self.rd.read(&mut buf[off..])? {
+                0 if off == 0 => return Ok(false),
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                n => off += n,
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn read_vec(&mut self, len: usize) -> Result<Vec<u8>, io::Error> {
+        if len > MAX_BLOCK {
+            return Err(invalid("record is too large"));
+        }
+
+        let mut buf = vec![0u8; len];
+        self.rd.read_exact(&mut buf)?;
+
+        Ok(buf)
+    }
+
+    fn next_pcap(&mut self) -> Result<Option<Record>, io::Error> {
+        let (endian, nsec, linktype) = match self.fmt {
+            Format::Pcap {
+                endian,
+                nsec,
+                linktype,
+            } => (endian, nsec, linktype),
+            _ => unreachable!(),
+        };
+
+        let mut hdr = [0u8; 16];
+        if !self.fill(&mut hdr)? {
+            return Ok(None);
+        }
+
+        let secs = endian.u32(&hdr[0..]) as u64;
+        let frac = endian.u32(&hdr[4..]) as u64;
+        let caplen = endian.u32(&hdr[8..]) as usize;
+
+        let data = self.read_vec(caplen)?;
+
+        Ok(Some(Record {
+            time: secs * 1_000_000_000 + if nsec { frac } else { frac * 1000 },
+            linktype,
+            pkt: packet(&data),
+        }))
+    }
+
+    /// The section header is where the byte-order is found, so it needs reading specially
+    fn shb_body(&mut self) -> Result<Vec<u8>, io::Error> {
+        let mut hdr = [0u8; 8];
+        self.rd.read_exact(&mut hdr)?;
+
+        let swap = match u32::from_ne_bytes(hdr[4..].try_into().unwrap()) {
+            BYTE_ORDER_MAGIC => false,
+            m if m.swap_bytes() == BYTE_ORDER_MAGIC => true,
+            _ => return Err(invalid("bad pcapng byte-order magic")),
+        };
+        let endian = Endian { swap };
+        let len = endian.u32(&hdr[..]) as usize;
+
+        if len < 28 || !len.is_multiple_of(4) {
+            return Err(invalid("bad pcapng section header length"));
+        }
+
+        if let Format::PcapNg {
+            endian: ref mut e, ..
+        } = self.fmt
+        {
+            *e = endian;
+        }
+
+        let mut body = hdr[4..].to_vec();
+        body.extend(self.read_vec(len - 12)?);
+        body.truncate(len - 12);
+
+        Ok(body)
+    }
+
+    fn next_pcapng(&mut self) -> Result<Option<Record>, io::Error> {
+        loop {
+            let mut hdr = [0u8; 4];
+            if !self.fill(&mut hdr)? {
+                return Ok(None);
+            }
+
+            let endian = match self.fmt {
+                Format::PcapNg { endian, .. } => endian,
+                _ => unreachable!(),
+            };
+
+            let block_type = endian.u32(&hdr);
+            let body = if block_type == BLOCK_SHB {
+                self.shb_body()?
+            } else {
+                let mut len = [0u8; 4];
+                self.rd.read_exact(&mut len)?;
+
+                let len = endian.u32(&len) as usize;
+                if len < 12 || !len.is_multiple_of(4) {
+                    return Err(invalid("bad pcapng block length"));
+                }
+
+                let mut body = self.read_vec(len - 8)?;
+                body.truncate(len - 12);
+                body
+            };
+
+            if let Some(rec) = self.block(block_type, &body)? {
+                return Ok(Some(rec));
+            }
+        }
+    }
+
+    fn block(&mut self, block_type: u32, body: &[u8]) -> Result<Option<Record>, io::Error> {
+        let (endian, interfaces) = match self.fmt {
+            Format::PcapNg {
+                endian,
+                ref mut interfaces,
+            } => (endian, interfaces),
+            _ => unreachable!(),
+        };
+        let short = || invalid("truncated pcapng block");
+
+        match block_type {
+            BLOCK_SHB => interfaces.clear(),
+            BLOCK_IDB => {
+                if body.len() < 8 {
+                    return Err(short());
+                }
+
+                let mut iface = Interface {
+                    linktype: endian.u16(body) as u32,
+                    name: None,
+                    tsresol: 6,
+                };
+
+                for (code, val) in options(endian, &body[8..]) {
+                    match code {
+                        IF_NAME => iface.name = Some(String::from_utf8_lossy(val).into_owned()),
+                        IF_TSRESOL if !val.is_empty() => iface.tsresol = val[0],
+                        _ => {}
+                    }
+                }
+
+                interfaces.push(iface);
+            }
+            BLOCK_EPB => {
+                if body.len() < 20 {
+                    return Err(short());
+                }
+
+                let iface = interfaces
+                    .get(endian.u32(body) as usize)
+                    .ok_or_else(|| invalid("packet on an undeclared pcapng interface"))?;
+                let ts = ((endian.u32(&body[4..]) as u64) << 32) | endian.u32(&body[8..]) as u64;
+                let caplen = endian.u32(&body[12..]) as usize;
+                let padded = (caplen + 3) & !3;
+
+                if body.len() < 20 + padded {
+                    return Err(short());
+                }
+
+                let mut pkt = packet(&body[20..20 + caplen]);
+                let meta = pkt.meta_mut();
+
+                meta.interface = iface.name.clone();
+
+                for (code, val) in options(endian, &body[20 + padded..]) {
+                    match code {
+                        OPT_COMMENT => meta
+                            .comments
+                            .push(String::from_utf8_lossy(val).into_owned()),
+                        EPB_FLAGS if val.len() >= 4 => {
+                            meta.direction = match endian.u32(val) & 3 {
+                                1 => Some(Direction::Inbound),
+                                2 => Some(Direction::Outbound),
+                                _ => None,
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+
+                return Ok(Some(Record {
+                    time: iface.to_nsecs(ts),
+                    linktype: iface.linktype,
+                    pkt,
+                }));
+            }
+            BLOCK_SPB => {
+                if body.len() < 4 {
+                    return Err(short());
+                }
+
+                let iface = interfaces
+                    .first()
+                    .ok_or_else(|| invalid("packet on an undeclared pcapng interface"))?;
+                let len = std::cmp::min(endian.u32(body) as usize, body.len() - 4);
+
+                let mut pkt = packet(&body[4..4 + len]);
+                pkt.meta_mut().interface = iface.name.clone();
+
+                /* Simple packet blocks have no timestamp */
+                return Ok(Some(Record {
+                    time: 0,
+                    linktype: iface.linktype,
+                    pkt,
+                }));
+            }
+            _ => {}
+        }
+
+        Ok(None)
+    }
+}
+
+/// The options at the end of a pcapng block, as (code, value) pairs
+fn options(endian: Endian, mut buf: &[u8]) -> Vec<(u16, &[u8])> {
+    let mut ret = Vec::new();
+
+    while buf.len() >= 4 {
+        let code = endian.u16(buf);
+        let len = endian.u16(&buf[2..]) as usize;
+
+        if code == OPT_ENDOFOPT || buf.len() < 4 + len {
+            break;
+        }
+
+        ret.push((code, &buf[4..4 + len]));
+        buf = &buf[std::cmp::min(buf.len(), 4 + ((len + 3) & !3))..];
+    }
+
+    ret
+}
+
+fn packet(data: &[u8]) -> Packet {
+    let pkt = Packet::with_capacity(data.len());
+    pkt.push_bytes(data);
+    pkt
+}
+
+impl<R: Read> Iterator for PcapReader<R> {
+    type Item = Result<Record, io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let ret = match self.fmt {
+            Format::Pcap { .. } => self.next_pcap(),
+            Format::PcapNg { .. } => self.next_pcapng(),
+        };
+
+        ret.transpose()
+    }
+}
@@ -0,0 +1,101 @@
+use std::io;
+
+use crate::eth::{eth_hdr, ethertype};
+use crate::{Direction, LinkType, Packet};
+
+const IP: &[u8] =
+    b"\x45\x00\x00\x14\x00\x00\x00\x00\x40\x11\x00\x00\x01\x02\x03\x04\x05\x06\x07\x08";
+
+fn frame(proto: u16) -> Packet {
+    let pkt = Packet::default();
+    pkt.push(eth_hdr::new(
+        "1.2.3.4".parse::<std::net::Ipv4Addr>().unwrap().into(),
+        "5.6.7.8".parse::<std::net::Ipv4Addr>().unwrap().into(),
+        proto,
+    ));
+    pkt.push_bytes(IP);
+    pkt
+}
+
+fn raw() -> Packet {
+    let pkt = Packet::default().with_raw(true);
+    pkt.push_bytes(IP);
+    pkt
+}
+
+fn adapt(linktype: LinkType, pkt: &Packet) -> Result<Option<Vec<u8>>, io::Error> {
+    Ok(linktype.adapt(pkt)?.map(|pkt| pkt.to_vec()))
+}
+
+/// Ethernet headers are stripped, replaced or synthesized to suit the linktype
+#[test]
+fn test_link_adapt() -> Result<(), io::Error> {
+    let eth = frame(ethertype::IPV4).with_direction(Direction::Outbound);
+
+    assert_eq!(adapt(LinkType::Ethernet, &eth)?, None);
+    assert_eq!(adapt(LinkType::Raw, &raw())?, None);
+
+    assert_eq!(adapt(LinkType::Raw, &eth)?, Some(IP.to_vec()));
+    assert_eq!(
+        adapt(LinkType::Ethernet, &raw())?,
+        Some(frame(ethertype::IPV4).to_vec())
+    );
+
+    let null = adapt(LinkType::Null, &eth)?.unwrap();
+    assert_eq!(&null[..4], 2u32.to_ne_bytes());
+    assert_eq!(&null[4..], IP);
+
+    let sll = adapt(LinkType::LinuxSll, &eth)?.unwrap();
+    assert_eq!(
+        &sll[..16],
+        b"\x00\x04\x00\x01\x00\x06\x00\x02\x01\x02\x03\x04\x00\x00\x08\x00"
+    );
+    assert_eq!(&sll[16..], IP);
+
+    let sll2 = adapt(LinkType::LinuxSll2, &raw())?.unwrap();
+    assert_eq!(
+        &sll2[..20],
+        b"\x08\x00\x00\x00\x00\x00\x00\x00\x00\x01\x00\x06\x00\x02\x01\x02\x03\x04\x00\x00"
+    );
+    assert_eq!(&sll2[20..], IP);
+
+    Ok(())
+}
+
+/// Only IP can go in captures without a link-layer protocol field
+#[test]
+fn test_link_invalid() {
+    let arp = frame(0x0806);
+
+    for linktype in [LinkType::Raw, LinkType::Null] {
+        let err = linktype.adapt(&arp).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+    assert!(LinkType::LinuxSll.adapt(&arp).is_ok());
+
+    let runt = Packet::default();
+    runt.push_bytes(b"\x00\x01");
+    assert!(LinkType::Ethernet.adapt(&runt).unwrap().is_none());
+    assert!(LinkType::LinuxSll.adapt(&runt).is_err());
+
+    let runt = Packet::default().with_raw(true);
+    runt.push_bytes(&IP[..10]);
+    assert!(LinkType::Ethernet.adapt(&runt).is_err());
+}
+
+/// Whether a packet has an ethernet header is down to how it was built, not what it looks like
+#[test]
+fn test_link_ip_like_mac() -> Result<(), io::Error> {
+    let eth = Packet::default();
+    eth.push_bytes(b"\x45\x00\x00\x00\x00\x01\x02\x00\x00\x00\x00\x02\x08\x00");
+    eth.push_bytes(IP);
+
+    assert_eq!(adapt(LinkType::Ethernet, &eth)?, None);
+    assert_eq!(adapt(LinkType::Raw, &eth)?, Some(IP.to_vec()));
+
+    let raw = LinkType::Raw.normalize(eth)?;
+    assert!(raw.meta().raw);
+    assert_eq!(adapt(LinkType::Raw, &raw)?, None);
+
+    Ok(())
+}
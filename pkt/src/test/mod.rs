@@ -1,2 +1,5 @@
 mod dns;
+mod link;
 mod netbios;
+mod pcapng;
+mod reader;
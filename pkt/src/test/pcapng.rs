@@ -0,0 +1,97 @@
+use std::convert::TryInto;
+use std::env;
+use std::fs;
+use std::process;
+
+use crate::{Direction, Packet, PcapNgWriter};
+
+fn u32_at(buf: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes(buf[off..off + 4].try_into().unwrap())
+}
+
+/// Walk the blocks in a pcapng file, checking the framing, returning the block types and bodies
+fn blocks(buf: &[u8]) -> Vec<(u32, &[u8])> {
+    let mut ret = Vec::new();
+    let mut off = 0;
+
+    while off < buf.len() {
+        let len = u32_at(buf, off + 4) as usize;
+
+        assert_eq!(len % 4, 0);
+        assert_eq!(u32_at(buf, off + len - 4) as usize, len);
+
+        ret.push((u32_at(buf, off), &buf[off + 8..off + len - 4]));
+        off += len;
+    }
+
+    ret
+}
+
+/// Interfaces are declared lazily, and comments and direction flags go in EPB options
+#[test]
+fn test_pcapng_write() {
+    let path = env::temp_dir().join(format!("resynth-pcapng-{}.pcapng", process::id()));
+
+    {
+        let mut wr = PcapNgWriter::create(&path).unwrap();
+
+        let mut pkt = Packet::default();
+        pkt.push_bytes(b"hello");
+        wr.write_packet(0x1_0000_0002, &mut pkt).unwrap();
+
+        let mut pkt = Packet::default().with_direction(Direction::Inbound);
+        pkt.push_bytes(b"world!!!");
+        pkt.meta_mut().interface = Some("eth1".to_owned());
+        pkt.meta_mut().comments.push("a comment".to_owned());
+        wr.write_packet(3, &mut pkt).unwrap();
+    }
+
+    let buf = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let blocks = blocks(&buf);
+    let types: Vec<u32> = blocks.iter().map(|(t, _)| *t).collect();
+    assert_eq!(types, vec![0x0a0d0d0a, 1, 6, 1, 6]);
+
+    let (_, shb) = blocks[0];
+    assert_eq!(u32_at(shb, 0), 0x1a2b3c4d);
+
+    let (_, eth1) = blocks[3];
+    assert_eq!(&eth1[8..16], b"\x02\x00\x04\x00eth1");
+
+    let (_, first) = blocks[2];
+    assert_eq!(u32_at(first, 0), 0);
+    assert_eq!(u32_at(first, 4), 1);
+    assert_eq!(u32_at(first, 8), 2);
+    assert_eq!(u32_at(first, 12), 5);
+    assert_eq!(&first[20..28], b"hello\0\0\0");
+    assert_eq!(&first[28..], b"\0\0\0\0");
+
+    let (_, second) = blocks[4];
+    assert_eq!(u32_at(second, 0), 1);
+    assert_eq!(&second[20..28], b"world!!!");
+    assert_eq!(&second[28..44], b"\x01\x00\x09\x00a comment\0\0\0");
+    assert_eq!(&second[44..52], b"\x02\x00\x04\x00\x01\x00\x00\x00");
+    assert_eq!(&second[52..], b"\0\0\0\0");
+}
+
+/// Options have a 16-bit length, so longer ones are refused rather than truncated
+#[test]
+fn test_pcapng_long_option() {
+    let mut wr = PcapNgWriter::new(Vec::new()).unwrap();
+
+    let mut pkt = Packet::default();
+    pkt.push_bytes(b"hello");
+    pkt.meta_mut().comments.push("x".repeat(65536));
+    assert!(wr.write_packet(0, &mut pkt).is_err());
+
+    let mut pkt = Packet::default();
+    pkt.push_bytes(b"hello");
+    pkt.meta_mut().interface = Some("x".repeat(65536));
+    assert!(wr.write_packet(0, &mut pkt).is_err());
+
+    let mut pkt = Packet::default();
+    pkt.push_bytes(b"hello");
+    pkt.meta_mut().comments.push("x".repeat(65535));
+    wr.write_packet(0, &mut pkt).unwrap();
+}
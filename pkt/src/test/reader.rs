@@ -0,0 +1,146 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::process;
+
+use crate::{Direction, LinkType, Packet, PcapNgWriter, PcapReader, PcapWriter, Record};
+
+fn packet(bytes: &[u8]) -> Packet {
+    let pkt = Packet::default();
+    pkt.push_bytes(bytes);
+    pkt
+}
+
+fn read_all(rd: impl io::Read) -> Vec<Record> {
+    PcapReader::new(rd)
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap()
+}
+
+/// What the writers write, the reader reads back, including pcapng metadata
+#[test]
+fn test_read_roundtrip() {
+    let dir = env::temp_dir();
+    let pcap = dir.join(format!("resynth-rd-{}.pcap", process::id()));
+    let pcapng = dir.join(format!("resynth-rd-{}.pcapng", process::id()));
+
+    {
+        let mut wr = PcapWriter::create(&pcap).unwrap();
+        wr.write_packet(1_000_000_007, &mut packet(&[0xaa; 20]))
+            .unwrap();
+        wr.write_packet(2_000_000_000, &mut packet(b"\x00\x01\x02"))
+            .unwrap();
+
+        let mut wr = PcapNgWriter::create(&pcapng).unwrap();
+        wr.set_linktype(LinkType::LinuxSll).unwrap();
+        let mut pkt = packet(&[0xbb; 20]).with_direction(Direction::Outbound);
+        pkt.meta_mut().interface = Some("tap0".to_owned());
+        pkt.meta_mut().comments.push("hi".to_owned());
+        wr.write_packet(5_000_000_123, &mut pkt).unwrap();
+    }
+
+    let recs = read_all(fs::File::open(&pcap).unwrap());
+    let recs_ng = read_all(fs::File::open(&pcapng).unwrap());
+    fs::remove_file(&pcap).unwrap();
+    fs::remove_file(&pcapng).unwrap();
+
+    let times: Vec<u64> = recs.iter().map(|r| r.time).collect();
+    assert_eq!(times, vec![1_000_000_007, 2_000_000_000]);
+    assert_eq!(recs[0].linktype, LinkType::Ethernet as u32);
+    assert_eq!(recs[1].pkt.to_vec(), b"\x00\x01\x02");
+
+    assert_eq!(recs_ng.len(), 1);
+    let rec = &recs_ng[0];
+    assert_eq!(rec.time, 5_000_000_123);
+    assert_eq!(rec.linktype, LinkType::LinuxSll as u32);
+    assert_eq!(rec.pkt.meta().interface.as_deref(), Some("tap0"));
+    assert_eq!(rec.pkt.meta().comments, vec!["hi".to_owned()]);
+    assert_eq!(rec.pkt.meta().direction, Some(Direction::Outbound));
+
+    /* Converting back from linux cooked gets the ethernet source and payload back */
+    let eth = LinkType::LinuxSll.normalize(rec.pkt.clone()).unwrap();
+    assert_eq!(&eth.to_vec()[6..12], &[0xbb; 6]);
+    assert_eq!(&eth.to_vec()[14..], &[0xbb; 6]);
+}
+
+/// The writers can write to anything, not just files
+#[test]
+fn test_write_to_memory() {
+    let mut empty = Vec::new();
+    PcapWriter::new(&mut empty);
+    assert!(read_all(&empty[..]).is_empty());
+
+    let mut pcap = Vec::new();
+    let mut pcapng = Vec::new();
+    {
+        let mut wr = PcapWriter::new(&mut pcap);
+        wr.write_packet(7, &mut packet(b"abc")).unwrap();
+
+        let mut wr = PcapNgWriter::new(&mut pcapng).unwrap();
+        wr.write_packet(9, &mut packet(b"defg")).unwrap();
+    }
+
+    let recs = read_all(&pcap[..]);
+    assert_eq!((recs[0].time, recs[0].pkt.to_vec()), (7, b"abc".to_vec()));
+
+    let recs = read_all(&pcapng[..]);
+    assert_eq!((recs[0].time, recs[0].pkt.to_vec()), (9, b"defg".to_vec()));
+}
+
+/// Somewhere to write to which is always full, like /dev/full
+struct Full;
+
+impl io::Write for Full {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::Error::other("no space left"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Buffered write errors are reported by finish() rather than being lost when it's dropped
+#[test]
+fn test_finish_errors() {
+    let mut empty = Vec::new();
+    PcapWriter::new(&mut empty).finish().unwrap();
+    assert_eq!(empty.len(), 24);
+
+    let mut wr = PcapWriter::new(io::BufWriter::new(Full));
+    assert!(wr.finish().is_err());
+
+    let mut wr = PcapWriter::new(io::BufWriter::new(Full));
+    wr.write_packet(7, &mut packet(b"abc")).unwrap();
+    assert!(wr.finish().is_err());
+
+    let mut wr = PcapNgWriter::new(io::BufWriter::new(Full)).unwrap();
+    wr.write_packet(9, &mut packet(b"defg")).unwrap();
+    assert!(wr.finish().is_err());
+}
+
+/// Big-endian microsecond pcaps, as written on other machines
+#[test]
+fn test_read_big_endian() {
+    let mut buf = Vec::new();
+    buf.extend(0xa1b2c3d4u32.to_be_bytes());
+    buf.extend(b"\x00\x02\x00\x04\0\0\0\0\0\0\0\0\0\0\xff\xff\0\0\0\x65");
+    buf.extend(3u32.to_be_bytes());
+    buf.extend(500u32.to_be_bytes());
+    buf.extend(2u32.to_be_bytes());
+    buf.extend(2u32.to_be_bytes());
+    buf.extend(b"hi");
+
+    let recs = read_all(&buf[..]);
+    assert_eq!(recs.len(), 1);
+    assert_eq!(recs[0].time, 3_000_500_000);
+    assert_eq!(recs[0].linktype, LinkType::Raw as u32);
+    assert_eq!(recs[0].pkt.to_vec(), b"hi");
+
+    /* Truncated records are an error, rather than being silently dropped */
+    let mut rd = PcapReader::new(&buf[..buf.len() - 1]).unwrap();
+    assert!(rd.next().unwrap().is_err());
+
+    assert!(PcapReader::new(&b"not a pcap"[..]).is_err());
+}
@@ -0,0 +1,444 @@
+//! Queries about the source code of a program for editor integration, such as a language server.
+//! The source is usually in the middle of being edited and so may well be broken, for that reason
+//! everything apart from the diagnostics works on the tokens rather than needing it to parse.
+
+use crate::check::Checker;
+use crate::err::Error;
+use crate::lex::{Lexer, TokType};
+use crate::libapi::FuncDef;
+use crate::loc::{Loc, Span};
+use crate::stdlib::{classes, complete, lookup, write_symbol_docs};
+use crate::sym::Symbol;
+use crate::val::Typed;
+
+use std::path::Path;
+
+/// An owned copy of a [token](crate::Token)
+#[derive(Debug)]
+struct Tok {
+    typ: TokType,
+    span: Span,
+    val: Option<String>,
+}
+
+impl Tok {
+    fn is(&self, typ: TokType) -> bool {
+        self.typ == typ
+    }
+
+    fn name(&self) -> &str {
+        self.val.as_deref().unwrap_or_default()
+    }
+}
+
+fn pos(loc: Loc) -> (usize, usize) {
+    (loc.line(), loc.col())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+    Module,
+    Class,
+    Function,
+    Method,
+    Constant,
+    Variable,
+}
+
+/// A suggestion for what could be typed at the cursor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    pub label: String,
+    pub kind: CompletionKind,
+    /// The signature of a function, or the value of a constant
+    pub detail: Option<String>,
+}
+
+/// The signature of the function whose arguments are being typed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureHelp {
+    pub label: String,
+    /// The declaration of each argument, each of which appears in the label
+    pub params: Vec<String>,
+    /// Index of the argument which the cursor is in
+    pub active: usize,
+    pub doc: String,
+}
+
+/// A one-line signature of a stdlib function, ie. `flow(cl: Sock4, sv: Sock4) -> Obj`
+fn func_label(func: &FuncDef) -> (String, Vec<String>) {
+    let mut params: Vec<String> = func.args.iter().map(|arg| arg.to_string()).collect();
+
+    if !func.collect_type.is_nil() {
+        params.push(format!("*collect_args: {}", func.collect_type));
+    }
+
+    let label = format!(
+        "{}({}) -> {}",
+        func.name,
+        params.join(", "),
+        func.return_type
+    );
+
+    (label, params)
+}
+
+fn sym_completion(label: &str, sym: Symbol, method: bool) -> Completion {
+    let (kind, detail) = match sym {
+        Symbol::Module(_) => (CompletionKind::Module, None),
+        Symbol::Class(_) => (CompletionKind::Class, None),
+        Symbol::Func(func) if method => (CompletionKind::Method, Some(func_label(func).0)),
+        Symbol::Func(func) => (CompletionKind::Function, Some(func_label(func).0)),
+        Symbol::Val(val) => (
+            CompletionKind::Constant,
+            Some(format!("({}){}", val.val_type(), val)),
+        ),
+    };
+
+    Completion {
+        label: label.to_owned(),
+        kind,
+        detail,
+    }
+}
+
+/// A snapshot of a source file
+#[derive(Debug)]
+pub struct Document {
+    toks: Vec<Tok>,
+    errors: Vec<Error>,
+}
+
+impl Document {
+    /// Resynth source files which it imports are found relative to `path`, and are only checked
+    /// if it's given
+    pub fn new(src: &str, path: Option<&Path>) -> Self {
+        let mut lex = Lexer::default();
+        let mut toks = Vec::new();
+
+        for (lno, line) in src.lines().enumerate() {
+            /* Any line which doesn't lex is left out, the error is in the diagnostics */
+            if let Ok(line_toks) = lex.line(lno + 1, line) {
+                toks.extend(line_toks.iter().map(|tok| Tok {
+                    typ: tok.tok_type(),
+                    span: tok.span(),
+                    val: tok.optval().map(String::from),
+                }));
+            }
+        }
+
+        let mut check = Checker::default();
+        if let Some(path) = path {
+            check.set_path(path);
+        }
+
+        Self {
+            toks,
+            errors: check.check_source(src),
+        }
+    }
+
+    /// Every error which would be found by `--check`
+    pub fn diagnostics(&self) -> &[Error] {
+        &self.errors
+    }
+
+    fn tok(&self, idx: Option<usize>, typ: TokType) -> Option<&Tok> {
+        idx.and_then(|idx| self.toks.get(idx))
+            .filter(|tok| tok.is(typ))
+    }
+
+    /// The identifier which `loc` is in, or right at the end of
+    fn ident_at(&self, loc: Loc) -> Option<usize> {
+        self.toks.iter().rposition(|tok| {
+            tok.is(TokType::Identifier)
+                && pos(tok.span.start) <= pos(loc)
+                && pos(loc) <= pos(tok.span.end)
+        })
+    }
+
+    /// The path which ends with the identifier at `idx`, ie. `ipv4::tcp` for `tcp` in
+    /// `ipv4::tcp::flow`. Returns the index at which the path starts.
+    fn path_to(&self, idx: usize) -> (usize, String) {
+        let mut first = idx;
+
+        while first >= 2
+            && self.toks[first - 1].is(TokType::DoubleColon)
+            && self.toks[first - 2].is(TokType::Identifier)
+        {
+            first -= 2;
+        }
+
+        let path: Vec<&str> = self.toks[first..=idx]
+            .iter()
+            .step_by(2)
+            .map(Tok::name)
+            .collect();
+
+        (first, path.join("::"))
+    }
+
+    /// If the path starting at `first` is a method call, the variable which it's called on
+    fn receiver(&self, first: usize) -> Option<&Tok> {
+        let dot = first.checked_sub(1);
+        self.tok(dot, TokType::Dot)?;
+        self.tok(first.checked_sub(2), TokType::Identifier)
+    }
+
+    /// Returns true if the identifier at `idx` is being bound by a `let`, `fn`, or `for`
+    fn is_binding(&self, idx: usize) -> bool {
+        let prev = |n: usize| idx.checked_sub(n).map(|i| self.toks[i].typ);
+
+        match prev(1) {
+            Some(TokType::LetKeyword | TokType::FnKeyword | TokType::ForKeyword) => true,
+            Some(TokType::MutKeyword) => prev(2) == Some(TokType::LetKeyword),
+            _ => false,
+        }
+    }
+
+    /// Where a name which is used at `loc` was bound. That's usually the last binding before it,
+    /// but functions may refer to globals which come after them.
+    fn binding(&self, name: &str, loc: Loc) -> Option<usize> {
+        let mut bindings = (0..self.toks.len())
+            .filter(|&idx| self.toks[idx].name() == name && self.is_binding(idx));
+
+        let first = bindings.next()?;
+        let last = std::iter::once(first)
+            .chain(bindings)
+            .take_while(|&idx| pos(self.toks[idx].span.start) <= pos(loc))
+            .last();
+
+        Some(last.unwrap_or(first))
+    }
+
+    /// The classes which a variable might be an instance of. If it was bound to the result of a
+    /// stdlib function then it's assumed to be one from the same module, otherwise it could be
+    /// any of them.
+    fn classes_of(&self, var: &str, loc: Loc) -> Vec<String> {
+        let all = classes();
+
+        let module = self.binding(var, loc).and_then(|idx| {
+            self.tok(Some(idx + 1), TokType::Equals)?;
+
+            let mut end = idx + 2;
+            self.tok(Some(end), TokType::Identifier)?;
+            while end + 2 < self.toks.len()
+                && self.toks[end + 1].is(TokType::DoubleColon)
+                && self.toks[end + 2].is(TokType::Identifier)
+            {
+                end += 2;
+            }
+
+            let (_, path) = self.path_to(end);
+            match lookup(&path)? {
+                Symbol::Func(_) => path.rsplit_once("::").map(|(module, _)| module.to_owned()),
+                _ => None,
+            }
+        });
+
+        if let Some(module) = module {
+            let prefix = format!("{}::", module);
+            let ret: Vec<String> = all
+                .iter()
+                .filter(|class| {
+                    class
+                        .strip_prefix(&prefix)
+                        .is_some_and(|name| !name.contains("::"))
+                })
+                .cloned()
+                .collect();
+
+            if !ret.is_empty() {
+                return ret;
+            }
+        }
+
+        all
+    }
+
+    /// The full path of the stdlib symbol at `idx`, including methods called on variables
+    fn resolve(&self, idx: usize) -> Option<String> {
+        let (first, path) = self.path_to(idx);
+
+        match self.receiver(first) {
+            Some(var) if first == idx => self
+                .classes_of(var.name(), var.span.start)
+                .into_iter()
+                .map(|class| format!("{}::{}", class, path))
+                .find(|path| matches!(lookup(path), Some(Symbol::Func(_)))),
+            Some(_) => None,
+            None => lookup(&path).map(|_| path),
+        }
+    }
+
+    /// Documentation for the stdlib symbol under the cursor, as markdown
+    pub fn hover(&self, loc: Loc) -> Option<(Span, String)> {
+        let idx = self.ident_at(loc)?;
+        let path = self.resolve(idx)?;
+
+        let mut doc = Vec::new();
+        write_symbol_docs(&path, &mut doc).ok()?;
+
+        Some((
+            self.toks[idx].span,
+            String::from_utf8_lossy(&doc).trim().to_owned(),
+        ))
+    }
+
+    /// Where the variable or function under the cursor was defined
+    pub fn definition(&self, loc: Loc) -> Option<Span> {
+        let idx = self.ident_at(loc)?;
+        let (first, _) = self.path_to(idx);
+
+        let in_path = first != idx || self.tok(Some(idx + 1), TokType::DoubleColon).is_some();
+        if in_path || self.receiver(idx).is_some() {
+            return None;
+        }
+
+        let tok = &self.toks[idx];
+        self.binding(tok.name(), tok.span.start)
+            .map(|idx| self.toks[idx].span)
+    }
+
+    /// Names of variables and functions defined anywhere in the file
+    fn bindings(&self) -> Vec<&str> {
+        let mut ret: Vec<&str> = (0..self.toks.len())
+            .filter(|&idx| self.toks[idx].is(TokType::Identifier) && self.is_binding(idx))
+            .map(|idx| self.toks[idx].name())
+            .collect();
+
+        ret.sort_unstable();
+        ret.dedup();
+        ret
+    }
+
+    /// What could be typed at the cursor, this could be a module path, a method, or a name
+    pub fn complete(&self, loc: Loc) -> Vec<Completion> {
+        let last = self
+            .toks
+            .iter()
+            .rposition(|tok| pos(tok.span.end) <= pos(loc));
+
+        /* If a word is being typed then that's a prefix of whatever is to be completed */
+        let (prefix, last) = match last.map(|idx| (idx, &self.toks[idx])) {
+            Some((idx, tok)) if tok.is(TokType::Identifier) && tok.span.end == loc => {
+                (tok.name(), idx.checked_sub(1))
+            }
+            _ => ("", last),
+        };
+
+        if self.tok(last, TokType::DoubleColon).is_some() {
+            let parent = last.and_then(|idx| idx.checked_sub(1));
+            if self.tok(parent, TokType::Identifier).is_none() {
+                return Vec::new();
+            }
+
+            let (_, parent) = self.path_to(parent.unwrap());
+
+            return complete(&format!("{}::{}", parent, prefix))
+                .into_iter()
+                .filter_map(|path| {
+                    let sym = lookup(&path)?;
+                    let name = path.rsplit("::").next().unwrap_or_default();
+                    Some(sym_completion(name, sym, false))
+                })
+                .collect();
+        }
+
+        if self.tok(last, TokType::Dot).is_some() {
+            let var = match self.tok(last.and_then(|idx| idx.checked_sub(1)), TokType::Identifier) {
+                Some(var) => var,
+                None => return Vec::new(),
+            };
+
+            let mut ret: Vec<Completion> = Vec::new();
+            for class in self.classes_of(var.name(), var.span.start) {
+                if let Some(Symbol::Class(class)) = lookup(&class) {
+                    for desc in class.symtab.iter() {
+                        let method = matches!(desc.sym, Symbol::Func(_));
+                        if method
+                            && desc.name.starts_with(prefix)
+                            && !ret.iter().any(|c| c.label == desc.name)
+                        {
+                            ret.push(sym_completion(desc.name, desc.sym, true));
+                        }
+                    }
+                }
+            }
+
+            ret.sort_by(|a, b| a.label.cmp(&b.label));
+            return ret;
+        }
+
+        let mut ret: Vec<Completion> = complete(prefix)
+            .into_iter()
+            .filter_map(|name| Some(sym_completion(&name, lookup(&name)?, false)))
+            .collect();
+
+        for name in self.bindings() {
+            if name.starts_with(prefix) && !ret.iter().any(|c| c.label == name) {
+                ret.push(Completion {
+                    label: name.to_owned(),
+                    kind: CompletionKind::Variable,
+                    detail: None,
+                });
+            }
+        }
+
+        ret
+    }
+
+    /// The signature of the stdlib function whose arguments the cursor is in
+    pub fn signature(&self, loc: Loc) -> Option<SignatureHelp> {
+        let mut depth = 0_usize;
+        let mut active = 0_usize;
+        let mut named = None;
+
+        for idx in (0..self.toks.len()).rev() {
+            let tok = &self.toks[idx];
+            if pos(tok.span.end) > pos(loc) {
+                continue;
+            }
+
+            match tok.typ {
+                TokType::RParen | TokType::RBracket => depth += 1,
+                TokType::LParen | TokType::LBracket if depth > 0 => depth -= 1,
+                TokType::Comma if depth == 0 => active += 1,
+                TokType::Colon if depth == 0 && active == 0 => {
+                    named = self.tok(idx.checked_sub(1), TokType::Identifier);
+                }
+                TokType::LParen => {
+                    let callee = idx.checked_sub(1);
+                    self.tok(callee, TokType::Identifier)?;
+
+                    let func = match lookup(&self.resolve(callee?)?)? {
+                        Symbol::Func(func) => func,
+                        _ => return None,
+                    };
+
+                    let (label, params) = func_label(func);
+
+                    /* Once an argument is passed by name, the position doesn't matter */
+                    if let Some(pos) = named
+                        .and_then(|tok| func.args.iter().position(|arg| arg.name == tok.name()))
+                    {
+                        active = pos;
+                    }
+
+                    return Some(SignatureHelp {
+                        label,
+                        params,
+                        active,
+                        doc: func.doc.trim().to_owned(),
+                    });
+                }
+                TokType::LBracket | TokType::SemiColon | TokType::LBrace | TokType::RBrace => {
+                    return None
+                }
+                _ => {}
+            }
+        }
+
+        None
+    }
+}
@@ -121,11 +121,25 @@ impl Drop for Args {
 pub struct ArgExpr {
     pub name: Option<String>,
     pub expr: Expr,
+    /// The expression is a list whose items are each passed as an anonymous arg, ie. `*list`
+    pub splat: bool,
 }
 
 impl ArgExpr {
     pub fn new(name: Option<String>, expr: Expr) -> Self {
-        Self { name, expr }
+        Self {
+            name,
+            expr,
+            splat: false,
+        }
+    }
+
+    pub fn splat(expr: Expr) -> Self {
+        Self {
+            name: None,
+            expr,
+            splat: true,
+        }
     }
 }
 
@@ -139,14 +153,6 @@ impl ArgSpec {
     pub fn new(name: Option<String>, val: Val) -> Self {
         Self { name, val }
     }
-
-    pub fn is_anon(&self) -> bool {
-        self.name.is_none()
-    }
-
-    pub fn is_named(&self) -> bool {
-        self.name.is_some()
-    }
 }
 
 impl From<ValDef> for ArgSpec {
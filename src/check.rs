@@ -0,0 +1,786 @@
+use crate::args::ArgExpr;
+use crate::err::Error;
+use crate::err::Error::{
+    CircularImportError, ImportError, MultipleAssignError, NameError, TypeError,
+};
+use crate::lex::{Lexer, EOF};
+use crate::libapi::{ArgInfo, ClassDef, FuncDef, Module, Signature};
+use crate::loc::Span;
+use crate::ops;
+use crate::parse::{Assign, Call, Expr, For, Func, If, Import, ObjectRef, Parser, Stmt};
+use crate::program::Program;
+use crate::stdlib::{builtin, is_cast, result_classes, toplevel_module, PARAM};
+use crate::sym::Symbol;
+use crate::val::{Typed, Val, ValType};
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Lex, parse and type-check a whole source file without running it, returning every error found,
+/// in order.
+pub fn check_source(src: &str) -> Vec<Error> {
+    Checker::default().check_source(src)
+}
+
+/// What's known about a value without running the program. [ValType::Any] means that the type
+/// isn't known until runtime, and anything goes.
+#[derive(Debug, Clone)]
+enum Ty {
+    Val(ValType),
+    /// An object which is an instance of one of these classes
+    Obj(Vec<&'static ClassDef>),
+    Func(&'static FuncDef),
+    /// A method of one of the classes which an object might be an instance of
+    Method(Vec<&'static FuncDef>),
+    UserFunc(Rc<UserSig>),
+}
+
+impl Ty {
+    const ANY: Ty = Ty::Val(ValType::Any);
+
+    fn val_type(&self) -> ValType {
+        match self {
+            Ty::Val(typ) => *typ,
+            Ty::Obj(_) => ValType::Obj,
+            Ty::Func(_) => ValType::Func,
+            Ty::Method(_) => ValType::Method,
+            Ty::UserFunc(_) => ValType::Func,
+        }
+    }
+}
+
+/// The signature of a user-defined function, default values aren't evaluated so this is only
+/// good for [type-checking](Signature::typecheck) calls.
+#[derive(Debug)]
+struct UserSig {
+    name: String,
+    params: Vec<String>,
+    min_args: usize,
+}
+
+impl Signature for UserSig {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn nr_args(&self) -> usize {
+        self.params.len()
+    }
+
+    fn min_args(&self) -> usize {
+        self.min_args
+    }
+
+    fn arg_pos(&self, name: &str) -> Option<usize> {
+        self.params.iter().position(|param| param == name)
+    }
+
+    fn arg_name(&self, idx: usize) -> &str {
+        &self.params[idx]
+    }
+
+    fn arg_default(&self, _idx: usize) -> Option<Val> {
+        None
+    }
+
+    fn arg_compatible(&self, _idx: usize, _typ: ValType) -> bool {
+        true
+    }
+
+    fn arg_type(&self, _idx: usize) -> ValType {
+        ValType::Any
+    }
+
+    fn collect_type(&self) -> ValType {
+        ValType::Void
+    }
+}
+
+/// An integer cast such as `u8()`, which is checked as though its argument were declared with the
+/// type being cast to, so that constants are range-checked as they are for other functions
+#[derive(Debug)]
+struct CastSig(&'static FuncDef);
+
+impl Signature for CastSig {
+    fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    fn nr_args(&self) -> usize {
+        self.0.nr_args()
+    }
+
+    fn min_args(&self) -> usize {
+        self.0.min_args()
+    }
+
+    fn arg_pos(&self, name: &str) -> Option<usize> {
+        self.0.arg_pos(name)
+    }
+
+    fn arg_name(&self, idx: usize) -> &str {
+        self.0.arg_name(idx)
+    }
+
+    fn arg_default(&self, idx: usize) -> Option<Val> {
+        self.0.arg_default(idx)
+    }
+
+    fn arg_compatible(&self, idx: usize, typ: ValType) -> bool {
+        self.0.arg_compatible(idx, typ)
+    }
+
+    fn arg_type(&self, _idx: usize) -> ValType {
+        self.0.return_type
+    }
+
+    fn collect_type(&self) -> ValType {
+        self.0.collect_type()
+    }
+}
+
+/// What an imported name refers to
+#[derive(Debug)]
+enum Namespace {
+    Stdlib(&'static Module),
+    /// The globals of a resynth source file
+    User(Rc<HashMap<String, Ty>>),
+    /// A module which couldn't be found, or a resynth source file when there's no
+    /// [path](Checker::set_path) to find it relative to, anything goes
+    Unknown,
+}
+
+#[derive(Debug, Default)]
+struct Scope {
+    vars: HashMap<String, Ty>,
+    mutable: HashSet<String>,
+    frame: bool,
+}
+
+/// Checks a program without running it, so that no packets are generated. Names are resolved
+/// just as [Program] would, and every call to a stdlib function is checked against its
+/// [declaration](FuncDef) using whatever argument types can be worked out statically.
+///
+/// Function bodies are checked once the whole file has been seen, since they may refer to
+/// globals which are defined after the function.
+#[derive(Debug)]
+pub struct Checker {
+    /// The globals are the first scope
+    scopes: Vec<Scope>,
+    imports: HashMap<String, Namespace>,
+    /// The file being checked, which imports are relative to
+    path: Option<PathBuf>,
+    /// User modules which have already been checked, by canonical path
+    modules: HashMap<PathBuf, Rc<HashMap<String, Ty>>>,
+    /// The files which are in the middle of being checked, for detecting circular imports
+    loading: Vec<PathBuf>,
+    funcs: Vec<(Rc<UserSig>, Vec<Stmt>)>,
+    /// Globals which were [defined](Self::define) rather than bound with `let`
+    defined: HashSet<String>,
+    errors: Vec<Error>,
+}
+
+impl Default for Checker {
+    fn default() -> Self {
+        Self {
+            scopes: vec![Scope::default()],
+            imports: HashMap::new(),
+            path: None,
+            modules: HashMap::new(),
+            loading: Vec::new(),
+            funcs: Vec::new(),
+            defined: HashSet::new(),
+            errors: Vec::new(),
+        }
+    }
+}
+
+impl Checker {
+    /// Lex, parse and type-check a whole source file, returning every error found, in order
+    pub fn check_source(mut self, src: &str) -> Vec<Error> {
+        let mut errors = self.add_source(src);
+        errors.extend(self.finish());
+        sort_errors(&mut errors);
+        errors
+    }
+
+    /// Feed a whole source file through the checker, returning any lex and parse errors
+    fn add_source(&mut self, src: &str) -> Vec<Error> {
+        let mut parse = Parser::default();
+        let mut lex = Lexer::default();
+        let mut errors = Vec::new();
+
+        for (lno, line) in src.lines().enumerate() {
+            match lex.line(lno + 1, line) {
+                Ok(toks) => {
+                    for tok in toks {
+                        if let Err(err) = parse.feed(&tok) {
+                            errors.push(err.with_span(tok.span()));
+                        }
+                    }
+                }
+                Err(err) => errors.push(err.with_span(lex.loc())),
+            };
+
+            for name in parse.get_poisoned() {
+                self.poison(&name);
+            }
+            self.add_stmts(parse.get_results());
+        }
+
+        if let Err(err) = parse.feed(&EOF) {
+            errors.push(err.with_span(lex.loc()));
+        }
+        self.add_stmts(parse.get_results());
+
+        errors
+    }
+
+    /// Set the path of the file being checked, as [Program::set_path] does, so that resynth
+    /// source files it imports can be found and checked too. Without it they're not checked.
+    pub fn set_path(&mut self, path: &Path) {
+        if let Ok(canonical) = path.canonicalize() {
+            self.loading.push(canonical);
+        }
+        self.path = Some(path.to_owned());
+    }
+
+    /// Bind a global as [Program::define] does, so that it can be referred to, or replaced by
+    /// the value of a `param()`
+    pub fn define(&mut self, name: &str, val: &Val) {
+        self.scopes[0]
+            .vars
+            .insert(name.to_owned(), Ty::Val(val.val_type()));
+        self.defined.insert(name.to_owned());
+    }
+
+    pub fn add_stmts(&mut self, stmts: Vec<Stmt>) {
+        for stmt in stmts {
+            self.add_stmt(stmt);
+        }
+    }
+
+    pub fn add_stmt(&mut self, stmt: Stmt) {
+        match stmt {
+            Stmt::Import(import) => self.check_import(&import),
+            Stmt::Func(func) => self.check_func(func),
+            stmt => self.check_stmt(&stmt),
+        }
+    }
+
+    /// Treat a name whose definition failed to parse as being defined, but of unknown type
+    pub fn poison(&mut self, name: &str) {
+        self.scopes[0]
+            .vars
+            .entry(name.to_owned())
+            .or_insert(Ty::ANY);
+    }
+
+    /// Check the bodies of all the functions, and return every error found, in order
+    pub fn finish(mut self) -> Vec<Error> {
+        self.check_bodies();
+        sort_errors(&mut self.errors);
+        self.errors
+    }
+
+    fn check_bodies(&mut self) {
+        for (sig, body) in std::mem::take(&mut self.funcs) {
+            let mut frame = Scope {
+                frame: true,
+                ..Default::default()
+            };
+            for param in sig.params.iter() {
+                frame.vars.insert(param.clone(), Ty::ANY);
+            }
+
+            self.scopes.push(frame);
+            self.check_block(&body);
+            self.scopes.pop();
+        }
+    }
+
+    fn error(&mut self, err: Error) {
+        self.errors.push(err);
+    }
+
+    fn bind(&mut self, name: &str, ty: Ty, span: Span) {
+        let scope = self.scopes.last_mut().unwrap();
+        if scope.vars.contains_key(name) {
+            self.error(MultipleAssignError(name.to_owned()).with_span(span));
+            return;
+        }
+        scope.vars.insert(name.to_owned(), ty);
+    }
+
+    fn lookup(&self, name: &str) -> Option<(usize, &Ty)> {
+        for (i, scope) in self.scopes.iter().enumerate().rev() {
+            if let Some(ty) = scope.vars.get(name) {
+                return Some((i, ty));
+            }
+            if scope.frame {
+                break;
+            }
+        }
+
+        self.scopes[0].vars.get(name).map(|ty| (0, ty))
+    }
+
+    fn check_import(&mut self, import: &Import) {
+        let name = &import.module;
+
+        let ns = match &import.path {
+            Some(path) => self.load_module(path).unwrap_or_else(|err| {
+                self.error(err.with_span(Span::word(import.loc, path.len() + 2)));
+                Namespace::Unknown
+            }),
+            None => match toplevel_module(name) {
+                Some(module) => Namespace::Stdlib(module),
+                None => {
+                    let span = Span::word(import.loc, name.len());
+                    self.error(ImportError(name.to_owned()).with_span(span));
+                    Namespace::Unknown
+                }
+            },
+        };
+
+        self.imports.entry(name.to_owned()).or_insert(ns);
+    }
+
+    /// Check a resynth source file, relative to the one being checked, and return its globals.
+    /// Each file is only checked once, no matter how many times it's imported. Nothing is known
+    /// about it if the checker has no path.
+    fn load_module(&mut self, spec: &str) -> Result<Namespace, Error> {
+        let path = match &self.path {
+            Some(importer) => importer.parent().unwrap_or(Path::new("")).join(spec),
+            None => return Ok(Namespace::Unknown),
+        };
+
+        let canonical = path.canonicalize().map_err(|_| {
+            ImportError(spec.to_owned()).with_msg(format!("Can't find {}", path.display()))
+        })?;
+
+        if self.loading.contains(&canonical) {
+            return Err(CircularImportError(spec.to_owned()));
+        }
+
+        if let Some(globals) = self.modules.get(&canonical) {
+            return Ok(Namespace::User(globals.clone()));
+        }
+
+        let src = fs::read_to_string(&path)?;
+
+        let mut child = Checker {
+            path: Some(path.clone()),
+            modules: std::mem::take(&mut self.modules),
+            loading: std::mem::take(&mut self.loading),
+            ..Default::default()
+        };
+
+        child.loading.push(canonical.clone());
+        let mut errors = child.add_source(&src);
+        child.check_bodies();
+        child.loading.pop();
+
+        errors.append(&mut child.errors);
+        for err in errors {
+            self.error(err.with_file(&path));
+        }
+
+        self.modules = child.modules;
+        self.loading = child.loading;
+
+        let globals = Rc::new(std::mem::take(&mut child.scopes[0].vars));
+        self.modules.insert(canonical, globals.clone());
+
+        Ok(Namespace::User(globals))
+    }
+
+    fn check_func(&mut self, func: Func) {
+        let Func {
+            loc,
+            name,
+            params,
+            body,
+        } = func;
+
+        for param in params.iter() {
+            if let Some(dfl) = &param.dfl {
+                self.check_expr(dfl);
+            }
+        }
+
+        let min_args = params
+            .iter()
+            .take_while(|param| param.dfl.is_none())
+            .count();
+        let sig = Rc::new(UserSig {
+            name: name.clone(),
+            params: params.into_iter().map(|param| param.name).collect(),
+            min_args,
+        });
+
+        self.bind(
+            &name,
+            Ty::UserFunc(sig.clone()),
+            Span::word(loc, name.len()),
+        );
+        self.funcs.push((sig, body));
+    }
+
+    fn check_block(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.check_stmt(stmt);
+        }
+    }
+
+    fn check_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Assign(assign) => self.check_assign(assign),
+            Stmt::Reassign(assign) => self.check_reassign(assign),
+            Stmt::Expr(expr) => {
+                self.check_expr(expr);
+            }
+            Stmt::For(stmt) => self.check_for(stmt),
+            Stmt::If(stmt) => self.check_if(stmt),
+            Stmt::Import(_) | Stmt::Func(_) => {
+                /* The parser only allows these at the top-level */
+                unreachable!();
+            }
+        }
+    }
+
+    fn check_assign(&mut self, assign: &Assign) {
+        let name = &assign.target;
+        let span = Span::word(assign.loc, name.len());
+        let ty = self.check_expr(&assign.rvalue);
+
+        /* As when running, only a param() may replace a definition */
+        if self.scopes.len() == 1 && self.defined.remove(name) {
+            self.scopes[0].vars.remove(name);
+            if !self.is_param(&assign.rvalue) {
+                self.error(
+                    MultipleAssignError(name.to_owned())
+                        .with_msg(format!(
+                            "`{}` is defined on the command line, use param() to give it a default",
+                            name
+                        ))
+                        .with_span(span),
+                );
+            }
+        }
+
+        self.bind(name, ty, span);
+        if assign.mutable {
+            self.scopes
+                .last_mut()
+                .unwrap()
+                .mutable
+                .insert(name.to_owned());
+        }
+    }
+
+    /// Whether an expression is a call to the builtin `param()`
+    fn is_param(&mut self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Call(call) => {
+                matches!(self.obj_type(&call.obj), Ok(Ty::Func(func)) if std::ptr::eq(func, &PARAM))
+            }
+            _ => false,
+        }
+    }
+
+    fn check_reassign(&mut self, assign: &Assign) {
+        let name = &assign.target;
+        let span = Span::word(assign.loc, name.len());
+
+        self.check_expr(&assign.rvalue);
+
+        match self.lookup(name) {
+            Some((i, _)) if self.scopes[i].mutable.contains(name) => {}
+            Some(_) => self.error(MultipleAssignError(name.to_owned()).with_span(span)),
+            None => self.error(
+                NameError
+                    .with_msg(format!("Can't find variable: {}", name))
+                    .with_span(span),
+            ),
+        }
+    }
+
+    fn check_for(&mut self, stmt: &For) {
+        let typ = self.check_expr(&stmt.iter).val_type();
+        if !matches!(typ, ValType::List | ValType::PktGen | ValType::Any) {
+            self.error(
+                TypeError
+                    .with_msg(format!("Not iterable: {}", typ))
+                    .with_span(stmt.iter.span()),
+            );
+        }
+
+        let item = match typ {
+            ValType::PktGen => Ty::Val(ValType::Pkt),
+            _ => Ty::ANY,
+        };
+
+        let mut scope = Scope::default();
+        scope.vars.insert(stmt.var.clone(), item);
+
+        self.scopes.push(scope);
+        self.check_block(&stmt.body);
+        self.scopes.pop();
+    }
+
+    fn check_if(&mut self, stmt: &If) {
+        let typ = self.check_expr(&stmt.cond).val_type();
+        if !matches!(typ, ValType::Bool | ValType::Any) {
+            self.error(
+                TypeError
+                    .with_msg(format!("Condition must be bool, not {}", typ))
+                    .with_types(ValType::Bool, typ)
+                    .with_span(stmt.cond.span()),
+            );
+        }
+
+        /* Unlike when running the program, both branches are checked */
+        for body in [&stmt.body, &stmt.orelse] {
+            self.scopes.push(Scope::default());
+            self.check_block(body);
+            self.scopes.pop();
+        }
+    }
+
+    /// Work out the type of an expression, reporting any errors along the way. If the expression
+    /// is broken then its type is unknown, so that the error doesn't cascade.
+    fn check_expr(&mut self, expr: &Expr) -> Ty {
+        match self.expr_type(expr) {
+            Ok(ty) => ty,
+            Err(err) => {
+                self.error(err.with_span(expr.span()));
+                Ty::ANY
+            }
+        }
+    }
+
+    fn expr_type(&mut self, expr: &Expr) -> Result<Ty, Error> {
+        Ok(match expr {
+            Expr::Nil => Ty::Val(ValType::Void),
+            Expr::Literal(_, lit) => Ty::Val(lit.val_type()),
+            Expr::ObjectRef(obj) => self.obj_type(obj)?,
+            Expr::Call(call) => self.call_type(call)?,
+            Expr::Neg(_, operand) => {
+                let typ = self.check_expr(operand).val_type();
+                Ty::Val(ops::neg_type(typ)?)
+            }
+            Expr::BinOp(_, op, a, b) => {
+                let a = self.check_expr(a).val_type();
+                let b = self.check_expr(b).val_type();
+                Ty::Val(op.result_type(a, b)?)
+            }
+            Expr::List(_, exprs) => {
+                for expr in exprs {
+                    self.check_expr(expr);
+                }
+                Ty::Val(ValType::List)
+            }
+            Expr::Index(_, target, index) => {
+                let target = self.check_expr(target).val_type();
+                let index = self.check_expr(index).val_type();
+                Ty::Val(ops::index_type(target, index)?)
+            }
+        })
+    }
+
+    fn obj_type(&mut self, obj: &ObjectRef) -> Result<Ty, Error> {
+        let ty = match obj.modules.first() {
+            Some(toplevel) => match self.imports.get(toplevel) {
+                None => {
+                    return Err(NameError.with_msg(format!("You have not imported {}", toplevel)));
+                }
+                Some(Namespace::Unknown) => return Ok(Ty::ANY),
+                Some(Namespace::Stdlib(module)) => Self::module_type(module, obj)?,
+                Some(Namespace::User(globals)) => Self::user_type(globals, obj)?,
+            },
+            None => {
+                let var_name = &obj.components[0];
+                match self.lookup(var_name) {
+                    Some((_, ty)) => ty.clone(),
+                    None => match builtin(var_name) {
+                        Some(Symbol::Func(fndef)) => Ty::Func(fndef),
+                        _ => {
+                            return Err(
+                                NameError.with_msg(format!("Can't find variable: {}", var_name))
+                            );
+                        }
+                    },
+                }
+            }
+        };
+
+        if obj.components.len() == 1 {
+            return Ok(ty);
+        }
+
+        /* Only objects have methods, and we may not know their class */
+        let name = &obj.components[1];
+        match ty {
+            Ty::Obj(classes) => Self::method_type(&classes, name),
+            Ty::Val(ValType::Obj | ValType::Any) => Ok(Ty::ANY),
+            ty => Err(TypeError.with_msg(format!("{} has no method {:?}", ty.val_type(), name))),
+        }
+    }
+
+    /// The method of that name in any of the classes which an object might be an instance of
+    fn method_type(classes: &[&'static ClassDef], name: &str) -> Result<Ty, Error> {
+        let methods: Vec<&'static FuncDef> = classes
+            .iter()
+            .filter_map(|class| match class.get(name) {
+                Some(Symbol::Func(fndef)) => Some(*fndef),
+                _ => None,
+            })
+            .collect();
+
+        if methods.is_empty() {
+            let names: Vec<&str> = classes.iter().map(|class| class.name).collect();
+            return Err(NameError.with_msg(format!(
+                "{} has no method {:?}",
+                names.join(" or "),
+                name
+            )));
+        }
+
+        Ok(Ty::Method(methods))
+    }
+
+    fn user_type(globals: &HashMap<String, Ty>, obj: &ObjectRef) -> Result<Ty, Error> {
+        if obj.modules.len() > 1 || obj.components.len() > 2 {
+            return Err(NameError.with_msg("Too many components in object"));
+        }
+
+        let var_name = &obj.components[0];
+        match globals.get(var_name) {
+            Some(ty) => Ok(ty.clone()),
+            None => {
+                Err(NameError.with_msg(format!("Can't find {} in {}", var_name, obj.modules[0])))
+            }
+        }
+    }
+
+    fn module_type(mut top: &'static Module, obj: &ObjectRef) -> Result<Ty, Error> {
+        for c in obj.modules.iter().skip(1) {
+            top = match top.get(c) {
+                Some(Symbol::Module(module)) => module,
+                None => {
+                    return Err(NameError.with_msg(format!("Can't find module component: {}", c)));
+                }
+                _ => {
+                    return Err(TypeError.with_msg(format!("Component is not module: {}", c)));
+                }
+            }
+        }
+
+        let topvar = &obj.components[0];
+        match top.get(topvar) {
+            Some(Symbol::Val(valdef)) => Ok(Ty::Val(valdef.val_type())),
+            Some(Symbol::Func(fndef)) => Ok(Ty::Func(fndef)),
+            Some(Symbol::Module(_)) | Some(Symbol::Class(_)) => {
+                Err(TypeError.with_msg(format!("Component is not a variable: {}", topvar)))
+            }
+            None => Err(NameError.with_msg(format!("Can't find ref component: {}", topvar))),
+        }
+    }
+
+    fn call_type(&mut self, call: &Call) -> Result<Ty, Error> {
+        let callee = self.obj_type(&call.obj)?;
+        let args = self.check_args(&call.args);
+
+        match callee {
+            Ty::Func(fndef) if is_cast(fndef) => {
+                Self::check_call(&CastSig(fndef), args, &call.args)?;
+                Ok(Ty::Val(fndef.return_type))
+            }
+            Ty::Func(fndef) => {
+                Self::check_call(fndef, args, &call.args)?;
+                Ok(match fndef.return_type {
+                    ValType::Obj => Ty::Obj(result_classes(fndef)),
+                    typ => Ty::Val(typ),
+                })
+            }
+            Ty::Method(methods) => {
+                /* The call is fine so long as one of the candidates accepts the args */
+                let mut result = Ok(());
+                for method in methods.iter() {
+                    result = Self::check_call(*method, args.clone(), &call.args);
+                    if result.is_ok() {
+                        break;
+                    }
+                }
+                result?;
+
+                let typ = methods[0].return_type;
+                Ok(
+                    match methods.iter().all(|method| method.return_type == typ) {
+                        true => Ty::Val(typ),
+                        false => Ty::ANY,
+                    },
+                )
+            }
+            Ty::UserFunc(sig) => {
+                Self::check_call(sig.as_ref(), args, &call.args)?;
+                Ok(Ty::Val(ValType::Void))
+            }
+            Ty::Val(ValType::Any) => Ok(Ty::ANY),
+            ty => Err(TypeError.with_msg(format!("{} is not callable", ty.val_type()))),
+        }
+    }
+
+    /// What's known about each argument, or [None] if they include a splat, since then the number
+    /// of arguments isn't known
+    fn check_args(&mut self, argexprs: &[ArgExpr]) -> Option<Vec<(Option<String>, ArgInfo)>> {
+        let mut ret = Vec::with_capacity(argexprs.len());
+        let mut splat = false;
+
+        for arg in argexprs {
+            let typ = self.check_expr(&arg.expr).val_type();
+            if arg.splat {
+                if !matches!(typ, ValType::List | ValType::Any) {
+                    self.error(
+                        TypeError
+                            .with_msg(format!("Can only splat a list, not {}", typ))
+                            .with_span(arg.expr.span()),
+                    );
+                }
+                splat = true;
+            }
+            let val = match &arg.expr {
+                Expr::Literal(_, lit) => Some(lit.clone()),
+                _ => None,
+            };
+            ret.push((arg.name.clone(), ArgInfo { typ, val }));
+        }
+
+        (!splat).then_some(ret)
+    }
+
+    fn check_call<S: Signature + ?Sized>(
+        sig: &S,
+        args: Option<Vec<(Option<String>, ArgInfo)>>,
+        argexprs: &[ArgExpr],
+    ) -> Result<(), Error> {
+        match args {
+            Some(args) => sig
+                .typecheck(args)
+                .map_err(|err| Program::locate_arg(err, sig, argexprs)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Errors in the file being checked come first, then those in each file it imports, in order
+fn sort_errors(errors: &mut [Error]) {
+    errors.sort_by_key(|err| {
+        let diag = err.diag();
+        let loc = diag.and_then(|d| d.span).unwrap_or_default().start;
+        (diag.and_then(|d| d.file.clone()), loc.line(), loc.col())
+    });
+}
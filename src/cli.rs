@@ -1,11 +1,16 @@
-use pkt::PcapWriter;
+mod repl;
+
+use pkt::{LinkType, Packet, PcapNgWriter, PcapReader, PcapWriter};
 
 use resynth::stdlib::write_docs;
+use resynth::{
+    decompile, format_source, Checker, Error, Lexer, Lint, Loc, Origin, Parser, Program, Span,
+    TokType, Val, EOF,
+};
 use resynth::{error, ok, warn};
-use resynth::{Error, Lexer, Loc, Parser, Program, EOF};
 
 use std::borrow::Cow;
-use std::io::BufRead;
+use std::io::{BufRead, Write};
 use std::path::{Path, PathBuf};
 use std::{fs, io};
 
@@ -26,6 +31,16 @@ impl ErrorLoc {
     }
 }
 
+impl ErrorLoc {
+    /// The most precise location of the error which is known
+    pub fn span(&self) -> Span {
+        self.err
+            .diag()
+            .and_then(|d| d.span)
+            .unwrap_or_else(|| Span::from(self.loc))
+    }
+}
+
 impl From<Error> for ErrorLoc {
     fn from(e: Error) -> Self {
         Self::new(Loc::nil(), e)
@@ -38,65 +53,501 @@ impl From<io::Error> for ErrorLoc {
     }
 }
 
+/// How lint warnings should be treated, as configured with `-W`
+#[derive(Debug, Default)]
+pub struct Warnings {
+    /// Treat warnings as errors
+    pub error: bool,
+    /// Lints which are not reported at all
+    pub allow: Vec<Lint>,
+}
+
+impl Warnings {
+    fn parse(args: &[&String]) -> Result<Self, String> {
+        let mut ret = Self::default();
+
+        for arg in args {
+            if arg.as_str() == "error" {
+                ret.error = true;
+            } else if let Some(name) = arg.strip_prefix("allow=") {
+                match Lint::from_name(name) {
+                    Some(lint) => ret.allow.push(lint),
+                    None => return Err(format!("Unknown lint: {}", name)),
+                }
+            } else {
+                return Err(format!("Bad warning option: {}", arg));
+            }
+        }
+
+        Ok(ret)
+    }
+}
+
+/// Print an error rustc-style, quoting the offending source line with the span underlined. The
+/// source is read from the file unless it's given in `src`, which is only used for errors in the
+/// input file itself and not for those in imported modules.
+fn report_error(st: &mut StandardStream, inp: &Path, src: Option<&str>, error: ErrorLoc) {
+    /* There's nowhere left to report a failure to write to the terminal */
+    write_error(st, inp, src, error).ok();
+}
+
+fn write_error(
+    st: &mut StandardStream,
+    inp: &Path,
+    src: Option<&str>,
+    error: ErrorLoc,
+) -> io::Result<()> {
+    let span = error.span();
+    let err = error.err;
+    let diag = err.diag();
+
+    let file = diag.and_then(|d| d.file.as_deref());
+    let path = file.unwrap_or(inp);
+
+    error!(st, "error");
+    writeln!(st, ": {}", err)?;
+
+    if span.is_nil() {
+        writeln!(st, " --> {}", path.display())?;
+        return Ok(());
+    }
+
+    let start = span.start;
+    let lno = start.line().to_string();
+    let gutter = " ".repeat(lno.len());
+
+    writeln!(
+        st,
+        "{}--> {}:{}:{}",
+        gutter,
+        path.display(),
+        start.line(),
+        start.col()
+    )?;
+
+    let src = match (src, file) {
+        (Some(src), None) => Some(Cow::Borrowed(src)),
+        _ => fs::read_to_string(path).ok().map(Cow::Owned),
+    };
+    let line = src.and_then(|src| src.lines().nth(start.line() - 1).map(str::to_owned));
+
+    if let Some(line) = line {
+        let line = line.trim_end();
+        let end = if span.end.line() == start.line() {
+            span.end.col().min(line.len() + 1)
+        } else {
+            line.len() + 1
+        };
+        let width = end.saturating_sub(start.col()).max(1);
+
+        writeln!(st, "{} |", gutter)?;
+        writeln!(st, "{} | {}", lno, line)?;
+        write!(st, "{} | {}", gutter, " ".repeat(start.col() - 1))?;
+        error!(st, "{}", "^".repeat(width));
+        writeln!(st)?;
+    }
+
+    if let Some(d) = diag {
+        match (&d.arg, d.expected, d.received) {
+            (Some(arg), Some(exp), Some(rcv)) => writeln!(
+                st,
+                "{} = note: argument `{}` expected `{}`, received `{}`",
+                gutter, arg, exp, rcv
+            )?,
+            (None, Some(exp), Some(rcv)) => writeln!(
+                st,
+                "{} = note: expected `{}`, received `{}`",
+                gutter, exp, rcv
+            )?,
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Execute whatever has been parsed so far, collecting errors rather than stopping at the first
+fn run_stmts(prog: &mut Program, parse: &mut Parser, errors: &mut Vec<ErrorLoc>) {
+    for name in parse.get_poisoned() {
+        prog.poison(&name);
+    }
+
+    for stmt in parse.get_results() {
+        match prog.add_stmt(stmt) {
+            Err(err) if matches!(err.kind(), Error::PoisonError(_)) => {}
+            Err(err) => errors.push(ErrorLoc::new(prog.loc(), err)),
+            Ok(()) => {}
+        }
+    }
+}
+
+/// Parse a `-D name=value` argument. The value is a literal, as it would be written in a program,
+/// and anything else is taken to be a string so that they don't need to be quoted.
+pub fn parse_define(arg: &str) -> Result<(String, Val), String> {
+    let (name, value) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("{}: expected name=value", arg))?;
+
+    let mut lex = Lexer::default();
+    match lex.line(1, name).as_deref() {
+        Ok([tok]) if tok.tok_type() == TokType::Identifier => {}
+        _ => return Err(format!("{}: not a valid name", name)),
+    }
+
+    let val = match Val::from_literal(value) {
+        Some(ret) => ret.map_err(|err| format!("{}: {}", value, err))?,
+        None => Val::str(value),
+    };
+
+    Ok((name.to_owned(), val))
+}
+
+/// What to record about where each packet came from, as configured with `--trace` and
+/// `--trace-json`
+#[derive(Debug, Default)]
+pub struct Trace {
+    /// Print the statement which emitted each packet
+    pub print: bool,
+    /// Write a JSON file alongside the pcap, mapping frame numbers to statements
+    pub json: bool,
+}
+
+impl Trace {
+    /// The JSON file which goes with a pcap
+    pub fn json_path(out: &Path) -> PathBuf {
+        out.with_extension("json")
+    }
+}
+
+fn json_site(file: Option<&Path>, loc: Loc) -> String {
+    let mut ret = String::new();
+    if let Some(file) = file {
+        ret.push_str(&format!("\"file\":{},", json_str(&file.to_string_lossy())));
+    }
+    ret.push_str(&format!("\"line\":{},\"col\":{}", loc.line(), loc.col()));
+    ret
+}
+
+/// Write out the origin of each packet as a JSON array, in frame order
+fn write_trace(path: &Path, origins: &[(u64, u64, Origin)]) -> Result<(), io::Error> {
+    let mut wr = io::BufWriter::new(fs::File::create(path)?);
+
+    writeln!(wr, "[")?;
+    for (i, (frame, ts, origin)) in origins.iter().enumerate() {
+        let calls: Vec<String> = origin
+            .calls
+            .iter()
+            .map(|call| {
+                format!(
+                    "{{\"func\":{},{}}}",
+                    json_str(&call.func),
+                    json_site(call.file.as_deref(), call.loc)
+                )
+            })
+            .collect();
+
+        writeln!(
+            wr,
+            "  {{\"frame\":{},\"time\":{},{},\"calls\":[{}]}}{}",
+            frame,
+            ts,
+            json_site(origin.file.as_deref(), origin.loc),
+            calls.join(","),
+            if i + 1 < origins.len() { "," } else { "" },
+        )?;
+    }
+    writeln!(wr, "]")?;
+
+    wr.flush()
+}
+
+/// The capture file format to write, as chosen with `--format` or the output file extension
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Format {
+    Pcap,
+    /// Also records interfaces, packet comments and directions
+    PcapNg,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "pcap" => Some(Format::Pcap),
+            "pcapng" => Some(Format::PcapNg),
+            _ => None,
+        }
+    }
+
+    /// The format implied by a file's extension, pcap if it's not one we know
+    pub fn from_path(path: &Path) -> Self {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(Self::from_name)
+            .unwrap_or(Format::Pcap)
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Pcap => "pcap",
+            Format::PcapNg => "pcapng",
+        }
+    }
+}
+
+/// Settings from the command line which apply to every input file
+#[derive(Debug, Default)]
+pub struct Options {
+    pub verbose: bool,
+    pub warnings: Warnings,
+    pub trace: Trace,
+    pub defines: Vec<(String, Val)>,
+    /// Overrides the format implied by the output file extension
+    pub format: Option<Format>,
+    /// Overrides any linktype chosen by the script
+    pub linktype: Option<LinkType>,
+}
+
+/// The output filename which means stdout
+const STDOUT: &str = "-";
+
 pub fn process_file(
-    stdout: &mut StandardStream,
+    stderr: &mut StandardStream,
     inp: &Path,
     out: &Path,
-    verbose: bool,
-) -> Result<(), ErrorLoc> {
-    let file = fs::File::open(inp)?;
+    opts: &Options,
+) -> Result<(), Vec<ErrorLoc>> {
+    let Options {
+        verbose,
+        warnings,
+        trace,
+        defines,
+        format,
+        linktype,
+    } = opts;
+    let fatal = |err: Error| vec![ErrorLoc::from(err)];
+
+    let file = fs::File::open(inp).map_err(|err| fatal(err.into()))?;
     let rd = io::BufReader::new(file);
-    let wr = {
-        let wr = PcapWriter::create(out)?;
-        if verbose {
-            wr.debug()
-        } else {
-            wr
+    let dest: Box<dyn Write> = if out == Path::new(STDOUT) {
+        Box::new(io::stdout())
+    } else {
+        Box::new(fs::File::create(out).map_err(|err| fatal(err.into()))?)
+    };
+    let dest = io::BufWriter::new(dest);
+    let mut prog = match format.unwrap_or_else(|| Format::from_path(out)) {
+        Format::Pcap => {
+            let wr = PcapWriter::new(dest);
+            Program::with_pcap_writer(if *verbose { wr.debug() } else { wr }).map_err(fatal)?
+        }
+        Format::PcapNg => {
+            let wr = PcapNgWriter::new(dest).map_err(|err| fatal(err.into()))?;
+            let mut prog =
+                Program::with_pcap_writer(if *verbose { wr.debug() } else { wr }).map_err(fatal)?;
+            prog.set_comments(true);
+            prog
         }
     };
-    let mut prog = Program::with_pcap_writer(wr)?;
+    if let Some(linktype) = linktype {
+        prog.set_linktype(*linktype).map_err(fatal)?;
+    }
+    prog.set_path(inp);
+    for (name, val) in defines {
+        prog.define(name, val.clone());
+    }
     let mut parse = Parser::default();
     let mut lex = Lexer::default();
 
-    let mut warning = |loc: Loc, warn: &str| {
+    /* With -W error, lints are collected and reported along with the errors */
+    let mut lints = Vec::new();
+
+    let mut warning = |lint: Lint, loc: Loc, warn: &str| {
+        if warnings.allow.contains(&lint) {
+            return;
+        }
+
+        if warnings.error {
+            let err = Error::LintError(lint).with_msg(warn).with_span(loc);
+            lints.push(ErrorLoc::new(loc, err));
+            return;
+        }
+
         if loc.is_nil() {
-            print!("{}: ", inp.display());
+            eprint!("{}: ", inp.display());
         } else {
-            print!("{}:{}:{}: ", inp.display(), loc.line(), loc.col());
+            eprint!("{}:{}:{}: ", inp.display(), loc.line(), loc.col());
         }
-        warn!(stdout, "warning");
-        println!(": {}", warn);
+        warn!(stderr, "warning");
+        eprintln!(": {} [{}]", warn, lint);
     };
     prog.set_warning(&mut warning);
 
-    for (lno, res) in rd.lines().enumerate() {
-        let line = res?;
+    /* Frames are numbered from one, as in wireshark */
+    let mut origins = Vec::new();
+    let mut frame = 0;
+    let mut packet = |ts: u64, _: &Packet, origin: &Origin| {
+        frame += 1;
+        if trace.print {
+            eprintln!("#{} {}", frame, origin);
+        }
+        if trace.json {
+            origins.push((frame, ts, origin.clone()));
+        }
+    };
+    if trace.print || trace.json {
+        prog.set_packet_callback(&mut packet);
+    }
 
-        let toks = match lex.line(lno + 1, &line) {
-            Ok(toks) => toks,
-            Err(err) => return Err(ErrorLoc::new(lex.loc(), err)),
-        };
+    /* Keep going after errors so that they can all be reported at once */
+    let mut errors = Vec::new();
 
-        for tok in toks {
-            if let Err(err) = parse.feed(&tok) {
-                return Err(ErrorLoc::new(tok.loc(), err));
+    for (lno, res) in rd.lines().enumerate() {
+        let line = res.map_err(|err| fatal(err.into()))?;
+
+        match lex.line(lno + 1, &line) {
+            Ok(toks) => {
+                for tok in toks {
+                    if let Err(err) = parse.feed(&tok) {
+                        errors.push(ErrorLoc::new(tok.loc(), err));
+                    }
+                }
             }
+            Err(err) => errors.push(ErrorLoc::new(lex.loc(), err)),
+        };
+
+        run_stmts(&mut prog, &mut parse, &mut errors);
+    }
+
+    if let Err(err) = parse.feed(&EOF) {
+        errors.push(ErrorLoc::new(lex.loc(), err));
+    }
+
+    run_stmts(&mut prog, &mut parse, &mut errors);
+
+    if errors.is_empty() {
+        prog.finish();
+    }
+
+    if let Err(err) = prog.flush() {
+        errors.push(ErrorLoc::from(err));
+    }
+
+    drop(prog);
+    errors.append(&mut lints);
+
+    if trace.json {
+        if let Err(err) = write_trace(&Trace::json_path(out), &origins) {
+            errors.push(ErrorLoc::from(err));
         }
+    }
+
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(errors),
+    }
+}
+
+/// Lex, parse and type-check a file without running it, so no pcap is written
+pub fn check_file(inp: &Path, opts: &Options) -> Result<(), Vec<ErrorLoc>> {
+    let src = fs::read_to_string(inp).map_err(|err| vec![ErrorLoc::from(err)])?;
+
+    let mut check = Checker::default();
+    check.set_path(inp);
+    for (name, val) in opts.defines.iter() {
+        check.define(name, val);
+    }
+    let errors: Vec<ErrorLoc> = check
+        .check_source(&src)
+        .into_iter()
+        .map(ErrorLoc::from)
+        .collect();
+
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(errors),
+    }
+}
+
+/// Lay a file out canonically, returns whether it needed to change. Under `check` the file is
+/// left alone.
+pub fn fmt_file(inp: &Path, check: bool) -> Result<bool, ErrorLoc> {
+    let src = fs::read_to_string(inp)?;
+    let out = format_source(&src)?;
+
+    if out == src {
+        return Ok(false);
+    }
+
+    if !check {
+        fs::write(inp, out)?;
+    }
+
+    Ok(true)
+}
 
-        if let Err(err) = prog.add_stmts(parse.get_results()) {
-            return Err(ErrorLoc::new(prog.loc(), err));
+/// Reconstruct a program from a pcap or pcapng file
+pub fn decompile_file(inp: &Path) -> Result<String, ErrorLoc> {
+    Ok(decompile(PcapReader::open(inp)?)?)
+}
+
+fn json_str(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len() + 2);
+    ret.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            '\n' => ret.push_str("\\n"),
+            c if (c as u32) < 0x20 => ret.push_str(&format!("\\u{:04x}", c as u32)),
+            c => ret.push(c),
         }
     }
+    ret.push('"');
+    ret
+}
 
-    if let Err(err) = parse.feed(&EOF) {
-        return Err(ErrorLoc::new(lex.loc(), err));
+/// Print an error as a single line of JSON, for consumption by other tools
+fn report_json(inp: &Path, error: &ErrorLoc) {
+    let err = &error.err;
+    let diag = err.diag();
+    let path = diag.and_then(|d| d.file.as_deref()).unwrap_or(inp);
+    let span = error.span();
+
+    let mut fields = vec![
+        ("file", json_str(&path.to_string_lossy())),
+        ("error", json_str(err.name())),
+        (
+            "message",
+            json_str(&match diag.and_then(|d| d.msg.as_ref()) {
+                Some(msg) => msg.clone(),
+                None => err.kind().to_string(),
+            }),
+        ),
+    ];
+
+    if !span.is_nil() {
+        fields.push(("line", span.start.line().to_string()));
+        fields.push(("col", span.start.col().to_string()));
+        fields.push(("end_line", span.end.line().to_string()));
+        fields.push(("end_col", span.end.col().to_string()));
     }
 
-    if let Err(err) = prog.add_stmts(parse.get_results()) {
-        return Err(ErrorLoc::new(prog.loc(), err));
+    if let Some(d) = diag {
+        if let Some(arg) = &d.arg {
+            fields.push(("arg", json_str(arg)));
+        }
+        if let Some(typ) = d.expected {
+            fields.push(("expected", json_str(&typ.to_string())));
+        }
+        if let Some(typ) = d.received {
+            fields.push(("received", json_str(&typ.to_string())));
+        }
     }
 
-    Ok(())
+    let fields: Vec<String> = fields
+        .into_iter()
+        .map(|(k, v)| format!("\"{}\":{}", k, v))
+        .collect();
+    println!("{{{}}}", fields.join(","));
 }
 
 fn resynth() -> Result<(), ()> {
@@ -127,6 +578,56 @@ fn resynth() -> Result<(), ()> {
                 .action(ArgAction::SetTrue)
                 .help("Keep pcap files on error"),
         )
+        .arg(
+            Arg::new("check")
+                .long("check")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["out", "keep", "verbose"])
+                .help("Check the input files for errors without writing pcaps, as JSON lines"),
+        )
+        .arg(
+            Arg::new("trace")
+                .long("trace")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("check")
+                .help("Print the source location and call path which produced each packet"),
+        )
+        .arg(
+            Arg::new("trace_json")
+                .long("trace-json")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("check")
+                .help("Write the origin of each packet to a .json file next to the pcap"),
+        )
+        .arg(
+            Arg::new("define")
+                .short('D')
+                .long("define")
+                .value_name("NAME=VALUE")
+                .action(ArgAction::Append)
+                .help("Bind a global variable, which is also the value of param(\"NAME\", ...)"),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .value_parser(["pcap", "pcapng"])
+                .conflicts_with("check")
+                .help("pcap|pcapng, by default this is chosen by the output file extension"),
+        )
+        .arg(
+            Arg::new("linktype")
+                .long("linktype")
+                .value_parser(["null", "ethernet", "raw", "linux_sll", "linux_sll2"])
+                .conflicts_with("check")
+                .help("Link-layer type of the output, packets are converted to suit it"),
+        )
+        .arg(
+            Arg::new("warn")
+                .short('W')
+                .value_name("OPTION")
+                .action(ArgAction::Append)
+                .help("error: treat warnings as errors, allow=<name>: silence a lint"),
+        )
         .arg(
             Arg::new("docs")
                 .long("output-docs")
@@ -143,7 +644,7 @@ fn resynth() -> Result<(), ()> {
                 .required(false)
                 .value_parser(value_parser!(PathBuf))
                 .action(ArgAction::Append)
-                .help("Filenames for pcap output"),
+                .help("Filenames for pcap output, - writes to stdout"),
         )
         .arg(
             Arg::new("outdir")
@@ -166,19 +667,91 @@ fn resynth() -> Result<(), ()> {
             ArgGroup::new("run_mode")
                 .args(["docs", "in"])
                 .required(true),
-        );
+        )
+        .subcommand(
+            Command::new("repl")
+                .about("Interactively evaluate statements, with tab completion over the stdlib"),
+        )
+        .subcommand(
+            Command::new("fmt")
+                .about("Reformat source files in place, in the canonical style")
+                .arg(
+                    Arg::new("check")
+                        .long("check")
+                        .action(ArgAction::SetTrue)
+                        .help("Don't write anything, fail if any file would be reformatted"),
+                )
+                .arg(
+                    Arg::new("in")
+                        .value_name("FILE")
+                        .required(true)
+                        .action(ArgAction::Append)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            Command::new("decompile")
+                .about("Turn a capture in to a program which reproduces its TCP and UDP flows")
+                .arg(
+                    Arg::new("out")
+                        .short('o')
+                        .long("output")
+                        .value_name("FILE")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("Write the program to a file instead of printing it"),
+                )
+                .arg(
+                    Arg::new("in")
+                        .value_name("FILE")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                        .index(1),
+                ),
+        )
+        .subcommand_negates_reqs(true)
+        .args_conflicts_with_subcommands(true);
 
     let argv = cmd.clone().get_matches();
 
-    let verbose = argv.get_one::<bool>("verbose").copied().unwrap();
     let keep = argv.get_one::<bool>("keep").copied().unwrap();
+    let check = argv.get_one::<bool>("check").copied().unwrap();
+    let mut opts = Options {
+        verbose: argv.get_one::<bool>("verbose").copied().unwrap(),
+        trace: Trace {
+            print: argv.get_one::<bool>("trace").copied().unwrap(),
+            json: argv.get_one::<bool>("trace_json").copied().unwrap(),
+        },
+        format: argv
+            .get_one::<String>("format")
+            .and_then(|name| Format::from_name(name)),
+        linktype: argv
+            .get_one::<String>("linktype")
+            .and_then(|name| LinkType::from_name(name)),
+        ..Default::default()
+    };
+
+    let warn_args = argv
+        .get_many::<String>("warn")
+        .unwrap_or_default()
+        .collect::<Vec<_>>();
+    opts.warnings = match Warnings::parse(&warn_args) {
+        Ok(warnings) => warnings,
+        Err(msg) => cmd.error(ErrorKind::InvalidValue, msg).exit(),
+    };
+
+    for arg in argv.get_many::<String>("define").unwrap_or_default() {
+        match parse_define(arg) {
+            Ok(define) => opts.defines.push(define),
+            Err(msg) => cmd.error(ErrorKind::InvalidValue, msg).exit(),
+        }
+    }
 
     let preference: &String = argv.get_one("color").expect("default");
     let color = match preference.as_str() {
         "always" => ColorChoice::Always,
         "ansi" => ColorChoice::AlwaysAnsi,
         "auto" => {
-            if atty::is(atty::Stream::Stdout) {
+            if atty::is(atty::Stream::Stderr) {
                 ColorChoice::Auto
             } else {
                 ColorChoice::Never
@@ -186,7 +759,47 @@ fn resynth() -> Result<(), ()> {
         }
         _ => ColorChoice::Never,
     };
-    let mut stdout = StandardStream::stdout(color);
+    /* Anything informational goes to stderr, since a pcap might be being written to stdout */
+    let mut stderr = StandardStream::stderr(color);
+
+    if argv.subcommand_matches("repl").is_some() {
+        return repl::repl(color);
+    }
+
+    if let Some(fmt) = argv.subcommand_matches("fmt") {
+        let check = fmt.get_one::<bool>("check").copied().unwrap();
+
+        for input in fmt.get_many::<String>("in").unwrap() {
+            let p = Path::new(input);
+            match fmt_file(p, check) {
+                Ok(false) => {}
+                Ok(true) if check => {
+                    eprintln!("{}: would be reformatted", p.display());
+                    ret = Err(());
+                }
+                Ok(true) => eprintln!("{}: reformatted", p.display()),
+                Err(error) => {
+                    report_error(&mut stderr, p, None, error);
+                    ret = Err(());
+                }
+            }
+        }
+        return ret;
+    }
+
+    if let Some(dec) = argv.subcommand_matches("decompile") {
+        let p: &PathBuf = dec.get_one("in").unwrap();
+        let result = decompile_file(p).and_then(|src| match dec.get_one::<PathBuf>("out") {
+            Some(out) => Ok(fs::write(out, src)?),
+            None => Ok(io::stdout().write_all(src.as_bytes())?),
+        });
+
+        if let Err(error) = result {
+            report_error(&mut stderr, p, None, error);
+            return Err(());
+        }
+        return Ok(());
+    }
 
     if let Some(docs_dir) = argv.get_one::<PathBuf>("docs") {
         write_docs(docs_dir);
@@ -195,6 +808,19 @@ fn resynth() -> Result<(), ()> {
 
     let use_filenames = argv.contains_id("out");
 
+    if check {
+        for input in argv.get_many::<String>("in").unwrap() {
+            let p = Path::new(input);
+            if let Err(errors) = check_file(p, &opts) {
+                for error in errors.iter() {
+                    report_json(p, error);
+                }
+                ret = Err(());
+            }
+        }
+        return ret;
+    }
+
     let in_args = argv.get_many::<String>("in").unwrap();
 
     let out_args = argv
@@ -215,6 +841,22 @@ fn resynth() -> Result<(), ()> {
         .exit();
     }
 
+    let to_stdout = out_args.iter().filter(|p| **p == Path::new(STDOUT)).count();
+    if to_stdout > 1 {
+        cmd.error(
+            ErrorKind::ArgumentConflict,
+            "Only one output can be written to stdout",
+        )
+        .exit();
+    }
+    if to_stdout > 0 && opts.trace.json {
+        cmd.error(
+            ErrorKind::ArgumentConflict,
+            "--trace-json needs an output file to write the trace next to, not stdout",
+        )
+        .exit();
+    }
+
     for (i, input) in in_args.enumerate() {
         let p = Path::new(input);
         let out = if use_filenames {
@@ -225,36 +867,43 @@ fn resynth() -> Result<(), ()> {
                 None => PathBuf::new(),
             };
             out.push(p.file_stem().unwrap());
-            out.set_extension("pcap");
+            out.set_extension(opts.format.unwrap_or(Format::Pcap).extension());
             Cow::Owned(out)
         };
 
-        let result = process_file(&mut stdout, p, &out, verbose);
-
-        if let Err(error) = result {
-            let ErrorLoc { loc, err } = error;
+        let result = process_file(&mut stderr, p, &out, &opts);
 
-            if loc.is_nil() {
-                print!("{}: ", p.display());
-            } else {
-                print!("{}:{}:{}: ", p.display(), loc.line(), loc.col());
+        if let Err(errors) = result {
+            let count = errors.len();
+            for error in errors {
+                report_error(&mut stderr, p, None, error);
+                eprintln!();
             }
-            error!(stdout, "error");
-            println!(": process_file: {}", err);
 
-            if !keep {
+            error!(stderr, "error");
+            eprintln!(
+                ": {}: aborting due to {} previous error{}",
+                p.display(),
+                count,
+                if count == 1 { "" } else { "s" }
+            );
+
+            if !keep && out.as_ref() != Path::new(STDOUT) {
                 if let Err(rm_err) = fs::remove_file(out.as_ref()) {
-                    print!("{}: ", p.display());
-                    error!(stdout, "error");
-                    println!(": delete: {}", rm_err);
+                    eprint!("{}: ", p.display());
+                    error!(stderr, "error");
+                    eprintln!(": delete: {}", rm_err);
+                }
+                if opts.trace.json {
+                    fs::remove_file(Trace::json_path(&out)).ok();
                 }
             }
 
             ret = Err(());
         } else {
-            print!("{} -> {} ", p.display(), out.display());
-            ok!(stdout, "ok");
-            println!();
+            eprint!("{} -> {} ", p.display(), out.display());
+            ok!(stderr, "ok");
+            eprintln!();
         }
     }
 
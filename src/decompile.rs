@@ -0,0 +1,586 @@
+use crate::err::Error;
+use crate::err::Error::RuntimeError;
+use crate::format::format_source;
+
+use pkt::eth::{eth_hdr, ethertype};
+use pkt::ipv4::proto;
+use pkt::{LinkType, Packet, PcapReader};
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::io::Read;
+use std::mem::size_of;
+use std::net::{Ipv4Addr, SocketAddrV4};
+
+/// Gaps between packets shorter than this are just the usual spacing, and aren't worth a
+/// `time::jump_*()`
+const JUMP_MIN: u64 = 1_000_000;
+
+const TCP_FIN: u8 = 0x01;
+const TCP_SYN: u8 = 0x02;
+const TCP_RST: u8 = 0x04;
+const TCP_ACK: u8 = 0x10;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Side {
+    Client,
+    Server,
+}
+
+impl Side {
+    fn idx(self) -> usize {
+        self as usize
+    }
+
+    fn peer(self) -> usize {
+        1 - self.idx()
+    }
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Side::Client => "client",
+            Side::Server => "server",
+        })
+    }
+}
+
+/// An unfragmented IPv4 packet
+struct Ip<'a> {
+    src: Ipv4Addr,
+    dst: Ipv4Addr,
+    proto: u8,
+    payload: &'a [u8],
+}
+
+impl<'a> Ip<'a> {
+    fn parse(buf: &'a [u8]) -> Option<Self> {
+        if buf.len() < 20 || buf[0] >> 4 != 4 {
+            return None;
+        }
+
+        let ihl = ((buf[0] & 0xf) as usize) * 4;
+        let tot_len = u16::from_be_bytes([buf[2], buf[3]]) as usize;
+        let frag = u16::from_be_bytes([buf[6], buf[7]]) & 0x3fff;
+
+        if ihl < 20 || tot_len < ihl || tot_len > buf.len() || frag != 0 {
+            return None;
+        }
+
+        Some(Self {
+            src: Ipv4Addr::new(buf[12], buf[13], buf[14], buf[15]),
+            dst: Ipv4Addr::new(buf[16], buf[17], buf[18], buf[19]),
+            proto: buf[9],
+            payload: &buf[ihl..tot_len],
+        })
+    }
+}
+
+struct Seg<'a> {
+    sport: u16,
+    dport: u16,
+    seq: u32,
+    ack: u32,
+    flags: u8,
+    payload: &'a [u8],
+}
+
+impl<'a> Seg<'a> {
+    fn parse(buf: &'a [u8]) -> Option<Self> {
+        if buf.len() < 20 {
+            return None;
+        }
+
+        let doff = ((buf[12] >> 4) as usize) * 4;
+        if doff < 20 || doff > buf.len() {
+            return None;
+        }
+
+        Some(Self {
+            sport: u16::from_be_bytes([buf[0], buf[1]]),
+            dport: u16::from_be_bytes([buf[2], buf[3]]),
+            seq: u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]),
+            ack: u32::from_be_bytes([buf[8], buf[9], buf[10], buf[11]]),
+            flags: buf[13],
+            payload: &buf[doff..],
+        })
+    }
+}
+
+/// What we know about a TCP connection, indexed by [Side]
+#[derive(Default)]
+struct TcpConv {
+    /// The initial sequence numbers, for the `cl_seq` and `sv_seq` arguments of the flow
+    isn: [Option<u32>; 2],
+    /// The next sequence number we expect from each side
+    nxt: [Option<u32>; 2],
+    opened: bool,
+    closed: bool,
+}
+
+impl TcpConv {
+    /// The method calls on the flow which reproduce a segment. Handshakes, closes and ACKs
+    /// come out as a single call, since the flow generates the rest of the packets itself.
+    fn segment(&mut self, side: Side, seg: &Seg) -> Vec<String> {
+        let (me, peer) = (side.idx(), side.peer());
+        let mut calls = Vec::new();
+
+        if self.closed {
+            return calls;
+        }
+
+        if seg.flags & TCP_RST != 0 {
+            self.closed = true;
+            calls.push(format!("{}_reset()", side));
+            return calls;
+        }
+
+        if seg.flags & TCP_SYN != 0 {
+            self.isn[me] = Some(seg.seq);
+            self.nxt[me] = Some(seg.seq.wrapping_add(1));
+            if seg.flags & TCP_ACK != 0 && self.isn[peer].is_none() {
+                self.isn[peer] = Some(seg.ack.wrapping_sub(1));
+                self.nxt[peer] = Some(seg.ack);
+            }
+            if !self.opened {
+                self.opened = true;
+                calls.push("open()".to_owned());
+            }
+            return calls;
+        }
+
+        /* We've joined mid-stream, so the flow starts wherever we are now */
+        let nxt = *self.nxt[me].get_or_insert(seg.seq);
+        self.isn[me].get_or_insert(seg.seq);
+        if seg.flags & TCP_ACK != 0 && self.nxt[peer].is_none() {
+            self.isn[peer] = Some(seg.ack);
+            self.nxt[peer] = Some(seg.ack);
+        }
+
+        if !seg.payload.is_empty() {
+            let ahead = seg.seq.wrapping_sub(nxt) as i32;
+            let mut data = seg.payload;
+
+            if ahead > 0 {
+                calls.push(format!("{}_hole({})", side, ahead));
+            } else if ahead < 0 {
+                /* Retransmitted, perhaps with some new data on the end */
+                data = data.get(ahead.unsigned_abs() as usize..).unwrap_or(&[]);
+            }
+
+            if !data.is_empty() {
+                calls.push(format!("{}_message({})", side, literals(data).join(", ")));
+            }
+
+            let end = seg.seq.wrapping_add(seg.payload.len() as u32);
+            if end.wrapping_sub(nxt) as i32 > 0 {
+                self.nxt[me] = Some(end);
+            }
+        }
+
+        if seg.flags & TCP_FIN != 0 {
+            self.closed = true;
+            calls.push(format!("{}_close()", side));
+        }
+
+        calls
+    }
+}
+
+enum Flow {
+    Tcp(TcpConv),
+    Udp,
+}
+
+struct Conv {
+    var: String,
+    cl: SocketAddrV4,
+    sv: SocketAddrV4,
+    declared: bool,
+    flow: Flow,
+}
+
+impl Conv {
+    fn decl(&self) -> String {
+        let mut args: Vec<String> = [self.cl, self.sv]
+            .iter()
+            .map(|sock| format!("{}/{}", sock.ip(), sock.port()))
+            .collect();
+
+        let module = match &self.flow {
+            Flow::Tcp(tcp) => {
+                for (name, isn) in ["cl_seq", "sv_seq"].iter().zip(tcp.isn.iter()) {
+                    match isn {
+                        Some(1) | None => {}
+                        Some(isn) => args.push(format!("{}: {}", name, isn)),
+                    }
+                }
+                "tcp"
+            }
+            Flow::Udp => "udp",
+        };
+
+        format!(
+            "let {} = ipv4::{}::flow({});",
+            self.var,
+            module,
+            args.join(", ")
+        )
+    }
+}
+
+enum Item {
+    /// The gap before a group of statements, which becomes a `time::jump_*()` if it's long enough
+    Gap(u64),
+    /// Declare the flow for a conversation
+    Let(usize),
+    Stmt(String),
+}
+
+/// The statements which came from a packet, along with any following packets which they'll
+/// produce themselves, such as ACKs
+struct Group {
+    /// Where in the items its [Gap](Item::Gap) is
+    slot: usize,
+    start: u64,
+    /// The time it takes to send all of the packets
+    bits: u64,
+    /// When the last packet before the group was sent
+    prev: Option<u64>,
+}
+
+/// Bytes which can go in to a string literal as they are
+fn printable(b: u8) -> bool {
+    (0x20..0x7f).contains(&b) && b != b'"' && b != b'|'
+}
+
+/// String literals for a payload, with anything unprintable in `|..|` hex. Text is split in to
+/// lines, binary data in to 16 byte chunks.
+fn literals(data: &[u8]) -> Vec<String> {
+    let text = data
+        .iter()
+        .filter(|b| printable(**b) || matches!(b, b'\r' | b'\n' | b'\t'))
+        .count();
+    let is_text = text * 4 >= data.len() * 3;
+
+    let chunks: Vec<&[u8]> = if is_text {
+        data.split_inclusive(|b| *b == b'\n')
+            .flat_map(|line| line.chunks(64))
+            .collect()
+    } else {
+        data.chunks(16).collect()
+    };
+
+    chunks
+        .into_iter()
+        .map(|chunk| {
+            let mut ret = String::from("\"");
+            let mut hex = false;
+
+            for b in chunk {
+                if is_text && printable(*b) {
+                    if hex {
+                        ret.push('|');
+                        hex = false;
+                    }
+                    ret.push(*b as char);
+                } else {
+                    ret.push_str(if hex { " " } else { "|" });
+                    ret.push_str(&format!("{:02x}", b));
+                    hex = true;
+                }
+            }
+
+            if hex {
+                ret.push('|');
+            }
+            ret.push('"');
+            ret
+        })
+        .collect()
+}
+
+fn jump(ns: u64) -> String {
+    const SEC: u64 = 1_000_000_000;
+
+    let (func, n) = if ns.is_multiple_of(SEC) && ns / SEC <= u32::MAX as u64 {
+        ("seconds", ns / SEC)
+    } else if ns.is_multiple_of(1_000_000) {
+        ("millis", ns / 1_000_000)
+    } else if ns.is_multiple_of(1_000) {
+        ("micros", ns / 1_000)
+    } else {
+        ("nanos", ns)
+    };
+
+    format!("time::jump_{}({});", func, n)
+}
+
+#[derive(Default)]
+struct Decompiler {
+    linktype: Option<LinkType>,
+    convs: Vec<Conv>,
+    index: HashMap<(u8, SocketAddrV4, SocketAddrV4), usize>,
+    items: Vec<Item>,
+    imports: BTreeSet<&'static str>,
+    group: Option<Group>,
+    /// When the previous packet was sent
+    last: Option<u64>,
+}
+
+impl Decompiler {
+    /// Work out the gap before the current group. All of the packets which a statement emits
+    /// are sent together, once there's been time to transmit them.
+    fn close(&mut self) {
+        if let Some(Group {
+            slot,
+            start,
+            bits,
+            prev: Some(prev),
+        }) = self.group.take()
+        {
+            let gap = start.saturating_sub(prev + bits);
+            if gap >= JUMP_MIN {
+                self.imports.insert("time");
+            }
+            self.items[slot] = Item::Gap(gap);
+        }
+    }
+
+    fn stmt(&mut self, conv: Option<usize>, stmt: String) {
+        if let Some(idx) = conv {
+            if !self.convs[idx].declared {
+                self.convs[idx].declared = true;
+                self.imports.insert("ipv4");
+                self.items.push(Item::Let(idx));
+            }
+        }
+
+        self.items.push(Item::Stmt(stmt));
+    }
+
+    /// Find the conversation a packet belongs to, and which side sent it. New conversations are
+    /// started by the client, unless the first packet we see is the SYN-ACK.
+    fn conv(
+        &mut self,
+        proto: u8,
+        src: SocketAddrV4,
+        dst: SocketAddrV4,
+        reply: bool,
+    ) -> (usize, Side) {
+        if let Some(idx) = self.index.get(&(proto, src, dst)) {
+            return (*idx, Side::Client);
+        }
+        if let Some(idx) = self.index.get(&(proto, dst, src)) {
+            return (*idx, Side::Server);
+        }
+
+        let (cl, sv, side) = match reply {
+            true => (dst, src, Side::Server),
+            false => (src, dst, Side::Client),
+        };
+
+        let (name, flow) = match proto {
+            proto::TCP => ("tcp", Flow::Tcp(TcpConv::default())),
+            _ => ("udp", Flow::Udp),
+        };
+        let idx = self.convs.len();
+
+        self.convs.push(Conv {
+            var: format!("{}{}", name, idx + 1),
+            cl,
+            sv,
+            declared: false,
+            flow,
+        });
+        self.index.insert((proto, cl, sv), idx);
+
+        (idx, side)
+    }
+
+    fn tcp(&mut self, ip: &Ip) -> bool {
+        let seg = match Seg::parse(ip.payload) {
+            Some(seg) => seg,
+            None => return false,
+        };
+
+        let src = SocketAddrV4::new(ip.src, seg.sport);
+        let dst = SocketAddrV4::new(ip.dst, seg.dport);
+        let synack = seg.flags & (TCP_SYN | TCP_ACK) == TCP_SYN | TCP_ACK;
+        let (idx, side) = self.conv(proto::TCP, src, dst, synack);
+
+        let calls = match &mut self.convs[idx].flow {
+            Flow::Tcp(tcp) => tcp.segment(side, &seg),
+            Flow::Udp => unreachable!(),
+        };
+
+        for call in calls {
+            let stmt = format!("{}.{};", self.convs[idx].var, call);
+            self.stmt(Some(idx), stmt);
+        }
+
+        true
+    }
+
+    fn udp(&mut self, ip: &Ip) -> bool {
+        let buf = ip.payload;
+        if buf.len() < 8 {
+            return false;
+        }
+
+        let len = u16::from_be_bytes([buf[4], buf[5]]) as usize;
+        if len < 8 || len > buf.len() {
+            return false;
+        }
+
+        let src = SocketAddrV4::new(ip.src, u16::from_be_bytes([buf[0], buf[1]]));
+        let dst = SocketAddrV4::new(ip.dst, u16::from_be_bytes([buf[2], buf[3]]));
+        let (idx, side) = self.conv(proto::UDP, src, dst, false);
+
+        let stmt = format!(
+            "{}.{}_dgram({});",
+            self.convs[idx].var,
+            side,
+            literals(&buf[8..len]).join(", ")
+        );
+        self.stmt(Some(idx), stmt);
+
+        true
+    }
+
+    /// Anything we don't understand goes out as it is
+    fn frame(&mut self, buf: &[u8], raw: bool) -> Result<(), Error> {
+        let zero = [0u8; 6];
+
+        let (dst, src, typ, payload) = if raw {
+            let typ = match buf.first().map(|b| b >> 4) {
+                Some(6) => ethertype::IPV6,
+                _ => ethertype::IPV4,
+            };
+            (&zero[..], &zero[..], typ, buf)
+        } else if buf.len() >= 14 {
+            (
+                &buf[0..6],
+                &buf[6..12],
+                u16::from_be_bytes([buf[12], buf[13]]),
+                &buf[14..],
+            )
+        } else {
+            return Err(RuntimeError.with_msg(format!(
+                "decompile: {} byte packet is too short for ethernet",
+                buf.len()
+            )));
+        };
+
+        let mut args = literals(src);
+        args.extend(literals(dst));
+        if typ != ethertype::IPV4 {
+            args.push(format!("ethertype: 0x{:04x}", typ));
+        }
+        args.extend(literals(payload));
+
+        self.imports.insert("eth");
+        self.stmt(None, format!("eth::frame({});", args.join(", ")));
+
+        Ok(())
+    }
+
+    /// Packets are raw IP if they came from a raw or null capture, otherwise ethernet
+    fn packet(&mut self, time: u64, pkt: &Packet) -> Result<(), Error> {
+        let buf = pkt.to_vec();
+        let raw = pkt.meta().raw;
+
+        let ip = if raw {
+            Ip::parse(&buf)
+        } else if buf.len() >= 14 && u16::from_be_bytes([buf[12], buf[13]]) == ethertype::IPV4 {
+            Ip::parse(&buf[14..])
+        } else {
+            None
+        };
+
+        let at = self.items.len();
+
+        let handled = match ip {
+            Some(ip) if ip.proto == proto::TCP => self.tcp(&ip),
+            Some(ip) if ip.proto == proto::UDP => self.udp(&ip),
+            _ => false,
+        };
+
+        if !handled {
+            self.frame(&buf, raw)?;
+        }
+
+        if self.items.len() > at {
+            self.close();
+            self.items.insert(at, Item::Gap(0));
+            self.group = Some(Group {
+                slot: at,
+                start: time,
+                bits: 0,
+                prev: self.last,
+            });
+        }
+
+        if let Some(group) = &mut self.group {
+            /* The program will build raw packets as ethernet frames, and time them as such */
+            group.bits += pkt.bit_time()
+                + if raw {
+                    8 * size_of::<eth_hdr>() as u64
+                } else {
+                    0
+                };
+        }
+        self.last = Some(time);
+
+        Ok(())
+    }
+
+    fn source(mut self) -> String {
+        self.close();
+
+        let mut out = String::new();
+
+        for import in self.imports.iter() {
+            out.push_str(&format!("import {};\n", import));
+        }
+
+        if let Some(linktype) = self.linktype.filter(|l| *l != LinkType::Ethernet) {
+            out.push_str(&format!("\nlinktype(\"{}\");\n", linktype));
+        }
+
+        for item in self.items.iter() {
+            match item {
+                Item::Gap(gap) if *gap >= JUMP_MIN => out.push_str(&jump(*gap)),
+                Item::Gap(_) => continue,
+                Item::Let(idx) => {
+                    out.push('\n');
+                    out.push_str(&self.convs[*idx].decl());
+                }
+                Item::Stmt(stmt) => out.push_str(stmt),
+            }
+            out.push('\n');
+        }
+
+        out
+    }
+}
+
+/// Reconstruct a program which produces the packets in a capture. TCP and UDP conversations
+/// become flows, with one statement for each message, gaps in the traffic become
+/// `time::jump_*()`, and anything else is written out as an ethernet frame.
+pub fn decompile<R: Read>(rd: PcapReader<R>) -> Result<String, Error> {
+    let mut dec = Decompiler::default();
+
+    for rec in rd {
+        let rec = rec?;
+        let linktype = LinkType::from_u32(rec.linktype).ok_or_else(|| {
+            RuntimeError.with_msg(format!("decompile: unsupported linktype {}", rec.linktype))
+        })?;
+
+        dec.linktype.get_or_insert(linktype);
+        dec.packet(rec.time, &linktype.normalize(rec.pkt)?)?;
+    }
+
+    format_source(&dec.source())
+}
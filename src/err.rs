@@ -1,5 +1,10 @@
+use crate::lint::Lint;
+use crate::loc::Span;
+use crate::val::ValType;
+
 use std::fmt;
 use std::io;
+use std::path::{Path, PathBuf};
 
 /// Error code for resynth program. Think of it as base exception type for the resynth language.
 #[allow(clippy::enum_variant_names)]
@@ -14,6 +19,130 @@ pub enum Error {
     TypeError,
     RuntimeError,
     MultipleAssignError(String),
+    OverflowError,
+    ZeroDivisionError,
+    IndexError,
+    CircularImportError(String),
+    /// An `assert()` in the program failed
+    AssertionError,
+    /// A name whose definition failed, this is never reported since the original error was
+    PoisonError(String),
+    /// A warning which was turned in to an error
+    LintError(Lint),
+    /// Any of the above, along with the details of what went wrong and where
+    Diag(Box<Diagnostic>),
+}
+
+/// Everything we know about an error, for reporting it to the user. These are built up as the
+/// error propagates, and the innermost, most specific, details always win.
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub err: Error,
+    pub msg: Option<String>,
+    pub span: Option<Span>,
+    /// The source file which the span refers to, if it's not the one being executed
+    pub file: Option<PathBuf>,
+    /// The name of the argument, for errors binding arguments to function calls
+    pub arg: Option<String>,
+    pub expected: Option<ValType>,
+    pub received: Option<ValType>,
+}
+
+impl Error {
+    /// The error code, without any of the details
+    pub fn kind(&self) -> &Error {
+        match self {
+            Self::Diag(diag) => &diag.err,
+            _ => self,
+        }
+    }
+
+    /// The name of the error code, as it would be spelled in Python
+    pub fn name(&self) -> &'static str {
+        use Error::*;
+
+        match self.kind() {
+            IoError(_) => "IOError",
+            LexError => "LexError",
+            ParseError => "ParseError",
+            MemoryError => "MemoryError",
+            ImportError(_) => "ImportError",
+            NameError => "NameError",
+            TypeError => "TypeError",
+            RuntimeError => "RuntimeError",
+            MultipleAssignError(_) => "MultipleAssignError",
+            OverflowError => "OverflowError",
+            ZeroDivisionError => "ZeroDivisionError",
+            IndexError => "IndexError",
+            CircularImportError(_) => "CircularImportError",
+            AssertionError => "AssertionError",
+            PoisonError(_) => "PoisonError",
+            LintError(_) => "LintError",
+            Diag(..) => unreachable!(),
+        }
+    }
+
+    pub fn diag(&self) -> Option<&Diagnostic> {
+        match self {
+            Self::Diag(diag) => Some(diag),
+            _ => None,
+        }
+    }
+
+    fn into_diag(self) -> Box<Diagnostic> {
+        match self {
+            Self::Diag(diag) => diag,
+            err => Box::new(Diagnostic {
+                err,
+                msg: None,
+                span: None,
+                file: None,
+                arg: None,
+                expected: None,
+                received: None,
+            }),
+        }
+    }
+
+    fn update<F: FnOnce(&mut Diagnostic)>(self, f: F) -> Self {
+        let mut diag = self.into_diag();
+        f(&mut diag);
+        Self::Diag(diag)
+    }
+
+    pub fn with_msg<T: Into<String>>(self, msg: T) -> Self {
+        self.update(|d| {
+            d.msg.get_or_insert_with(|| msg.into());
+        })
+    }
+
+    /// Locate the error within the source file, unless it's already been located more precisely
+    pub fn with_span<T: Into<Span>>(self, span: T) -> Self {
+        let span = span.into();
+        if span.is_nil() || self.diag().and_then(|d| d.span).is_some() {
+            return self;
+        }
+        self.update(|d| d.span = Some(span))
+    }
+
+    pub fn with_file(self, path: &Path) -> Self {
+        self.update(|d| {
+            d.file.get_or_insert_with(|| path.to_owned());
+        })
+    }
+
+    pub fn with_arg(self, name: &str) -> Self {
+        self.update(|d| {
+            d.arg.get_or_insert_with(|| name.to_owned());
+        })
+    }
+
+    pub fn with_types(self, expected: ValType, received: ValType) -> Self {
+        self.update(|d| {
+            d.expected.get_or_insert(expected);
+            d.received.get_or_insert(received);
+        })
+    }
 }
 
 impl From<io::Error> for Error {
@@ -27,7 +156,10 @@ impl PartialEq for Error {
     fn eq(&self, other: &Self) -> bool {
         use Error::*;
 
-        match self {
+        /* Errors are equal if their codes are, regardless of the details */
+        let other = other.kind();
+
+        match self.kind() {
             IoError(a) => {
                 if let IoError(b) = other {
                     a.kind() == b.kind()
@@ -55,6 +187,26 @@ impl PartialEq for Error {
                     false
                 }
             }
+            OverflowError => matches!(other, OverflowError),
+            ZeroDivisionError => matches!(other, ZeroDivisionError),
+            IndexError => matches!(other, IndexError),
+            CircularImportError(a) => {
+                if let CircularImportError(b) = other {
+                    a == b
+                } else {
+                    false
+                }
+            }
+            AssertionError => matches!(other, AssertionError),
+            PoisonError(a) => {
+                if let PoisonError(b) = other {
+                    a == b
+                } else {
+                    false
+                }
+            }
+            LintError(a) => matches!(other, LintError(b) if a == b),
+            Diag(..) => unreachable!(),
         }
     }
 }
@@ -73,6 +225,17 @@ impl fmt::Display for Error {
             TypeError => write!(fmt, "Type Error"),
             RuntimeError => write!(fmt, "Runtime Error"),
             MultipleAssignError(s) => write!(fmt, "Variable '{}' reassigned", s),
+            OverflowError => write!(fmt, "Integer Overflow"),
+            ZeroDivisionError => write!(fmt, "Division by Zero"),
+            IndexError => write!(fmt, "Index Out of Range"),
+            CircularImportError(s) => write!(fmt, "Import Error: Circular import of '{}'", s),
+            AssertionError => write!(fmt, "Assertion Failed"),
+            PoisonError(s) => write!(fmt, "'{}' is undefined due to an earlier error", s),
+            LintError(lint) => write!(fmt, "Lint Error [{}]", lint),
+            Diag(diag) => match &diag.msg {
+                Some(msg) => write!(fmt, "{}: {}", diag.err, msg),
+                None => diag.err.fmt(fmt),
+            },
         }
     }
 }
@@ -0,0 +1,453 @@
+use crate::err::Error;
+use crate::err::Error::{ParseError, RuntimeError};
+use crate::lex::{Lexer, TokType, Token, EOF};
+use crate::loc::Loc;
+use crate::parse::Parser;
+
+/// Argument lists and such are only broken over several lines if they'd be longer than this
+const MAX_WIDTH: usize = 100;
+const INDENT: &str = "  ";
+
+#[derive(Debug)]
+struct Tok {
+    typ: TokType,
+    text: String,
+    loc: Loc,
+}
+
+impl Tok {
+    fn new(tok: &Token) -> Self {
+        let text = match (tok.tok_type(), tok.optval()) {
+            (TokType::StringLiteral, Some(val)) => format!("\"{}\"", val),
+            (_, Some(val)) => val.into_owned(),
+            (typ, None) => typ.text().to_owned(),
+        };
+
+        Self {
+            typ: tok.tok_type(),
+            text,
+            loc: tok.loc(),
+        }
+    }
+
+    fn is(&self, typ: TokType) -> bool {
+        self.typ == typ
+    }
+
+    fn line(&self) -> usize {
+        self.loc.line()
+    }
+}
+
+/// A concrete syntax tree which is just detailed enough to lay the code out: the tokens, including
+/// comments, with anything in brackets grouped together.
+#[derive(Debug)]
+enum Tree {
+    Tok(Tok),
+    Group(Tok, Vec<Tree>, Tok),
+}
+
+impl Tree {
+    fn first_line(&self) -> usize {
+        match self {
+            Tree::Tok(tok) | Tree::Group(tok, _, _) => tok.line(),
+        }
+    }
+
+    fn last_line(&self) -> usize {
+        match self {
+            Tree::Tok(tok) | Tree::Group(_, _, tok) => tok.line(),
+        }
+    }
+
+    fn is(&self, typ: TokType) -> bool {
+        matches!(self, Tree::Tok(tok) if tok.is(typ))
+    }
+
+    fn comment(&self) -> Option<&Tok> {
+        match self {
+            Tree::Tok(tok) if tok.typ.is_comment() => Some(tok),
+            _ => None,
+        }
+    }
+
+    fn is_block(&self) -> bool {
+        matches!(self, Tree::Group(open, _, _) if open.is(TokType::LBrace))
+    }
+}
+
+fn closer(typ: TokType) -> Option<TokType> {
+    match typ {
+        TokType::LParen => Some(TokType::RParen),
+        TokType::LBracket => Some(TokType::RBracket),
+        TokType::LBrace => Some(TokType::RBrace),
+        _ => None,
+    }
+}
+
+/// Group the tokens up to the given closing bracket, which is returned along with them
+fn build<I>(toks: &mut I, close: Option<TokType>) -> Result<(Vec<Tree>, Option<Tok>), Error>
+where
+    I: Iterator<Item = Tok>,
+{
+    let mut items = Vec::new();
+
+    while let Some(tok) = toks.next() {
+        if Some(tok.typ) == close {
+            return Ok((items, Some(tok)));
+        }
+
+        if let Some(want) = closer(tok.typ) {
+            match build(toks, Some(want))? {
+                (children, Some(end)) => items.push(Tree::Group(tok, children, end)),
+                (_, None) => {
+                    return Err(ParseError
+                        .with_msg(format!("Unclosed `{}`", tok.text))
+                        .with_span(tok.loc))
+                }
+            }
+        } else if matches!(
+            tok.typ,
+            TokType::RParen | TokType::RBracket | TokType::RBrace
+        ) {
+            return Err(ParseError
+                .with_msg(format!("Unexpected `{}`", tok.text))
+                .with_span(tok.loc));
+        } else {
+            items.push(Tree::Tok(tok));
+        }
+    }
+
+    Ok((items, None))
+}
+
+/// Whether there's a space between two tokens
+fn spaced(prev: TokType, next: TokType) -> bool {
+    use TokType::*;
+
+    !matches!(
+        (prev, next),
+        (
+            _,
+            Comma | SemiColon | RParen | RBracket | Dot | DoubleColon | Colon | Slash
+        ) | (LParen | LBracket | Dot | DoubleColon | Slash, _)
+            | (Identifier | RParen | RBracket, LParen | LBracket)
+            | (LBrace, RBrace)
+    )
+}
+
+/// Whether a `-` or `*` after this token would be a binary operator, rather than a prefix
+fn ends_operand(typ: TokType) -> bool {
+    use TokType::*;
+
+    matches!(
+        typ,
+        Identifier
+            | BooleanLiteral
+            | IPv4Literal
+            | StringLiteral
+            | HexIntegerLiteral
+            | IntegerLiteral
+            | RParen
+            | RBracket
+    )
+}
+
+#[derive(Debug, Default)]
+struct Formatter {
+    lines: Vec<String>,
+    prev: Option<TokType>,
+    /// The next token follows on without a space, eg. after a unary minus or a splat
+    tight: bool,
+    /// The indent for the rest of a statement which carries on over several lines
+    cont: usize,
+    /// The source line of the last token which was written, a comment on the same line trails
+    /// after it.
+    src_line: usize,
+}
+
+impl Formatter {
+    fn line(&mut self, indent: usize) {
+        self.lines.push(INDENT.repeat(indent));
+        self.prev = None;
+        self.tight = false;
+    }
+
+    fn cur(&mut self) -> &mut String {
+        if self.lines.is_empty() {
+            self.lines.push(String::new());
+        }
+        self.lines.last_mut().unwrap()
+    }
+
+    fn col(&self) -> usize {
+        self.lines.last().map_or(0, String::len)
+    }
+
+    fn tok(&mut self, tok: &Tok) {
+        if let Some(prev) = self.prev {
+            if !self.tight && spaced(prev, tok.typ) {
+                self.cur().push(' ');
+            }
+        }
+
+        self.tight = match tok.typ {
+            TokType::Colon => self.prev == Some(TokType::IPv4Literal),
+            TokType::Minus | TokType::Star => !self.prev.is_some_and(ends_operand),
+            _ => false,
+        };
+
+        self.cur().push_str(&tok.text);
+        self.prev = Some(tok.typ);
+        self.src_line = tok.line();
+    }
+
+    fn comment(&mut self, tok: &Tok) {
+        if !self.cur().trim().is_empty() {
+            self.cur().push(' ');
+        }
+        self.cur().push_str(&tok.text);
+        self.src_line = tok.line();
+    }
+
+    /// Write a list of statements, one per line. At most one blank line is kept between them.
+    fn stmts(&mut self, items: &[Tree], indent: usize) {
+        let mut prev_end = None;
+        let mut start = 0;
+
+        for (idx, item) in items.iter().enumerate() {
+            if idx == start {
+                if let Some(tok) = item.comment() {
+                    if tok.line() != self.src_line || self.lines.is_empty() {
+                        self.gap(prev_end, tok.line());
+                        self.line(indent);
+                    }
+                    self.comment(tok);
+                    prev_end = Some(tok.line());
+                    start = idx + 1;
+                    continue;
+                }
+            }
+
+            let else_next = items[idx + 1..]
+                .iter()
+                .find(|item| item.comment().is_none())
+                .is_some_and(|item| item.is(TokType::ElseKeyword));
+
+            if item.is(TokType::SemiColon)
+                || (item.is_block() && !else_next)
+                || idx + 1 == items.len()
+            {
+                self.gap(prev_end, items[start].first_line());
+                self.line(indent);
+                self.cont = indent + 1;
+                self.inline(&items[start..=idx], indent);
+                prev_end = Some(item.last_line());
+                start = idx + 1;
+            }
+        }
+    }
+
+    fn gap(&mut self, prev_end: Option<usize>, next: usize) {
+        if prev_end.is_some_and(|end| next > end + 1) {
+            self.lines.push(String::new());
+        }
+    }
+
+    /// Write out part of a statement
+    fn inline(&mut self, items: &[Tree], indent: usize) {
+        for (idx, item) in items.iter().enumerate() {
+            match item {
+                Tree::Tok(tok) if tok.typ.is_comment() => {
+                    self.comment(tok);
+                    if idx + 1 < items.len() {
+                        self.line(indent + 1);
+                    }
+                }
+                Tree::Tok(tok) => {
+                    /* Concatenated string literals which were on separate lines stay that way */
+                    if tok.is(TokType::StringLiteral)
+                        && self.prev == Some(TokType::StringLiteral)
+                        && tok.line() != self.src_line
+                    {
+                        self.line(self.cont);
+                    }
+                    self.tok(tok);
+                }
+                Tree::Group(open, children, close) if open.is(TokType::LBrace) => {
+                    self.tok(open);
+                    if !children.is_empty() {
+                        self.stmts(children, indent + 1);
+                        self.line(indent);
+                    }
+                    self.tok(close);
+                }
+                Tree::Group(open, children, close) => self.group(open, children, close, indent),
+            }
+        }
+    }
+
+    /// Write the contents of some brackets on one line, unless they were already split up, or
+    /// they contain a comment, or don't fit. Otherwise it's one argument per line, and blank lines
+    /// between them are kept as for statements.
+    fn group(&mut self, open: &Tok, children: &[Tree], close: &Tok, indent: usize) {
+        /* Trailing commas are allowed in calls, function parameters and lists */
+        let commas = open.is(TokType::LBracket)
+            || matches!(
+                self.prev,
+                Some(TokType::Identifier | TokType::RParen | TokType::RBracket)
+            );
+
+        let cont = self.cont;
+        self.tok(open);
+
+        let split = children
+            .first()
+            .is_some_and(|item| item.first_line() != open.line());
+
+        if !split {
+            if let Some(flat) = flat(children) {
+                if self.col() + flat.len() + close.text.len() <= MAX_WIDTH {
+                    self.cur().push_str(&flat);
+                    self.tok(close);
+                    return;
+                }
+            }
+        }
+
+        let mut pending = false;
+        let mut skip_comma = false;
+
+        for (idx, item) in children.iter().enumerate() {
+            if let Some(tok) = item.comment() {
+                let arg_done = children[idx + 1..]
+                    .iter()
+                    .find(|item| item.comment().is_none())
+                    .is_none_or(|item| item.is(TokType::Comma));
+
+                if pending && arg_done {
+                    /* The comma goes before a comment which trails the argument */
+                    if commas || !skip_comma {
+                        self.cur().push(',');
+                    }
+                    pending = false;
+                    skip_comma = true;
+                } else if !pending && tok.line() != self.src_line {
+                    self.gap((idx > 0).then_some(self.src_line), tok.line());
+                    self.line(indent + 1);
+                }
+
+                self.comment(tok);
+
+                if pending {
+                    self.line(indent + 2);
+                }
+            } else if item.is(TokType::Comma) {
+                if !skip_comma {
+                    self.cur().push(',');
+                }
+                pending = false;
+                skip_comma = false;
+            } else {
+                if !pending {
+                    self.gap((idx > 0).then_some(self.src_line), item.first_line());
+                    self.line(indent + 1);
+                    pending = true;
+                    skip_comma = false;
+                }
+                /* Arguments which run over several lines line up with their first line */
+                self.cont = indent + 1;
+                self.inline(std::slice::from_ref(item), indent + 1);
+            }
+        }
+
+        if pending && commas {
+            self.cur().push(',');
+        }
+
+        self.cont = cont;
+        self.line(indent);
+        self.tok(close);
+    }
+}
+
+/// The contents of some brackets all on one line, if that's possible
+fn flat(children: &[Tree]) -> Option<String> {
+    if children
+        .iter()
+        .any(|item| item.comment().is_some() || item.is_block())
+    {
+        return None;
+    }
+
+    let items = match children.split_last() {
+        Some((last, items)) if last.is(TokType::Comma) => items,
+        _ => children,
+    };
+
+    let mut fmt = Formatter::default();
+    fmt.inline(items, 0);
+
+    match fmt.lines.len() {
+        0 => Some(String::new()),
+        1 => fmt.lines.pop(),
+        _ => None,
+    }
+}
+
+/// The tokens which the parser sees, for checking that formatting hasn't changed the meaning of
+/// the program. Trailing commas make no difference, so they're left out.
+fn parse(src: &str) -> Result<Vec<(TokType, Option<String>)>, Error> {
+    let mut lex = Lexer::default();
+    let mut parse = Parser::default();
+    let mut ret: Vec<(TokType, Option<String>)> = Vec::new();
+
+    for (lno, line) in src.lines().enumerate() {
+        for tok in lex.line(lno + 1, line)? {
+            parse.feed(&tok)?;
+
+            if matches!(tok.tok_type(), TokType::RParen | TokType::RBracket)
+                && ret.last().is_some_and(|(typ, _)| *typ == TokType::Comma)
+            {
+                ret.pop();
+            }
+            ret.push((tok.tok_type(), tok.optval().map(String::from)));
+        }
+    }
+
+    parse.feed(&EOF).map_err(|err| err.with_span(lex.loc()))?;
+
+    Ok(ret)
+}
+
+/// Lay out a source file in the canonical style. Comments are kept, and so are blank lines
+/// between statements, although only one in a row. The source has to parse.
+pub fn format_source(src: &str) -> Result<String, Error> {
+    let before = parse(src)?;
+
+    let mut lex = Lexer::with_trivia();
+    let mut toks = Vec::new();
+
+    for (lno, line) in src.lines().enumerate() {
+        toks.extend(lex.line(lno + 1, line)?.iter().map(Tok::new));
+    }
+
+    let (items, _) = build(&mut toks.into_iter(), None)?;
+
+    let mut fmt = Formatter::default();
+    fmt.stmts(&items, 0);
+
+    let mut out = String::new();
+    for line in fmt.lines {
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+
+    if parse(&out).ok() != Some(before) {
+        return Err(
+            RuntimeError.with_msg("Formatting would have changed the meaning of the program")
+        );
+    }
+
+    Ok(out)
+}
@@ -6,7 +6,7 @@ use regex::CaptureLocations;
 
 use crate::err::Error;
 use crate::err::Error::LexError;
-use crate::loc::Loc;
+use crate::loc::{Loc, Span};
 
 static LEX_RE: Lazy<Regex> = lazy_regex!(
     "^\
@@ -23,6 +23,14 @@ static LEX_RE: Lazy<Regex> = lazy_regex!(
     |\
     (?P<rparen>\\))\
     |\
+    (?P<lbrace>\\{)\
+    |\
+    (?P<rbrace>\\})\
+    |\
+    (?P<lbracket>\\[)\
+    |\
+    (?P<rbracket>\\])\
+    |\
     (?P<dot>\\.)\
     |\
     (?P<doublecolon>::)\
@@ -31,16 +39,60 @@ static LEX_RE: Lazy<Regex> = lazy_regex!(
     |\
     (?P<semicolon>;)\
     |\
-    (?P<equals>=)\
-    |\
     (?P<comma>,)\
     |\
     (?P<slash>/)\
     |\
+    (?P<eq_eq>==)\
+    |\
+    (?P<not_eq>!=)\
+    |\
+    (?P<shift_left><<)\
+    |\
+    (?P<shift_right>>>)\
+    |\
+    (?P<less_eq><=)\
+    |\
+    (?P<greater_eq>>=)\
+    |\
+    (?P<less><)\
+    |\
+    (?P<greater>>)\
+    |\
+    (?P<plus>\\+)\
+    |\
+    (?P<minus>-)\
+    |\
+    (?P<star>\\*)\
+    |\
+    (?P<percent>%)\
+    |\
+    (?P<ampersand>&)\
+    |\
+    (?P<pipe>\\|)\
+    |\
+    (?P<caret>\\^)\
+    |\
+    (?P<equals>=)\
+    |\
     (?P<import_keyword>\\bimport\\b)\
     |\
     (?P<let_keyword>\\blet\\b)\
     |\
+    (?P<fn_keyword>\\bfn\\b)\
+    |\
+    (?P<for_keyword>\\bfor\\b)\
+    |\
+    (?P<in_keyword>\\bin\\b)\
+    |\
+    (?P<if_keyword>\\bif\\b)\
+    |\
+    (?P<else_keyword>\\belse\\b)\
+    |\
+    (?P<as_keyword>\\bas\\b)\
+    |\
+    (?P<mut_keyword>\\bmut\\b)\
+    |\
     (?P<boolean_literal>\\b(?:true|false)\\b)\
     |\
     (?P<identifier>[a-zA-Z_][a-zA-Z0-9_]*)\
@@ -52,9 +104,9 @@ static LEX_RE: Lazy<Regex> = lazy_regex!(
     |\
     (?P<string_literal>\"(?:[^\"])*\")\
     |\
-    (?P<hex_integer_literal>0x[0-9a-fA-F][0-9a-fA-F]*)\
+    (?P<hex_integer_literal>0x[0-9a-fA-F][0-9a-fA-F]*(?:[ui](?:8|16|32|64)\\b)?)\
     |\
-    (?P<integer_literal>[-]?[0-9][0-9]*)\
+    (?P<integer_literal>[0-9][0-9]*(?:[ui](?:8|16|32|64)\\b)?)\
     )\
 "
 );
@@ -70,16 +122,42 @@ pub enum TokType {
 
     LParen,
     RParen,
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
     Dot,
     DoubleColon,
     Colon,
     SemiColon,
-    Equals,
     Comma,
     Slash,
+    EqEq,
+    NotEq,
+    ShiftLeft,
+    ShiftRight,
+    LessEq,
+    GreaterEq,
+    Less,
+    Greater,
+    Plus,
+    Minus,
+    Star,
+    Percent,
+    Ampersand,
+    Pipe,
+    Caret,
+    Equals,
 
     ImportKeyword,
     LetKeyword,
+    FnKeyword,
+    ForKeyword,
+    InKeyword,
+    IfKeyword,
+    ElseKeyword,
+    AsKeyword,
+    MutKeyword,
     BooleanLiteral,
     Identifier,
     IPv4Literal,
@@ -100,15 +178,41 @@ impl TokType {
             TokType::NewLine,
             TokType::LParen,
             TokType::RParen,
+            TokType::LBrace,
+            TokType::RBrace,
+            TokType::LBracket,
+            TokType::RBracket,
             TokType::Dot,
             TokType::DoubleColon,
             TokType::Colon,
             TokType::SemiColon,
-            TokType::Equals,
             TokType::Comma,
             TokType::Slash,
+            TokType::EqEq,
+            TokType::NotEq,
+            TokType::ShiftLeft,
+            TokType::ShiftRight,
+            TokType::LessEq,
+            TokType::GreaterEq,
+            TokType::Less,
+            TokType::Greater,
+            TokType::Plus,
+            TokType::Minus,
+            TokType::Star,
+            TokType::Percent,
+            TokType::Ampersand,
+            TokType::Pipe,
+            TokType::Caret,
+            TokType::Equals,
             TokType::ImportKeyword,
             TokType::LetKeyword,
+            TokType::FnKeyword,
+            TokType::ForKeyword,
+            TokType::InKeyword,
+            TokType::IfKeyword,
+            TokType::ElseKeyword,
+            TokType::AsKeyword,
+            TokType::MutKeyword,
             TokType::BooleanLiteral,
             TokType::Identifier,
             TokType::IPv4Literal,
@@ -135,6 +239,22 @@ impl TokType {
         None
     }
 
+    pub fn is_comment(self) -> bool {
+        matches!(self, TokType::HashComment | TokType::CppComment)
+    }
+
+    /// Whether the token can be turned in to a value with [Val::from_token](crate::Val::from_token)
+    pub fn is_literal(self) -> bool {
+        matches!(
+            self,
+            TokType::StringLiteral
+                | TokType::IPv4Literal
+                | TokType::IntegerLiteral
+                | TokType::BooleanLiteral
+                | TokType::HexIntegerLiteral
+        )
+    }
+
     pub fn ignore(self) -> bool {
         matches!(
             self,
@@ -142,6 +262,50 @@ impl TokType {
         )
     }
 
+    /// The text of a token which doesn't carry a value, for error messages
+    pub fn text(self) -> &'static str {
+        match self {
+            TokType::LParen => "(",
+            TokType::RParen => ")",
+            TokType::LBrace => "{",
+            TokType::RBrace => "}",
+            TokType::LBracket => "[",
+            TokType::RBracket => "]",
+            TokType::Dot => ".",
+            TokType::DoubleColon => "::",
+            TokType::Colon => ":",
+            TokType::SemiColon => ";",
+            TokType::Comma => ",",
+            TokType::Slash => "/",
+            TokType::EqEq => "==",
+            TokType::NotEq => "!=",
+            TokType::ShiftLeft => "<<",
+            TokType::ShiftRight => ">>",
+            TokType::LessEq => "<=",
+            TokType::GreaterEq => ">=",
+            TokType::Less => "<",
+            TokType::Greater => ">",
+            TokType::Plus => "+",
+            TokType::Minus => "-",
+            TokType::Star => "*",
+            TokType::Percent => "%",
+            TokType::Ampersand => "&",
+            TokType::Pipe => "|",
+            TokType::Caret => "^",
+            TokType::Equals => "=",
+            TokType::ImportKeyword => "import",
+            TokType::LetKeyword => "let",
+            TokType::FnKeyword => "fn",
+            TokType::ForKeyword => "for",
+            TokType::InKeyword => "in",
+            TokType::IfKeyword => "if",
+            TokType::ElseKeyword => "else",
+            TokType::AsKeyword => "as",
+            TokType::MutKeyword => "mut",
+            _ => "",
+        }
+    }
+
     pub fn get_val(self, val: &str) -> Option<&str> {
         match self {
             TokType::Identifier => Some(val),
@@ -149,6 +313,7 @@ impl TokType {
             TokType::IntegerLiteral => Some(val),
             TokType::BooleanLiteral => Some(val),
             TokType::StringLiteral => Some(&val[1..val.len() - 1]),
+            TokType::HashComment | TokType::CppComment => Some(val.trim_end()),
             TokType::IPv4Literal => Some(val),
             _ => None,
         }
@@ -163,6 +328,7 @@ impl TokType {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Token<'a> {
     pub(crate) loc: Loc,
+    pub(crate) end: Loc,
     pub(crate) typ: TokType,
     pub(crate) val: Option<Cow<'a, str>>,
 }
@@ -172,10 +338,25 @@ impl<'a> Token<'a> {
         self.loc
     }
 
+    pub fn span(&self) -> Span {
+        Span::new(self.loc, self.end)
+    }
+
     pub fn tok_type(&self) -> TokType {
         self.typ
     }
 
+    /// Describe the token for error messages
+    pub fn describe(&self) -> String {
+        match (self.typ, &self.val) {
+            (TokType::Eof, _) => "end of file".to_owned(),
+            (TokType::Identifier, Some(val)) => format!("identifier `{}`", val),
+            (TokType::StringLiteral, Some(val)) => format!("string \"{}\"", val),
+            (_, Some(val)) => format!("`{}`", val),
+            (typ, None) => format!("`{}`", typ.text()),
+        }
+    }
+
     pub fn optval(&self) -> Option<Cow<'a, str>> {
         self.val.to_owned()
     }
@@ -194,6 +375,7 @@ impl From<&Token<'_>> for String {
 /// EOF token
 pub const EOF: Token = Token {
     loc: Loc::nil(),
+    end: Loc::nil(),
     typ: TokType::Eof,
     val: None,
 };
@@ -204,23 +386,42 @@ pub const EOF: Token = Token {
 #[derive(Debug, Default)]
 pub struct Lexer {
     loc: Loc,
+    /// Keep comments, and string literals exactly as written, for
+    /// [formatting](crate::format_source)
+    trivia: bool,
     concatenated_strings: String,
+    /// Where the string literals which are being concatenated start and end
+    concatenated_span: Span,
 }
 
 impl Lexer {
+    /// A lexer which also returns comments, and doesn't concatenate adjacent string literals.
+    /// The tokens are no good for parsing, but they have everything needed to reproduce the
+    /// source.
+    pub fn with_trivia() -> Self {
+        Self {
+            trivia: true,
+            ..Default::default()
+        }
+    }
+
     pub fn loc(&self) -> Loc {
         self.loc
     }
 
-    fn throw(&mut self, pos: usize) -> Error {
+    fn throw(&mut self, pos: usize, line: &str) -> Error {
         self.loc.set_col(pos + 1);
+        let chr = line[pos..].chars().next().unwrap_or(' ');
         LexError
+            .with_msg(format!("Unexpected character {:?}", chr))
+            .with_span(Span::word(self.loc, 1))
     }
 
     pub fn line<'a>(&mut self, lno: usize, line: &'a str) -> Result<Vec<Token<'a>>, Error> {
         let mut ret = Vec::new();
         let mut pos = 0_usize;
         let mut string_literals: Vec<&str> = Vec::new();
+        let mut string_span = self.concatenated_span;
         let mut caps = LEX_RE.capture_locations();
 
         if !self.concatenated_strings.is_empty() {
@@ -235,13 +436,13 @@ impl Lexer {
             let m = match res {
                 Some(m) => m,
                 None => {
-                    return Err(self.throw(pos + 1));
+                    return Err(self.throw(pos, line));
                 }
             };
 
             let (tok_type, match_end) = match TokType::from_caps(&caps) {
                 Some(result) => result,
-                _ => return Err(self.throw(pos + 1)),
+                _ => return Err(self.throw(pos, line)),
             };
             let tok_val = &s[..m.end()];
 
@@ -258,13 +459,18 @@ impl Lexer {
             );
             */
 
-            if !tok_type.ignore() {
-                if matches!(tok_type, TokType::StringLiteral) {
+            if !tok_type.ignore() || (self.trivia && tok_type.is_comment()) {
+                if matches!(tok_type, TokType::StringLiteral) && !self.trivia {
+                    if string_literals.is_empty() {
+                        string_span.start = Loc::new(lno, pos + 1);
+                    }
+                    string_span.end = Loc::new(lno, pos + m.end() + 1);
                     string_literals.push(&tok_val[1..m.end() - 1]);
                 } else {
                     if !string_literals.is_empty() {
                         ret.push(Token {
-                            loc: Loc::new(lno, pos + 1),
+                            loc: string_span.start,
+                            end: string_span.end,
                             typ: TokType::StringLiteral,
                             val: Some(Cow::from(string_literals.concat())),
                         });
@@ -272,6 +478,7 @@ impl Lexer {
                     }
                     ret.push(Token {
                         loc: Loc::new(lno, pos + 1),
+                        end: Loc::new(lno, pos + m.end() + 1),
                         typ: tok_type,
                         val: tok_type.get_val(tok_val).map(Cow::from),
                     });
@@ -286,6 +493,7 @@ impl Lexer {
         } else {
             string_literals.concat()
         };
+        self.concatenated_span = string_span;
 
         self.loc = Loc::new(lno, pos + 1);
 
@@ -22,23 +22,31 @@
 //!    [statement](Stmt) is encountered, the [statement](Stmt) is pushed in to a
 //!    [results vector](Parser::get_results) which can later be [retreived](Parser::get_results)
 //! 3. [Program] maintains the execution state of any given program. It takes one statement at a
-//!    time, and updates the program state based on that. If the program has a [pkt::PcapWriter]
-//!    attached to it, then any generated packets will be written in to the corresponding pcap file
-//!    as they are generated.
+//!    time, and updates the program state based on that. If the program has a writer, such as a
+//!    [pkt::PcapWriter] or [pkt::PcapNgWriter], attached to it, then any generated packets will be
+//!    written in to the corresponding capture file as they are generated.
 
 #[macro_use]
 mod macros;
+mod analysis;
 mod args;
+mod check;
+mod decompile;
 mod err;
+mod format;
 mod lex;
 mod libapi;
+mod lint;
 mod loc;
 mod object;
+mod ops;
 mod parse;
 mod program;
 mod str;
 mod sym;
+mod trace;
 mod traits;
+mod userfunc;
 mod val;
 
 pub mod stdlib;
@@ -46,8 +54,15 @@ pub mod stdlib;
 #[cfg(test)]
 mod test;
 
-pub use err::Error;
-pub use lex::{Lexer, Token, EOF};
-pub use loc::Loc;
+pub use analysis::{Completion, CompletionKind, Document, SignatureHelp};
+pub use check::{check_source, Checker};
+pub use decompile::decompile;
+pub use err::{Diagnostic, Error};
+pub use format::format_source;
+pub use lex::{Lexer, TokType, Token, EOF};
+pub use lint::Lint;
+pub use loc::{Loc, Span};
 pub use parse::{Parser, Stmt};
 pub use program::Program;
+pub use trace::{Frame, Origin};
+pub use val::{Val, ValType};
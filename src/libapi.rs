@@ -244,14 +244,168 @@ pub trait Class {
     fn doc(&self) -> &'static str {
         self.def().doc
     }
+
+    /// Whether calling the method begins a connection which ought to be ended later on
+    fn opens(&self, _method: &FuncDef) -> bool {
+        false
+    }
+
+    /// Whether calling the method ends the connection
+    fn closes(&self, _method: &FuncDef) -> bool {
+        false
+    }
+}
+
+/// The args are split up generically so that the same rules can be applied to either values, or
+/// just their types when [type-checking](Signature::typecheck) a program without running it.
+struct ArgPrep<V> {
+    positional: Vec<V>,
+    named: HashMap<String, V>,
+    extra: Vec<V>,
+}
+
+fn split_args<S: Signature + ?Sized, V>(
+    sig: &S,
+    args: Vec<(Option<String>, V)>,
+) -> Result<ArgPrep<V>, Error> {
+    enum State {
+        Anon,
+        Optional,
+        CollectOnly,
+    }
+    let mut positional: Vec<V> = Vec::new();
+    let mut named: HashMap<String, V> = HashMap::new();
+    let mut extra: Vec<V> = Vec::new();
+    let mut state = State::Anon;
+
+    for (name, val) in args {
+        loop {
+            match state {
+                State::Anon => {
+                    if name.is_some() {
+                        state = State::Optional;
+                        continue;
+                    } else if sig.is_collect() && positional.len() >= sig.min_args() {
+                        // If we have collect args, then any optionals must be named
+                        state = State::CollectOnly;
+                        continue;
+                    } else if positional.len() >= sig.nr_args() {
+                        if sig.is_collect() {
+                            state = State::CollectOnly;
+                            continue;
+                        }
+                        return Err(TypeError.with_msg(format!(
+                            "{}: Too many arguments, expected at most {}",
+                            sig.name(),
+                            sig.nr_args()
+                        )));
+                    } else {
+                        positional.push(val);
+                        break;
+                    }
+                }
+                State::Optional => {
+                    let Some(name) = &name else {
+                        state = State::CollectOnly;
+                        continue;
+                    };
+
+                    let arg_pos = sig.arg_pos(name);
+
+                    if arg_pos.is_none() {
+                        return Err(TypeError
+                            .with_msg(format!("{}: No such argument: {:?}", sig.name(), name))
+                            .with_arg(name));
+                    }
+
+                    let arg_index = arg_pos.unwrap();
+
+                    // Invariant: No argument may be supplied with a value more than once
+
+                    // a) If the index of the arg is one of the positionals we've already got,
+                    // then a positional has been specified by position, and is now attempting
+                    // to be specified by name. So nope.
+                    if arg_index < positional.len() {
+                        return Err(TypeError
+                            .with_msg(format!(
+                                "{}: Positional argument {:?} multiply specified",
+                                sig.name(),
+                                name
+                            ))
+                            .with_arg(name));
+                    }
+
+                    // b) if we've named the same arg twice then that's also not allowed.
+                    if named.contains_key(name) {
+                        return Err(TypeError
+                            .with_msg(format!(
+                                "{}: Optional argument {:?} multiply specified",
+                                sig.name(),
+                                name
+                            ))
+                            .with_arg(name));
+                    }
+
+                    named.insert(name.clone(), val);
+                    break;
+                }
+                State::CollectOnly => {
+                    if !sig.is_collect() {
+                        return Err(TypeError.with_msg(format!(
+                            "{}: Too many arguments, expected at most {}",
+                            sig.name(),
+                            sig.nr_args()
+                        )));
+                    }
+                    if let Some(name) = &name {
+                        return Err(TypeError
+                            .with_msg(format!(
+                                "{}: Named argument {:?} must come before the collect-args",
+                                sig.name(),
+                                name
+                            ))
+                            .with_arg(name));
+                    }
+                    extra.push(val);
+                    break;
+                }
+            }
+        }
+    }
+
+    positional.shrink_to_fit();
+    named.shrink_to_fit();
+    extra.shrink_to_fit();
+
+    Ok(ArgPrep {
+        positional,
+        named,
+        extra,
+    })
 }
 
-struct ArgPrep {
-    positional: Vec<Val>,
-    named: HashMap<String, Val>,
-    extra: Vec<Val>,
+/// What's known about an argument to a call which hasn't been made yet
+#[derive(Debug, Clone)]
+pub struct ArgInfo {
+    /// The argument's type, which may be [ValType::Any] if it isn't known until runtime
+    pub typ: ValType,
+
+    /// The argument's value if it's a constant
+    pub val: Option<Val>,
+}
+
+/// Convert an integer or bool argument to the declared integer type, so long as it fits
+fn narrow(val: &mut Val, typ: ValType) -> Result<(), Error> {
+    if typ.is_int() && val.is_integral() {
+        *val = val.cast(typ)?;
+    }
+    Ok(())
 }
 
+/// Anything which can be called with a list of [argument specifiers](ArgSpec). This allows
+/// stdlib [functions](FuncDef) and [user-defined functions](crate::userfunc::UserFunc) to share
+/// the same argument binding rules.
+///
 /// Invariant: No argument may be supplied with a value more than once
 /// Invariant: No argument can be ambiguous as to where it belongs
 ///
@@ -262,147 +416,61 @@ struct ArgPrep {
 ///  COLLECT-NAME-OPTS if func has collect args, optionals MUST be named
 ///  NOCOLLECT-ANON-OPTS if func doesn't have collect args, optionals MAY be anonymous
 ///  COLLECT-AFTER-NAMED collect args must come after the last named arg
-impl FuncDef {
-    pub fn is_collect(&self) -> bool {
-        self.collect_type != ValType::Void
-    }
-
-    fn split_args(&self, args: Vec<ArgSpec>) -> Result<ArgPrep, Error> {
-        enum State {
-            Anon,
-            Optional,
-            CollectOnly,
-        }
-        let mut positional: Vec<Val> = Vec::new();
-        let mut named: HashMap<String, Val> = HashMap::new();
-        let mut extra: Vec<Val> = Vec::new();
-        let mut state = State::Anon;
-
-        for arg in args {
-            loop {
-                match state {
-                    State::Anon => {
-                        if !arg.is_anon() {
-                            state = State::Optional;
-                            continue;
-                        } else if self.is_collect() && positional.len() >= self.min_args {
-                            // If we have collect args, then any optionals must be named
-                            state = State::CollectOnly;
-                            continue;
-                        } else if positional.len() >= self.args.len() {
-                            if self.is_collect() {
-                                state = State::CollectOnly;
-                                continue;
-                            }
-                            println!(
-                                "ERR: {}: Too many arguments: {} > {}",
-                                self.name,
-                                positional.len(),
-                                self.args.len()
-                            );
-                            return Err(TypeError);
-                        } else {
-                            positional.push(arg.val);
-                            break;
-                        }
-                    }
-                    State::Optional => {
-                        if arg.is_anon() {
-                            state = State::CollectOnly;
-                            continue;
-                        }
+pub trait Signature {
+    fn name(&self) -> &str;
 
-                        let name = arg.name.unwrap();
+    /// Total number of positional and optional args
+    fn nr_args(&self) -> usize;
 
-                        let arg_pos = (self.arg_pos)(&name);
-
-                        if arg_pos.is_none() {
-                            println!("ERR: {}: No such argument: \"{}\"", self.name, &name);
-                            return Err(TypeError);
-                        }
+    /// minimum number of args: ie. number of positionals
+    fn min_args(&self) -> usize;
 
-                        let arg_index = arg_pos.unwrap();
+    fn arg_pos(&self, name: &str) -> Option<usize>;
 
-                        // Invariant: No argument may be supplied with a value more than once
+    fn arg_name(&self, idx: usize) -> &str;
 
-                        // a) If the index of the arg is one of the positionals we've already got,
-                        // then a positional has been specified by position, and is now attempting
-                        // to be specified by name. So nope.
-                        if arg_index < positional.len() {
-                            println!(
-                                "ERR: {}: Positional argument \"{}\" multiply specified",
-                                self.name, &name
-                            );
-                            return Err(TypeError);
-                        }
+    /// The default value of an argument, or [None] if the argument is positional
+    fn arg_default(&self, idx: usize) -> Option<Val>;
 
-                        // b) if we've named the same arg twice then that's also not allowed.
-                        if named.contains_key(&name) {
-                            println!(
-                                "ERR: {}: Optional argument \"{}\" multiply specified",
-                                self.name, &name
-                            );
-                            return Err(TypeError);
-                        }
+    fn arg_compatible(&self, idx: usize, typ: ValType) -> bool;
 
-                        named.insert(name, arg.val);
-                        break;
-                    }
-                    State::CollectOnly => {
-                        if !self.is_collect() {
-                            println!("ERR: {}: Unexpected collect-arguments", self.name);
-                            return Err(TypeError);
-                        }
-                        if arg.is_named() {
-                            println!(
-                                "ERR: {}: Unexpected named argument: \"{}\"",
-                                self.name,
-                                arg.name.unwrap()
-                            );
-                            return Err(TypeError);
-                        }
-                        extra.push(arg.val);
-                        break;
-                    }
-                }
-            }
-        }
+    /// The declared type of an argument, integer args are converted to this type when they're
+    /// bound
+    fn arg_type(&self, idx: usize) -> ValType;
 
-        positional.shrink_to_fit();
-        named.shrink_to_fit();
-        extra.shrink_to_fit();
+    fn collect_type(&self) -> ValType;
 
-        Ok(ArgPrep {
-            positional,
-            named,
-            extra,
-        })
+    fn is_collect(&self) -> bool {
+        self.collect_type() != ValType::Void
     }
 
-    pub fn argvec(&self, this: Option<ObjRef>, args: Vec<ArgSpec>) -> Result<ArgVec, Error> {
+    /// Match each arg up with its place in the signature, those which weren't specified are
+    /// [None] and should take their default values
+    fn bind<V>(&self, args: Vec<(Option<String>, V)>) -> Result<(Vec<Option<V>>, Vec<V>), Error> {
         let ArgPrep {
             positional,
             mut named,
             extra,
-        } = self.split_args(args)?;
+        } = split_args(self, args)?;
         let nr_positional = positional.len();
         let nr_named = named.len();
         let nr_specified = nr_positional + nr_named;
 
         // Now do some basic sanity checks to stup us shooting ourselves in the foot later
-        if nr_specified < self.min_args {
-            println!(
-                "ERR: {}: Not enough specified args: {} ({} + {}) < {}",
-                self.name, nr_specified, nr_positional, nr_named, self.min_args
-            );
-            return Err(TypeError);
+        if nr_specified < self.min_args() {
+            return Err(TypeError.with_msg(format!(
+                "{}: Not enough arguments, expected at least {} but got {}",
+                self.name(),
+                self.min_args(),
+                nr_specified,
+            )));
         }
 
-        let mut args: Vec<Val> = Vec::with_capacity(self.args.len());
+        let mut args: Vec<Option<V>> = Vec::with_capacity(self.nr_args());
 
         // 1. push anon vals to start with
         for a in positional {
-            args.push(a);
+            args.push(Some(a));
         }
 
         // either all positional and optional args are supplied and then everything else is in
@@ -410,52 +478,201 @@ impl FuncDef {
         // are in extra assert!(args.len() <= self.args.len());
 
         // 2. Take named positionals and optionals
-        for ArgDesc { name, typ } in self.args.iter().skip(nr_positional) {
-            if let Some(val) = named.remove(*name) {
+        for idx in nr_positional..self.nr_args() {
+            let name = self.arg_name(idx);
+            if let Some(val) = named.remove(name) {
                 // positional or optional specified by name, push it
-                args.push(val);
-            } else if let ArgDecl::Optional(dfl) = typ {
+                args.push(Some(val));
+            } else if idx >= self.min_args() {
                 // not specified, but we're optional, so take the default
-                args.push((*dfl).into());
+                args.push(None);
             } else {
                 // not specified, and we're mandatory, barf
-                println!(
-                    "ERR: {}: Positional argument \"{}\" not specified",
-                    self.name, name
-                );
-                return Err(TypeError);
+                return Err(TypeError
+                    .with_msg(format!(
+                        "{}: Positional argument {:?} not specified",
+                        self.name(),
+                        name
+                    ))
+                    .with_arg(name));
             }
         }
 
         assert!(named.is_empty());
 
+        Ok((args, extra))
+    }
+
+    fn wrong_type(&self, idx: usize, received: ValType) -> Error {
+        TypeError
+            .with_msg(format!(
+                "{}: Argument {:?} has the wrong type",
+                self.name(),
+                self.arg_name(idx)
+            ))
+            .with_arg(self.arg_name(idx))
+            .with_types(self.arg_type(idx), received)
+    }
+
+    fn collect_wrong_type(&self, received: ValType) -> Error {
+        TypeError
+            .with_msg(format!(
+                "{}: Collect argument has the wrong type",
+                self.name()
+            ))
+            .with_types(self.collect_type(), received)
+    }
+
+    /// Convert an integer argument to the declared type of its parameter, failing if it's out
+    /// of range
+    fn narrow_arg(&self, idx: usize, arg: &mut Val) -> Result<(), Error> {
+        let typ = self.arg_type(idx);
+
+        narrow(arg, typ).map_err(|err| {
+            err.with_msg(format!(
+                "{}: Argument {:?} out of range for {}: {}",
+                self.name(),
+                self.arg_name(idx),
+                typ,
+                arg.as_int().unwrap(),
+            ))
+            .with_arg(self.arg_name(idx))
+            .with_types(typ, arg.val_type())
+        })
+    }
+
+    fn narrow_collect(&self, arg: &mut Val) -> Result<(), Error> {
+        let typ = self.collect_type();
+
+        narrow(arg, typ).map_err(|err| {
+            err.with_msg(format!(
+                "{}: Collect argument out of range for {}: {}",
+                self.name(),
+                typ,
+                arg.as_int().unwrap(),
+            ))
+            .with_types(typ, arg.val_type())
+        })
+    }
+
+    /// Check the arguments to a call without making it. [ValType::Any] stands in for any args
+    /// whose types aren't known until runtime, constant args are range-checked too.
+    fn typecheck(&self, args: Vec<(Option<String>, ArgInfo)>) -> Result<(), Error> {
+        let (args, extra) = self.bind(args)?;
+
+        for (idx, arg) in args.into_iter().enumerate() {
+            let arg = match arg {
+                Some(arg) => arg,
+                None => continue,
+            };
+
+            if arg.typ != ValType::Any && !self.arg_compatible(idx, arg.typ) {
+                return Err(self.wrong_type(idx, arg.typ));
+            }
+
+            if let Some(mut val) = arg.val {
+                self.narrow_arg(idx, &mut val)?;
+            }
+        }
+
+        for arg in extra {
+            if arg.typ != ValType::Any && !self.collect_type().compatible_with(&arg.typ) {
+                return Err(self.collect_wrong_type(arg.typ));
+            }
+
+            if let Some(mut val) = arg.val {
+                self.narrow_collect(&mut val)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn argvec(&self, this: Option<ObjRef>, args: Vec<ArgSpec>) -> Result<ArgVec, Error> {
+        let args = args.into_iter().map(|arg| (arg.name, arg.val)).collect();
+        let (args, mut extra) = self.bind(args)?;
+
+        let mut args: Vec<Val> = args
+            .into_iter()
+            .enumerate()
+            .map(|(idx, val)| val.unwrap_or_else(|| self.arg_default(idx).unwrap()))
+            .collect();
+
         // 3. Final type-check of all positional args
-        for (ArgDesc { name, typ }, arg) in self.args.iter().zip(args.iter()) {
-            if !match typ {
-                ArgDecl::Positional(typ) => typ.compatible_with(arg),
-                ArgDecl::Optional(dfl) => dfl.arg_compatible(arg),
-            } {
-                println!(
-                    "ERR: {}: Argument type-check failed for {:?}",
-                    self.name, name
-                );
-                return Err(TypeError);
+        for (idx, arg) in args.iter_mut().enumerate() {
+            if !self.arg_compatible(idx, arg.val_type()) {
+                return Err(self.wrong_type(idx, arg.val_type()));
             }
+
+            self.narrow_arg(idx, arg)?;
         }
 
         // 4. Type-check the collect-args
-        if extra.iter().any(|x| !self.collect_type.compatible_with(x)) {
-            println!("ERR: {}: Collect argument of wrong type", self.name);
-            return Err(TypeError);
+        for arg in extra.iter_mut() {
+            if !self.collect_type().compatible_with(arg) {
+                return Err(self.collect_wrong_type(arg.val_type()));
+            }
+
+            self.narrow_collect(arg)?;
         }
 
         Ok(ArgVec::new(this, args, extra))
     }
 
-    pub fn args(&self, this: Option<ObjRef>, args: Vec<ArgSpec>) -> Result<Args, Error> {
+    fn args(&self, this: Option<ObjRef>, args: Vec<ArgSpec>) -> Result<Args, Error> {
         Ok(self.argvec(this, args)?.into())
     }
+}
+
+impl Signature for FuncDef {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn nr_args(&self) -> usize {
+        self.args.len()
+    }
+
+    fn min_args(&self) -> usize {
+        self.min_args
+    }
+
+    fn arg_pos(&self, name: &str) -> Option<usize> {
+        (self.arg_pos)(name)
+    }
 
+    fn arg_name(&self, idx: usize) -> &str {
+        self.args[idx].name
+    }
+
+    fn arg_default(&self, idx: usize) -> Option<Val> {
+        match self.args[idx].typ {
+            ArgDecl::Positional(_) => None,
+            ArgDecl::Optional(dfl) => Some(dfl.into()),
+        }
+    }
+
+    fn arg_compatible(&self, idx: usize, typ: ValType) -> bool {
+        match self.args[idx].typ {
+            ArgDecl::Positional(decl) => decl.compatible_with(&typ),
+            ArgDecl::Optional(dfl) => dfl.arg_compatible(&typ),
+        }
+    }
+
+    fn arg_type(&self, idx: usize) -> ValType {
+        match self.args[idx].typ {
+            ArgDecl::Positional(typ) => typ,
+            ArgDecl::Optional(ValDef::Type(typ)) => typ,
+            ArgDecl::Optional(dfl) => dfl.val_type(),
+        }
+    }
+
+    fn collect_type(&self) -> ValType {
+        self.collect_type
+    }
+}
+
+impl FuncDef {
     pub fn write_docs<W: Write>(&self, wr: &mut W) -> Result<(), std::io::Error> {
         wr.write_all(format!("\n## {}\n", self.name).as_bytes())?;
         wr.write_all(b"```resynth\n")?;
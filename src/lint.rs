@@ -0,0 +1,51 @@
+use std::fmt;
+
+/// The kinds of warning which a [Program](crate::Program) can report while it runs. Each one has
+/// a name by which it can be allowed, or turned in to an error.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Lint {
+    /// The value of an expression statement was neither a packet nor nil
+    DiscardedValue,
+    /// A `let` binding was never referenced, names which start with `_` are exempt
+    UnusedVariable,
+    /// A module was imported but never referenced
+    UnusedImport,
+    /// A flow was opened but never closed or reset
+    UnclosedFlow,
+    /// A packet was bound with `let` but never emitted
+    UnusedPacket,
+    /// A value was defined on the command line, but the program never referred to it
+    UnusedDefine,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 6] = [
+        Lint::DiscardedValue,
+        Lint::UnusedVariable,
+        Lint::UnusedImport,
+        Lint::UnclosedFlow,
+        Lint::UnusedPacket,
+        Lint::UnusedDefine,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::DiscardedValue => "discarded_value",
+            Lint::UnusedVariable => "unused_variable",
+            Lint::UnusedImport => "unused_import",
+            Lint::UnclosedFlow => "unclosed_flow",
+            Lint::UnusedPacket => "unused_packet",
+            Lint::UnusedDefine => "unused_define",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|lint| lint.name() == name)
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str(self.name())
+    }
+}
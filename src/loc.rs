@@ -1,5 +1,5 @@
 /// Identifies a line number and column number within a source file.
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
 pub struct Loc {
     line: u32,
     col: u32,
@@ -37,3 +37,31 @@ impl Loc {
         self.col as usize
     }
 }
+
+/// A range of source code, from `start` up to but not including `end`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: Loc,
+    pub end: Loc,
+}
+
+impl Span {
+    pub const fn new(start: Loc, end: Loc) -> Self {
+        Self { start, end }
+    }
+
+    /// A span covering a single word which begins at `start`
+    pub const fn word(start: Loc, len: usize) -> Self {
+        Self::new(start, Loc::new(start.line(), start.col() + len))
+    }
+
+    pub fn is_nil(&self) -> bool {
+        self.start.is_nil()
+    }
+}
+
+impl From<Loc> for Span {
+    fn from(loc: Loc) -> Self {
+        Self::new(loc, loc)
+    }
+}
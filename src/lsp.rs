@@ -0,0 +1,364 @@
+//! A language server for resynth, which speaks LSP over stdio. Diagnostics are the same as
+//! those from `resynth --check`, and everything else comes from the stdlib symbol tables.
+
+use resynth::{format_source, CompletionKind, Document, Loc, Span};
+
+use std::collections::HashMap;
+use std::error::Error;
+
+use lsp_server::{Connection, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+    Notification as NotificationTrait, PublishDiagnostics,
+};
+use lsp_types::request::{
+    Completion, Formatting, GotoDefinition, HoverRequest, Request as RequestTrait,
+    SignatureHelpRequest,
+};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    Diagnostic, DiagnosticSeverity, DocumentFormattingParams, Documentation, GotoDefinitionParams,
+    GotoDefinitionResponse, Hover, HoverContents, HoverParams, HoverProviderCapability, Location,
+    MarkupContent, MarkupKind, NumberOrString, OneOf, ParameterInformation, ParameterLabel,
+    Position, PublishDiagnosticsParams, Range, ServerCapabilities, SignatureHelp,
+    SignatureHelpOptions, SignatureHelpParams, SignatureInformation, TextDocumentPositionParams,
+    TextDocumentSyncCapability, TextDocumentSyncKind, TextEdit, Url,
+};
+
+type Result<T> = std::result::Result<T, Box<dyn Error + Sync + Send>>;
+
+/// An open file, along with what's been worked out about it
+struct Source {
+    text: String,
+    doc: Document,
+}
+
+impl Source {
+    /// Imports are relative to the file, if the document is one
+    fn new(text: String, uri: &Url) -> Self {
+        let path = uri.to_file_path().ok();
+        let doc = Document::new(&text, path.as_deref());
+        Self { text, doc }
+    }
+
+    fn line(&self, lno: usize) -> &str {
+        self.text.lines().nth(lno).unwrap_or_default()
+    }
+
+    /// LSP positions are zero-based and count UTF-16 code units, whereas [Loc] is one-based and
+    /// counts bytes.
+    fn loc(&self, pos: Position) -> Loc {
+        let line = self.line(pos.line as usize);
+        let mut units = 0;
+        let mut byte = line.len();
+
+        for (idx, chr) in line.char_indices() {
+            if units >= pos.character as usize {
+                byte = idx;
+                break;
+            }
+            units += chr.len_utf16();
+        }
+
+        Loc::new(pos.line as usize + 1, byte + 1)
+    }
+
+    fn position(&self, loc: Loc) -> Position {
+        if loc.is_nil() {
+            return Position::default();
+        }
+
+        let line = self.line(loc.line() - 1);
+        let mut byte = (loc.col() - 1).min(line.len());
+        while !line.is_char_boundary(byte) {
+            byte -= 1;
+        }
+
+        Position::new(
+            (loc.line() - 1) as u32,
+            line[..byte].encode_utf16().count() as u32,
+        )
+    }
+
+    fn range(&self, span: Span) -> Range {
+        Range::new(self.position(span.start), self.position(span.end))
+    }
+
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.doc
+            .diagnostics()
+            .iter()
+            .map(|err| {
+                let diag = err.diag();
+
+                /* Errors in imported files can only be pinned on the top of this one */
+                let span = match diag {
+                    Some(d) if d.file.is_none() => d.span.unwrap_or_default(),
+                    _ => Span::default(),
+                };
+
+                let mut message = err.to_string();
+                if let Some(file) = diag.and_then(|d| d.file.as_ref()) {
+                    message = format!("{}: {}", file.display(), message);
+                }
+
+                Diagnostic {
+                    range: self.range(span),
+                    severity: Some(DiagnosticSeverity::ERROR),
+                    code: Some(NumberOrString::String(err.name().to_owned())),
+                    source: Some("resynth".to_owned()),
+                    message,
+                    ..Default::default()
+                }
+            })
+            .collect()
+    }
+
+    fn hover(&self, pos: Position) -> Option<Hover> {
+        let (span, doc) = self.doc.hover(self.loc(pos))?;
+
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: doc,
+            }),
+            range: Some(self.range(span)),
+        })
+    }
+
+    fn complete(&self, pos: Position) -> CompletionResponse {
+        let items = self
+            .doc
+            .complete(self.loc(pos))
+            .into_iter()
+            .map(|comp| CompletionItem {
+                label: comp.label,
+                kind: Some(match comp.kind {
+                    CompletionKind::Module => CompletionItemKind::MODULE,
+                    CompletionKind::Class => CompletionItemKind::CLASS,
+                    CompletionKind::Function => CompletionItemKind::FUNCTION,
+                    CompletionKind::Method => CompletionItemKind::METHOD,
+                    CompletionKind::Constant => CompletionItemKind::CONSTANT,
+                    CompletionKind::Variable => CompletionItemKind::VARIABLE,
+                }),
+                detail: comp.detail,
+                ..Default::default()
+            })
+            .collect();
+
+        CompletionResponse::Array(items)
+    }
+
+    fn signature(&self, pos: Position) -> Option<SignatureHelp> {
+        let sig = self.doc.signature(self.loc(pos))?;
+
+        /* Each parameter is identified by where it is in the label */
+        let mut off = sig.label.find('(').unwrap_or_default();
+        let params = sig
+            .params
+            .iter()
+            .map(|param| {
+                let start = off + sig.label[off..].find(param.as_str()).unwrap_or_default();
+                off = start + param.len();
+                ParameterInformation {
+                    label: ParameterLabel::LabelOffsets([start as u32, off as u32]),
+                    documentation: None,
+                }
+            })
+            .collect();
+
+        Some(SignatureHelp {
+            signatures: vec![SignatureInformation {
+                label: sig.label,
+                documentation: Some(Documentation::MarkupContent(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: sig.doc,
+                })),
+                parameters: Some(params),
+                active_parameter: Some(sig.active as u32),
+            }],
+            active_signature: Some(0),
+            active_parameter: Some(sig.active as u32),
+        })
+    }
+
+    /// The whole file, laid out as `resynth fmt` would. Nothing is changed if it doesn't parse.
+    fn format(&self) -> Option<Vec<TextEdit>> {
+        let text = format_source(&self.text).ok()?;
+        if text == self.text {
+            return Some(Vec::new());
+        }
+
+        let end = Position::new(self.text.lines().count() as u32 + 1, 0);
+        Some(vec![TextEdit::new(
+            Range::new(Position::default(), end),
+            text,
+        )])
+    }
+}
+
+struct Server {
+    conn: Connection,
+    sources: HashMap<Url, Source>,
+}
+
+impl Server {
+    fn source(&self, pos: &TextDocumentPositionParams) -> Option<&Source> {
+        self.sources.get(&pos.text_document.uri)
+    }
+
+    fn publish(&self, uri: Url, diagnostics: Vec<Diagnostic>) -> Result<()> {
+        let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+        let not = Notification::new(PublishDiagnostics::METHOD.to_owned(), params);
+        self.conn.sender.send(Message::Notification(not))?;
+        Ok(())
+    }
+
+    fn update(&mut self, uri: Url, text: String) -> Result<()> {
+        let src = Source::new(text, &uri);
+        let diagnostics = src.diagnostics();
+        self.sources.insert(uri.clone(), src);
+        self.publish(uri, diagnostics)
+    }
+
+    fn notification(&mut self, not: Notification) -> Result<()> {
+        match not.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: <DidOpenTextDocument as NotificationTrait>::Params =
+                    serde_json::from_value(not.params)?;
+                self.update(params.text_document.uri, params.text_document.text)
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: <DidChangeTextDocument as NotificationTrait>::Params =
+                    serde_json::from_value(not.params)?;
+                /* Only full syncs are asked for, so the last change has the whole text */
+                match params.content_changes.into_iter().last() {
+                    Some(change) => self.update(params.text_document.uri, change.text),
+                    None => Ok(()),
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: <DidCloseTextDocument as NotificationTrait>::Params =
+                    serde_json::from_value(not.params)?;
+                self.sources.remove(&params.text_document.uri);
+                self.publish(params.text_document.uri, Vec::new())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn request(&mut self, req: Request) -> Result<()> {
+        let id: RequestId = req.id.clone();
+
+        let result = match req.method.as_str() {
+            HoverRequest::METHOD => {
+                let params: HoverParams = serde_json::from_value(req.params)?;
+                let pos = params.text_document_position_params;
+                let hover = self.source(&pos).and_then(|src| src.hover(pos.position));
+                serde_json::to_value(hover)?
+            }
+            Completion::METHOD => {
+                let params: CompletionParams = serde_json::from_value(req.params)?;
+                let pos = params.text_document_position;
+                let items = self.source(&pos).map(|src| src.complete(pos.position));
+                serde_json::to_value(items)?
+            }
+            SignatureHelpRequest::METHOD => {
+                let params: SignatureHelpParams = serde_json::from_value(req.params)?;
+                let pos = params.text_document_position_params;
+                let sig = self
+                    .source(&pos)
+                    .and_then(|src| src.signature(pos.position));
+                serde_json::to_value(sig)?
+            }
+            GotoDefinition::METHOD => {
+                let params: GotoDefinitionParams = serde_json::from_value(req.params)?;
+                let pos = params.text_document_position_params;
+                let def = self.source(&pos).and_then(|src| {
+                    let span = src.doc.definition(src.loc(pos.position))?;
+                    Some(GotoDefinitionResponse::Scalar(Location::new(
+                        pos.text_document.uri.clone(),
+                        src.range(span),
+                    )))
+                });
+                serde_json::to_value(def)?
+            }
+            Formatting::METHOD => {
+                let params: DocumentFormattingParams = serde_json::from_value(req.params)?;
+                let edits = self
+                    .sources
+                    .get(&params.text_document.uri)
+                    .and_then(Source::format);
+                serde_json::to_value(edits)?
+            }
+            method => {
+                let resp = Response::new_err(
+                    id,
+                    lsp_server::ErrorCode::MethodNotFound as i32,
+                    format!("unhandled method: {}", method),
+                );
+                self.conn.sender.send(Message::Response(resp))?;
+                return Ok(());
+            }
+        };
+
+        let resp = Response {
+            id,
+            result: Some(result),
+            error: None,
+        };
+        self.conn.sender.send(Message::Response(resp))?;
+        Ok(())
+    }
+
+    fn run(&mut self) -> Result<()> {
+        while let Ok(msg) = self.conn.receiver.recv() {
+            match msg {
+                Message::Request(req) => {
+                    if self.conn.handle_shutdown(&req)? {
+                        return Ok(());
+                    }
+                    self.request(req)?;
+                }
+                Message::Notification(not) => self.notification(not)?,
+                Message::Response(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn main() -> Result<()> {
+    let (conn, io_threads) = Connection::stdio();
+
+    let caps = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec![":".to_owned(), ".".to_owned()]),
+            ..Default::default()
+        }),
+        signature_help_provider: Some(SignatureHelpOptions {
+            trigger_characters: Some(vec!["(".to_owned(), ",".to_owned()]),
+            ..Default::default()
+        }),
+        definition_provider: Some(OneOf::Left(true)),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    };
+
+    conn.initialize(serde_json::to_value(caps)?)?;
+
+    let mut server = Server {
+        conn,
+        sources: HashMap::new(),
+    };
+    server.run()?;
+
+    /* The connection has to be dropped for the writer thread to finish */
+    drop(server);
+    io_threads.join()?;
+
+    Ok(())
+}
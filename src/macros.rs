@@ -176,55 +176,51 @@ macro_rules! class {
 
 #[macro_export]
 macro_rules! ok {
-    ($st:expr, $fmt:expr $(, $($arg:expr),*)*) => {{
+    ($st:expr, $($arg:tt)*) => {{
         $st.set_color(ColorSpec::new()
             .set_fg(Some(Color::Green))
             .set_bold(true)
             .set_intense(true)
         ).unwrap();
-        let ret = print!($fmt, $($arg),*);
+        write!($st, $($arg)*).unwrap();
         $st.set_color(&ColorSpec::default()).unwrap();
-        ret
     }
 }}
 
 #[macro_export]
 macro_rules! warn {
-    ($st:expr, $fmt:expr $(, $($arg:expr),*)*) => {{
+    ($st:expr, $($arg:tt)*) => {{
         $st.set_color(ColorSpec::new()
             .set_fg(Some(Color::Yellow))
             .set_bold(true)
             .set_intense(true)
         ).unwrap();
-        let ret = print!($fmt, $($arg),*);
+        write!($st, $($arg)*).unwrap();
         $st.set_color(&ColorSpec::default()).unwrap();
-        ret
     }
 }}
 
 #[macro_export]
 macro_rules! error {
-    ($st:expr, $fmt:expr $(, $($arg:expr),*)*) => {{
+    ($st:expr, $($arg:tt)*) => {{
         $st.set_color(ColorSpec::new()
             .set_fg(Some(Color::Red))
             .set_bold(true)
             .set_intense(true)
         ).unwrap();
-        let ret = print!($fmt, $($arg),*);
+        write!($st, $($arg)*).unwrap();
         $st.set_color(&ColorSpec::default()).unwrap();
-        ret
     }
 }}
 
 #[macro_export]
 macro_rules! notice {
-    ($st:expr, $fmt:expr $(, $($arg:expr),*)*) => {{
+    ($st:expr, $($arg:tt)*) => {{
         $st.set_color(ColorSpec::new()
             .set_bold(true)
             .set_intense(true)
         ).unwrap();
-        let ret = print!($fmt, $($arg),*);
+        write!($st, $($arg)*).unwrap();
         $st.set_color(&ColorSpec::default()).unwrap();
-        ret
     }}
 }
@@ -67,11 +67,16 @@ impl Dispatchable for ObjRef {
 }
 
 impl ObjRef {
-    pub fn borrow(&self) -> Ref<dyn Obj> {
+    pub fn borrow(&self) -> Ref<'_, dyn Obj> {
         self.inner.borrow()
     }
 
-    pub fn borrow_mut(&self) -> RefMut<dyn Obj> {
+    pub fn borrow_mut(&self) -> RefMut<'_, dyn Obj> {
         self.inner.borrow_mut()
     }
+
+    /// Whether both refer to the very same object, rather than just equal ones
+    pub fn ptr_eq(&self, other: &ObjRef) -> bool {
+        Rc::ptr_eq(&self.inner, &other.inner)
+    }
 }
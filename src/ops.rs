@@ -0,0 +1,294 @@
+use crate::err::Error;
+use crate::err::Error::{IndexError, OverflowError, TypeError, ZeroDivisionError};
+use crate::lex::TokType;
+use crate::val::{Typed, Val, ValType};
+
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter};
+use std::net::{Ipv4Addr, SocketAddrV4};
+
+/// Binary operators
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BinOp {
+    /// Integer division, or building a [socket address](SocketAddrV4) from an IP and a port
+    Slash,
+    Mul,
+    Rem,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    BitAnd,
+    BitXor,
+    BitOr,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Display for BinOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        use BinOp::*;
+        let s = match self {
+            Slash => "/",
+            Mul => "*",
+            Rem => "%",
+            Add => "+",
+            Sub => "-",
+            Shl => "<<",
+            Shr => ">>",
+            BitAnd => "&",
+            BitXor => "^",
+            BitOr => "|",
+            Eq => "==",
+            Ne => "!=",
+            Lt => "<",
+            Le => "<=",
+            Gt => ">",
+            Ge => ">=",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Integers of mixed types are promoted to the widest of the two, and if either is signed, then
+/// so is the result
+fn promote(a: ValType, b: ValType) -> ValType {
+    let bits = a.int_bits().max(b.int_bits()).unwrap();
+    ValType::int(a.is_signed() || b.is_signed(), bits).unwrap()
+}
+
+impl BinOp {
+    pub fn from_tok(typ: TokType) -> Option<Self> {
+        use BinOp::*;
+        Some(match typ {
+            TokType::Slash => Slash,
+            TokType::Star => Mul,
+            TokType::Percent => Rem,
+            TokType::Plus => Add,
+            TokType::Minus => Sub,
+            TokType::ShiftLeft => Shl,
+            TokType::ShiftRight => Shr,
+            TokType::Ampersand => BitAnd,
+            TokType::Caret => BitXor,
+            TokType::Pipe => BitOr,
+            TokType::EqEq => Eq,
+            TokType::NotEq => Ne,
+            TokType::Less => Lt,
+            TokType::LessEq => Le,
+            TokType::Greater => Gt,
+            TokType::GreaterEq => Ge,
+            _ => return None,
+        })
+    }
+
+    /// Operators with a higher precedence bind more tightly. All operators are left-associative.
+    pub fn precedence(self) -> u8 {
+        use BinOp::*;
+        match self {
+            Slash | Mul | Rem => 10,
+            Add | Sub => 9,
+            Shl | Shr => 8,
+            BitAnd => 7,
+            BitXor => 6,
+            BitOr => 5,
+            Eq | Ne | Lt | Le | Gt | Ge => 4,
+        }
+    }
+
+    fn is_comparison(self) -> bool {
+        use BinOp::*;
+        matches!(self, Eq | Ne | Lt | Le | Gt | Ge)
+    }
+
+    fn type_error(self, a: ValType, b: ValType) -> Error {
+        TypeError.with_msg(format!(
+            "Unsupported operand types for {}: {} and {}",
+            self, a, b
+        ))
+    }
+
+    /// The type of the result of [eval](Self::eval), or the same [TypeError] if the operand types
+    /// aren't supported. This is for type-checking without running the program, so either type
+    /// may be [ValType::Any] if it's not known until runtime.
+    pub fn result_type(self, a: ValType, b: ValType) -> Result<ValType, Error> {
+        use BinOp::*;
+
+        if a.is_int() && b.is_int() {
+            return Ok(match self {
+                _ if self.is_comparison() => ValType::Bool,
+                Shl | Shr => a,
+                _ => promote(a, b),
+            });
+        }
+
+        match (a, b) {
+            (ValType::Any, _) | (_, ValType::Any) => Some(ValType::Any),
+            (ValType::Bool, ValType::Bool) if matches!(self, BitAnd | BitXor | BitOr) => {
+                Some(ValType::Bool)
+            }
+            (ValType::Ip4, _) if matches!(self, Add | Sub) && b.is_int() => Some(ValType::Ip4),
+            (_, ValType::Ip4) if matches!(self, Add) && a.is_int() => Some(ValType::Ip4),
+            (ValType::Ip4, _) if matches!(self, Slash) && b.is_int() => Some(ValType::Sock4),
+            (ValType::Ip4, ValType::Ip4) if self.is_comparison() => Some(ValType::Bool),
+            _ if matches!(self, Eq | Ne) => Some(ValType::Bool),
+            _ => None,
+        }
+        .ok_or_else(|| self.type_error(a, b))
+    }
+
+    fn compare<T: PartialOrd>(self, a: T, b: T) -> Val {
+        use BinOp::*;
+        Val::Bool(match self {
+            Eq => a == b,
+            Ne => a != b,
+            Lt => a < b,
+            Le => a <= b,
+            Gt => a > b,
+            Ge => a >= b,
+            _ => unreachable!(),
+        })
+    }
+
+    /// Integers of different types can be mixed, see [promote] for the type of the result. Any
+    /// result which doesn't fit in to that type is an [OverflowError].
+    fn int_op(self, typ: ValType, a: i128, b: i128) -> Result<Val, Error> {
+        use BinOp::*;
+
+        if self.is_comparison() {
+            return Ok(self.compare(a, b));
+        }
+
+        let ret = match self {
+            Mul => a.checked_mul(b),
+            Slash | Rem if b == 0 => return Err(ZeroDivisionError),
+            Slash => a.checked_div(b),
+            Rem => a.checked_rem(b),
+            Add => a.checked_add(b),
+            Sub => a.checked_sub(b),
+            Shl | Shr if !(0..typ.int_bits().unwrap() as i128).contains(&b) => None,
+            Shl => a.checked_mul(1 << b),
+            Shr => Some(a >> b),
+            BitAnd => Some(a & b),
+            BitXor => Some(a ^ b),
+            BitOr => Some(a | b),
+            _ => unreachable!(),
+        };
+
+        ret.map_or(Err(OverflowError), |val| Val::from_int(typ, val))
+            .map_err(|err| err.with_msg(format!("{} {} {} overflows {}", a, self, b, typ)))
+    }
+
+    fn bool_op(self, a: bool, b: bool) -> Option<Val> {
+        use BinOp::*;
+        Some(Val::Bool(match self {
+            BitAnd => a & b,
+            BitXor => a ^ b,
+            BitOr => a | b,
+            Eq => a == b,
+            Ne => a != b,
+            _ => return None,
+        }))
+    }
+
+    fn ip_op(self, ip: Ipv4Addr, offset: i128) -> Result<Val, Error> {
+        use BinOp::*;
+        let addr = u32::from(ip) as i128;
+        let ret = match self {
+            Add => addr.checked_add(offset),
+            Sub => addr.checked_sub(offset),
+            _ => unreachable!(),
+        };
+        match ret.and_then(|a| u32::try_from(a).ok()) {
+            Some(a) => Ok(Val::Ip4(Ipv4Addr::from(a))),
+            None => {
+                Err(OverflowError.with_msg(format!("{} {} {} is not an address", ip, self, offset)))
+            }
+        }
+    }
+
+    pub fn eval(self, a: Val, b: Val) -> Result<Val, Error> {
+        use BinOp::*;
+
+        if let (Some(x), Some(y)) = (a.as_int(), b.as_int()) {
+            let typ = match self {
+                /* The type of a shift is only determined by the left operand */
+                Shl | Shr => a.val_type(),
+                _ => promote(a.val_type(), b.val_type()),
+            };
+            return self.int_op(typ, x, y);
+        }
+
+        match (&a, &b) {
+            (Val::Bool(x), Val::Bool(y)) => self.bool_op(*x, *y).map(Ok),
+            (Val::Ip4(ip), _) if matches!(self, Add | Sub) => {
+                b.as_int().map(|offset| self.ip_op(*ip, offset))
+            }
+            (_, Val::Ip4(ip)) if matches!(self, Add) => {
+                a.as_int().map(|offset| self.ip_op(*ip, offset))
+            }
+            (Val::Ip4(ip), _) if matches!(self, Slash) => b.as_int().map(|port| {
+                let port = u16::try_from(port).map_err(|_| {
+                    OverflowError.with_msg(format!("Port number {} out of range", port))
+                })?;
+                Ok(Val::Sock4(SocketAddrV4::new(*ip, port)))
+            }),
+            (Val::Ip4(x), Val::Ip4(y)) if self.is_comparison() => Some(Ok(self.compare(x, y))),
+            _ if matches!(self, Eq | Ne) => Some(Ok(Val::Bool((a == b) == (self == Eq)))),
+            _ => None,
+        }
+        .unwrap_or_else(|| Err(self.type_error(a.val_type(), b.val_type())))
+    }
+}
+
+/// The type of a subscript, which is anything since lists may hold values of any type, or a
+/// [TypeError] if the operands are of the wrong types
+pub fn index_type(target: ValType, index: ValType) -> Result<ValType, Error> {
+    if !matches!(target, ValType::List | ValType::Any) {
+        return Err(TypeError.with_msg(format!("Not subscriptable: {}", target)));
+    }
+
+    if !index.is_int() && index != ValType::Any {
+        return Err(TypeError.with_msg(format!("List index must be an integer, not {}", index)));
+    }
+
+    Ok(ValType::Any)
+}
+
+/// Subscript a list, ie. `list[index]`
+pub fn index(target: Val, index: Val) -> Result<Val, Error> {
+    index_type(target.val_type(), index.val_type())?;
+
+    let (items, idx) = match (target, index.as_int()) {
+        (Val::List(items), Some(idx)) => (items, idx),
+        _ => unreachable!(),
+    };
+
+    match usize::try_from(idx).ok().and_then(|idx| items.get(idx)) {
+        Some(item) => Ok(item.clone()),
+        None => Err(IndexError.with_msg(format!(
+            "List index {} out of range for length {}",
+            idx,
+            items.len()
+        ))),
+    }
+}
+
+/// Unary minus. Negating an unsigned integer gives a signed integer of the same width, so that `-1`
+/// is an [i64](ValType::I64).
+pub fn neg(val: Val) -> Result<Val, Error> {
+    let typ = neg_type(val.val_type())?;
+    Val::from_int(typ, -val.as_int().unwrap())
+}
+
+/// The type of the result of [neg], which may be [ValType::Any] if it's not known until runtime
+pub fn neg_type(typ: ValType) -> Result<ValType, Error> {
+    match typ.int_bits() {
+        Some(bits) => Ok(ValType::int(true, bits).unwrap()),
+        None if typ == ValType::Any => Ok(ValType::Any),
+        None => Err(TypeError.with_msg(format!("Unsupported operand type for unary -: {}", typ))),
+    }
+}
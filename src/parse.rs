@@ -1,15 +1,17 @@
 use crate::args::ArgExpr;
 use crate::err::Error;
 use crate::err::Error::ParseError;
-use crate::lex::{TokType, Token};
-use crate::loc::Loc;
-use crate::val::Val;
+use crate::lex::{TokType, Token, EOF};
+use crate::loc::{Loc, Span};
+use crate::ops::BinOp;
+use crate::val::{Val, ValType};
 
 use std::net::{Ipv4Addr, SocketAddrV4};
 
 #[derive(Debug)]
 pub struct ObjectRef {
     pub loc: Loc,
+    pub end: Loc,
     pub modules: Box<[String]>,
     pub components: Box<[String]>,
 }
@@ -18,6 +20,8 @@ pub struct ObjectRef {
 pub struct Call {
     pub obj: ObjectRef,
     pub args: Vec<ArgExpr>,
+    /// The end of the closing parenthesis
+    pub end: Loc,
 }
 
 #[derive(Debug, Default)]
@@ -25,25 +29,83 @@ pub enum Expr {
     #[default]
     Nil,
 
-    Literal(Loc, Val),
+    Literal(Span, Val),
     ObjectRef(ObjectRef), // ObjectRef contains loc
     Call(Call),           // Call contains obj which contains loc
 
-    /* Binary operators */
-    Slash(Box<Expr>, Box<Expr>),
+    /* Unary and binary operators, the loc is that of the operator itself */
+    Neg(Loc, Box<Expr>),
+    BinOp(Loc, BinOp, Box<Expr>, Box<Expr>),
+
+    /* List literal and subscript, the span runs up to the closing bracket */
+    List(Span, Vec<Expr>),
+    Index(Span, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// The extent of the whole expression within the source file
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Nil => Span::default(),
+            Expr::Literal(span, _) => *span,
+            Expr::ObjectRef(obj) => Span::new(obj.loc, obj.end),
+            Expr::Call(call) => Span::new(call.obj.loc, call.end),
+            Expr::Neg(loc, operand) => Span::new(*loc, operand.span().end),
+            Expr::BinOp(_, _, a, b) => Span::new(a.span().start, b.span().end),
+            Expr::List(span, _) => *span,
+            Expr::Index(span, _, _) => *span,
+        }
+    }
 }
 
+/// Either a stdlib module, or if `path` is present, a resynth source file to be imported under
+/// the name `module`
 #[derive(Debug)]
 pub struct Import {
     pub loc: Loc,
     pub module: String,
+    pub path: Option<String>,
 }
 
+/// A `let` binding, or a plain `x = expr;` rebinding of a `let mut` variable. The `mutable` flag
+/// is only meaningful for the former.
 #[derive(Debug)]
 pub struct Assign {
     pub loc: Loc,
     pub target: String,
     pub rvalue: Expr,
+    pub mutable: bool,
+}
+
+#[derive(Debug)]
+pub struct For {
+    pub loc: Loc,
+    pub var: String,
+    pub iter: Expr,
+    pub body: Vec<Stmt>,
+}
+
+/// An `else if` chain is represented by an `If` which is the sole statement in the else block
+#[derive(Debug)]
+pub struct If {
+    pub loc: Loc,
+    pub cond: Expr,
+    pub body: Vec<Stmt>,
+    pub orelse: Vec<Stmt>,
+}
+
+#[derive(Debug)]
+pub struct Param {
+    pub name: String,
+    pub dfl: Option<Expr>,
+}
+
+#[derive(Debug)]
+pub struct Func {
+    pub loc: Loc,
+    pub name: String,
+    pub params: Vec<Param>,
+    pub body: Vec<Stmt>,
 }
 
 /// A complete statement of the resynth language
@@ -52,7 +114,11 @@ pub enum Stmt {
     //Nop,
     Import(Import),
     Assign(Assign),
+    Reassign(Assign),
     Expr(Expr),
+    Func(Func),
+    For(For),
+    If(If),
 }
 
 #[derive(Debug, Copy, Clone, Default)]
@@ -63,10 +129,38 @@ enum State {
     Import,
     ImportEnd,
     ReduceImport,
+    ImportAs,
+    ImportName,
+    ImportPathEnd,
+    ReduceImportPath,
 
     Let,
+    LetMut,
     Assign,
 
+    Func,
+    FuncLParen,
+    FuncParam,
+    FuncParamName,
+    ReduceParam,
+    FuncParamNext,
+    FuncBody,
+    ReduceFunc,
+
+    For,
+    ForIn,
+    ForBody,
+    ReduceFor,
+
+    IfBody,
+    IfElse,
+    ElseBody,
+    ReduceElseIf,
+    ReduceIf,
+
+    Block,
+    ReduceBlock,
+
     RefComponent,
     ReduceModule,
     RefModule,
@@ -94,7 +188,14 @@ enum State {
     ReduceLiteralExpr,
     ReduceRefExpr,
     ReduceCallExpr,
-    Slash,
+    ParenEnd,
+    ListItem,
+    ReduceListItem,
+    ListNext,
+    ReduceList,
+    IndexEnd,
+    ReduceIndex,
+    BinOp,
     ReduceExpr,
     ReduceSockAddr,
 
@@ -115,14 +216,16 @@ enum State {
 #[derive(Debug)]
 struct PathBuilder {
     loc: Loc,
+    end: Loc,
     module: Vec<String>,
     object: Vec<String>,
 }
 
 impl PathBuilder {
-    pub fn new(loc: Loc) -> Self {
+    pub fn new(span: Span) -> Self {
         Self {
-            loc,
+            loc: span.start,
+            end: span.end,
             module: Vec::new(),
             object: Vec::new(),
         }
@@ -137,24 +240,34 @@ enum Node {
 
     Module(String),
     AssignTo(String),
+    MutAssignTo(String),
+    ReassignTo(String),
     Component(String),
 
     ArgName(Option<String>),
+    Splat,
     ArgList(Vec<ArgExpr>),
 
+    Param(String),
+    ParamList(Vec<Param>),
+    Block(Vec<Stmt>),
+    ExprList(Vec<Expr>),
+
     Path(PathBuilder),
 
     Object(ObjectRef),
 
     Expr(Expr),
     Assign(Assign),
+    Reassign(Assign),
     Call(Call),
 
     Stmt(Stmt),
 
     Loc(Loc),
 
-    Slash,
+    Op(Loc, BinOp),
+    Neg(Loc),
 }
 
 impl From<Node> for Loc {
@@ -178,7 +291,7 @@ impl From<Node> for State {
 impl From<Node> for u16 {
     fn from(node: Node) -> Self {
         match node {
-            Node::Literal(Val::U64(u)) => u as u16,
+            Node::Literal(Val::U16(u)) => u,
             _ => unreachable!(),
         }
     }
@@ -198,7 +311,10 @@ impl From<Node> for String {
         match node {
             Node::Module(s) => s,
             Node::AssignTo(s) => s,
+            Node::MutAssignTo(s) => s,
+            Node::ReassignTo(s) => s,
             Node::Component(s) => s,
+            Node::Param(s) => s,
             _ => unreachable!(),
         }
     }
@@ -222,6 +338,24 @@ impl From<Node> for Vec<ArgExpr> {
     }
 }
 
+impl From<Node> for Vec<Param> {
+    fn from(node: Node) -> Self {
+        match node {
+            Node::ParamList(list) => list,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl From<Node> for Vec<Stmt> {
+    fn from(node: Node) -> Self {
+        match node {
+            Node::Block(stmts) => stmts,
+            _ => unreachable!(),
+        }
+    }
+}
+
 impl From<Node> for Val {
     fn from(node: Node) -> Self {
         match node {
@@ -240,6 +374,15 @@ impl From<Node> for Expr {
     }
 }
 
+impl From<Node> for Vec<Expr> {
+    fn from(node: Node) -> Self {
+        match node {
+            Node::ExprList(list) => list,
+            _ => unreachable!(),
+        }
+    }
+}
+
 impl From<Node> for Box<Expr> {
     fn from(node: Node) -> Self {
         match node {
@@ -303,8 +446,25 @@ impl From<Node> for Stmt {
 pub struct Parser {
     state: State,
     stack: Vec<Node>,
+    /// The end of the last token which was consumed, this is used to find the end of whichever
+    /// construct is being reduced.
+    end: Loc,
 
     stmts: Vec<Stmt>,
+
+    /// Set after a syntax error, while skipping the rest of the broken statement
+    recovery: Option<Recovery>,
+    /// Bindings whose definitions were skipped because of a syntax error
+    poisoned: Vec<String>,
+}
+
+/// After a syntax error, tokens are discarded up to the `;` which ends the broken statement, or
+/// if it contains any blocks, up to the closing `}`.
+#[derive(Debug, Default)]
+struct Recovery {
+    depth: usize,
+    /// A block was just closed, which ends the statement unless it's followed by an `else`
+    closed: bool,
 }
 
 enum Action {
@@ -354,6 +514,7 @@ impl Parser {
 
         let obj = ObjectRef {
             loc: builder.loc,
+            end: builder.end,
             modules: builder.module.into_boxed_slice(),
             components: builder.object.into_boxed_slice(),
         };
@@ -378,7 +539,8 @@ impl Parser {
     fn reduce_literal_expr(&mut self) {
         let lit = self.pop();
         let loc = self.pop();
-        self.push(Node::Expr(Expr::Literal(loc.into(), lit.into())));
+        let span = Span::new(loc.into(), self.end);
+        self.push(Node::Expr(Expr::Literal(span, lit.into())));
     }
 
     fn reduce_ref_expr(&mut self) {
@@ -397,17 +559,75 @@ impl Parser {
         let a = self.pop();
 
         let expr = match op {
-            Node::Slash => Expr::Slash(a.into(), b.into()),
+            Node::Op(loc, op) => Expr::BinOp(loc, op, a.into(), b.into()),
             _ => unreachable!(),
         };
 
         self.push(Node::Expr(expr));
     }
 
+    fn reduce_list_item(&mut self) {
+        let item = self.pop();
+        match self.stack.last_mut() {
+            Some(Node::ExprList(list)) => list.push(item.into()),
+            _ => unreachable!(),
+        }
+    }
+
+    fn reduce_list(&mut self) {
+        let items = self.pop();
+        let loc = self.pop();
+        let span = Span::new(loc.into(), self.end);
+        self.push(Node::Expr(Expr::List(span, items.into())));
+    }
+
+    fn reduce_index(&mut self) {
+        let index = self.pop();
+        let _ = self.pop();
+        let target: Expr = self.pop().into();
+        let span = Span::new(target.span().start, self.end);
+        self.push(Node::Expr(Expr::Index(span, target.into(), index.into())));
+    }
+
+    /// Unary operators bind more tightly than any binary operator, so if the expression on top of
+    /// the stack is the operand of one, then reduce it straight away.
+    fn reduce_unary(&mut self) -> bool {
+        let len = self.stack.len();
+        if len < 2 {
+            return false;
+        }
+
+        let loc = match self.stack[len - 2] {
+            Node::Neg(loc) => loc,
+            _ => return false,
+        };
+
+        let operand = self.pop();
+        let _ = self.pop();
+        self.push(Node::Expr(Expr::Neg(loc, operand.into())));
+        true
+    }
+
+    /// If the left operand of the expression on top of the stack is part of a binary operation,
+    /// then return that operator.
+    fn pending_op(&self) -> Option<BinOp> {
+        let len = self.stack.len();
+        if len < 3 {
+            return None;
+        }
+
+        match (&self.stack[len - 3], &self.stack[len - 2]) {
+            (Node::Op(_, op), Node::State(State::ReduceBop)) => Some(*op),
+            _ => None,
+        }
+    }
+
     fn reduce_arg(&mut self) {
         let arg = self.pop();
-        let arg_name = self.pop();
-        let def = ArgExpr::new(arg_name.into(), arg.into());
+        let def = match self.pop() {
+            Node::Splat => ArgExpr::splat(arg.into()),
+            arg_name => ArgExpr::new(arg_name.into(), arg.into()),
+        };
 
         let mut list: Vec<ArgExpr> = self.pop().into();
 
@@ -424,6 +644,7 @@ impl Parser {
         let call = Call {
             obj: obj.into(),
             args,
+            end: self.end,
         };
 
         //println!("reduce call: {:?}", call);
@@ -435,14 +656,22 @@ impl Parser {
         let target = self.pop();
         let loc = self.pop();
 
+        let mutable = matches!(target, Node::MutAssignTo(_));
+        let reassign = matches!(target, Node::ReassignTo(_));
+
         let assign = Assign {
             loc: loc.into(),
             target: target.into(),
             rvalue: call.into(),
+            mutable,
         };
 
         //println!("reduce assign: {:?}", assign);
-        self.push(Node::Assign(assign));
+        self.push(if reassign {
+            Node::Reassign(assign)
+        } else {
+            Node::Assign(assign)
+        });
     }
 
     fn reduce_expr_stmt(&mut self) {
@@ -453,10 +682,14 @@ impl Parser {
     }
 
     fn reduce_assign_stmt(&mut self) {
-        let assign = self.pop();
+        let stmt = match self.pop() {
+            Node::Assign(assign) => Stmt::Assign(assign),
+            Node::Reassign(assign) => Stmt::Reassign(assign),
+            _ => unreachable!(),
+        };
 
-        //println!("reduce assign stmt: {:?}", assign);
-        self.push(Node::Stmt(Stmt::Assign(assign.into())));
+        //println!("reduce assign stmt: {:?}", stmt);
+        self.push(Node::Stmt(stmt));
     }
 
     fn reduce_import_stmt(&mut self) {
@@ -466,33 +699,317 @@ impl Parser {
         let import = Import {
             loc: loc.into(),
             module: module.into(),
+            path: None,
+        };
+
+        //println!("reduce import stmt: {:?}", import);
+        self.push(Node::Stmt(Stmt::Import(import)));
+    }
+
+    fn reduce_import_path_stmt(&mut self) {
+        let module = self.pop();
+        let path = self.pop();
+        let loc = self.pop();
+
+        let import = Import {
+            loc: loc.into(),
+            module: module.into(),
+            path: Some(path.into()),
         };
 
         //println!("reduce import stmt: {:?}", import);
         self.push(Node::Stmt(Stmt::Import(import)));
     }
 
-    fn reduce_stmt(&mut self) {
+    fn reduce_param(&mut self, dfl: Option<Expr>) -> Result<(), Error> {
+        let name: String = self.pop().into();
+        let mut list: Vec<Param> = self.pop().into();
+
+        /* Just like stdlib functions, optional args must come after all the positionals */
+        if dfl.is_none() && list.last().map(|p| p.dfl.is_some()).unwrap_or(false) {
+            return Err(ParseError);
+        }
+
+        if list.iter().any(|p| p.name == name) {
+            return Err(ParseError.with_msg(format!("Duplicate parameter: {}", name)));
+        }
+
+        list.push(Param { name, dfl });
+        self.push(Node::ParamList(list));
+        Ok(())
+    }
+
+    fn reduce_func(&mut self) {
+        let body = self.pop();
+        let params = self.pop();
+        let name = self.pop();
+        let loc = self.pop();
+
+        let func = Func {
+            loc: loc.into(),
+            name: name.into(),
+            params: params.into(),
+            body: body.into(),
+        };
+
+        self.push(Node::Stmt(Stmt::Func(func)));
+    }
+
+    fn reduce_for(&mut self) {
+        let body = self.pop();
+        let iter = self.pop();
+        let var = self.pop();
+        let loc = self.pop();
+
+        let stmt = For {
+            loc: loc.into(),
+            var: var.into(),
+            iter: iter.into(),
+            body: body.into(),
+        };
+
+        self.push(Node::Stmt(Stmt::For(stmt)));
+    }
+
+    fn reduce_if(&mut self) -> State {
+        let orelse = self.pop();
+        let body = self.pop();
+        let cond = self.pop();
+        let loc = self.pop();
+        let st = self.pop();
+
+        let stmt = If {
+            loc: loc.into(),
+            cond: cond.into(),
+            body: body.into(),
+            orelse: orelse.into(),
+        };
+
+        self.push(Node::Stmt(Stmt::If(stmt)));
+        st.into()
+    }
+
+    fn reduce_block(&mut self) -> State {
+        let block = self.pop();
+        let st = self.pop();
+        self.push(block);
+        st.into()
+    }
+
+    /// Statements either go in to the innermost block that is being parsed, or if we're at the
+    /// top level, in to the results vector.
+    fn reduce_stmt(&mut self) -> State {
         let stmt = self.pop();
-        self.stmts.push(stmt.into());
+        match self.stack.last_mut() {
+            Some(Node::Block(block)) => {
+                block.push(stmt.into());
+                State::Block
+            }
+            _ => {
+                self.stmts.push(stmt.into());
+                State::Initial
+            }
+        }
     }
 
     fn state_initial(&mut self, tok: &Token) -> Result<Action, Error> {
         match tok.tok_type() {
             TokType::ImportKeyword => Ok(Action::Discard(State::Import)),
+            TokType::FnKeyword => Ok(Action::Discard(State::Func)),
+            TokType::Eof => Ok(Action::Accept),
+            _ => self.state_block(tok),
+        }
+    }
+
+    fn state_block(&mut self, tok: &Token) -> Result<Action, Error> {
+        match tok.tok_type() {
             TokType::LetKeyword => Ok(Action::Discard(State::Let)),
+            TokType::ForKeyword => Ok(Action::Discard(State::For)),
+            TokType::IfKeyword => {
+                self.push_goto(State::ReduceStmt);
+                Ok(self.start_if(tok))
+            }
             TokType::Identifier => Ok(Action::Goto(State::ExprStmt)),
-            TokType::Eof => Ok(Action::Accept),
+            TokType::RBrace if matches!(self.state, State::Block) => {
+                Ok(Action::Discard(State::ReduceBlock))
+            }
             _ => Err(ParseError),
         }
     }
 
+    fn state_reduce_block(&mut self, _tok: &Token) -> Result<Action, Error> {
+        Ok(Action::Goto(self.reduce_block()))
+    }
+
+    fn state_for(&mut self, tok: &Token) -> Result<Action, Error> {
+        match tok.tok_type() {
+            TokType::Identifier => {
+                self.push(Node::Loc(tok.loc()));
+                Ok(Action::Shift(State::ForIn, Node::Param(tok.into())))
+            }
+            _ => Err(ParseError),
+        }
+    }
+
+    fn state_for_in(&mut self, tok: &Token) -> Result<Action, Error> {
+        match tok.tok_type() {
+            TokType::InKeyword => {
+                self.push_goto(State::ForBody);
+                Ok(Action::Discard(State::Expr))
+            }
+            _ => Err(ParseError),
+        }
+    }
+
+    fn state_for_body(&mut self, tok: &Token) -> Result<Action, Error> {
+        match tok.tok_type() {
+            TokType::LBrace => {
+                self.push_goto(State::ReduceFor);
+                Ok(Action::Shift(State::Block, Node::Block(Vec::new())))
+            }
+            _ => Err(ParseError),
+        }
+    }
+
+    fn state_reduce_for(&mut self, _tok: &Token) -> Result<Action, Error> {
+        self.reduce_for();
+        Ok(Action::Goto(State::ReduceStmt))
+    }
+
+    /// The if statement itself is reduced to whichever state was pushed before calling this,
+    /// which lets us tell top-level if statements apart from those in an `else if`.
+    fn start_if(&mut self, tok: &Token) -> Action {
+        self.push(Node::Loc(tok.loc()));
+        self.push_goto(State::IfBody);
+        Action::Discard(State::Expr)
+    }
+
+    fn state_if_body(&mut self, tok: &Token) -> Result<Action, Error> {
+        match tok.tok_type() {
+            TokType::LBrace => {
+                self.push_goto(State::IfElse);
+                Ok(Action::Shift(State::Block, Node::Block(Vec::new())))
+            }
+            _ => Err(ParseError),
+        }
+    }
+
+    fn state_if_else(&mut self, tok: &Token) -> Result<Action, Error> {
+        match tok.tok_type() {
+            TokType::ElseKeyword => Ok(Action::Discard(State::ElseBody)),
+            _ => {
+                self.push(Node::Block(Vec::new()));
+                Ok(Action::Goto(State::ReduceIf))
+            }
+        }
+    }
+
+    fn state_else_body(&mut self, tok: &Token) -> Result<Action, Error> {
+        match tok.tok_type() {
+            TokType::LBrace => {
+                self.push_goto(State::ReduceIf);
+                Ok(Action::Shift(State::Block, Node::Block(Vec::new())))
+            }
+            TokType::IfKeyword => {
+                self.push_goto(State::ReduceElseIf);
+                Ok(self.start_if(tok))
+            }
+            _ => Err(ParseError),
+        }
+    }
+
+    fn state_reduce_else_if(&mut self, _tok: &Token) -> Result<Action, Error> {
+        let stmt = self.pop();
+        self.push(Node::Block(vec![stmt.into()]));
+        Ok(Action::Goto(State::ReduceIf))
+    }
+
+    fn state_reduce_if(&mut self, _tok: &Token) -> Result<Action, Error> {
+        Ok(Action::Goto(self.reduce_if()))
+    }
+
+    fn state_func(&mut self, tok: &Token) -> Result<Action, Error> {
+        match tok.tok_type() {
+            TokType::Identifier => {
+                self.push(Node::Loc(tok.loc()));
+                Ok(Action::Shift(State::FuncLParen, Node::AssignTo(tok.into())))
+            }
+            _ => Err(ParseError),
+        }
+    }
+
+    fn state_func_lparen(&mut self, tok: &Token) -> Result<Action, Error> {
+        match tok.tok_type() {
+            TokType::LParen => Ok(Action::Shift(State::FuncParam, Node::ParamList(Vec::new()))),
+            _ => Err(ParseError),
+        }
+    }
+
+    fn state_func_param(&mut self, tok: &Token) -> Result<Action, Error> {
+        match tok.tok_type() {
+            TokType::Identifier => Ok(Action::Shift(State::FuncParamName, Node::Param(tok.into()))),
+            TokType::RParen => Ok(Action::Discard(State::FuncBody)),
+            _ => Err(ParseError),
+        }
+    }
+
+    fn state_func_param_name(&mut self, tok: &Token) -> Result<Action, Error> {
+        match tok.tok_type() {
+            TokType::Colon => {
+                self.push_goto(State::ReduceParam);
+                Ok(Action::Discard(State::Expr))
+            }
+            TokType::Comma => {
+                self.reduce_param(None)?;
+                Ok(Action::Discard(State::FuncParam))
+            }
+            TokType::RParen => {
+                self.reduce_param(None)?;
+                Ok(Action::Discard(State::FuncBody))
+            }
+            _ => Err(ParseError),
+        }
+    }
+
+    fn state_reduce_param(&mut self, _tok: &Token) -> Result<Action, Error> {
+        let dfl = self.pop();
+        self.reduce_param(Some(dfl.into()))?;
+        Ok(Action::Goto(State::FuncParamNext))
+    }
+
+    fn state_func_param_next(&mut self, tok: &Token) -> Result<Action, Error> {
+        match tok.tok_type() {
+            TokType::Comma => Ok(Action::Discard(State::FuncParam)),
+            TokType::RParen => Ok(Action::Discard(State::FuncBody)),
+            _ => Err(ParseError),
+        }
+    }
+
+    fn state_func_body(&mut self, tok: &Token) -> Result<Action, Error> {
+        match tok.tok_type() {
+            TokType::LBrace => {
+                self.push_goto(State::ReduceFunc);
+                Ok(Action::Shift(State::Block, Node::Block(Vec::new())))
+            }
+            _ => Err(ParseError),
+        }
+    }
+
+    fn state_reduce_func(&mut self, _tok: &Token) -> Result<Action, Error> {
+        self.reduce_func();
+        Ok(Action::Goto(State::ReduceStmt))
+    }
+
     fn state_import(&mut self, tok: &Token) -> Result<Action, Error> {
         match tok.tok_type() {
             TokType::Identifier => {
                 self.push(Node::Loc(tok.loc()));
                 Ok(Action::Shift(State::ImportEnd, Node::Module(tok.into())))
             }
+            TokType::StringLiteral => {
+                self.push(Node::Loc(tok.loc()));
+                Ok(Action::Shift(State::ImportAs, Node::Module(tok.into())))
+            }
             _ => Err(ParseError),
         }
     }
@@ -509,12 +1026,52 @@ impl Parser {
         Ok(Action::Goto(State::ReduceStmt))
     }
 
+    fn state_import_as(&mut self, tok: &Token) -> Result<Action, Error> {
+        match tok.tok_type() {
+            TokType::AsKeyword => Ok(Action::Discard(State::ImportName)),
+            _ => Err(ParseError),
+        }
+    }
+
+    fn state_import_name(&mut self, tok: &Token) -> Result<Action, Error> {
+        match tok.tok_type() {
+            TokType::Identifier => Ok(Action::Shift(
+                State::ImportPathEnd,
+                Node::Module(tok.into()),
+            )),
+            _ => Err(ParseError),
+        }
+    }
+
+    fn state_import_path_end(&mut self, tok: &Token) -> Result<Action, Error> {
+        match tok.tok_type() {
+            TokType::SemiColon => Ok(Action::Discard(State::ReduceImportPath)),
+            _ => Err(ParseError),
+        }
+    }
+
+    fn state_reduce_import_path(&mut self, _tok: &Token) -> Result<Action, Error> {
+        self.reduce_import_path_stmt();
+        Ok(Action::Goto(State::ReduceStmt))
+    }
+
     fn state_let(&mut self, tok: &Token) -> Result<Action, Error> {
         match tok.tok_type() {
             TokType::Identifier => {
                 self.push(Node::Loc(tok.loc()));
                 Ok(Action::Shift(State::Assign, Node::AssignTo(tok.into())))
             }
+            TokType::MutKeyword => Ok(Action::Discard(State::LetMut)),
+            _ => Err(ParseError),
+        }
+    }
+
+    fn state_let_mut(&mut self, tok: &Token) -> Result<Action, Error> {
+        match tok.tok_type() {
+            TokType::Identifier => {
+                self.push(Node::Loc(tok.loc()));
+                Ok(Action::Shift(State::Assign, Node::MutAssignTo(tok.into())))
+            }
             _ => Err(ParseError),
         }
     }
@@ -556,7 +1113,15 @@ impl Parser {
         Ok(Action::Goto(State::RefModule))
     }
 
+    /// Extend the span of the object reference on top of the stack to include another component
+    fn extend_path(&mut self, tok: &Token) {
+        if let Some(Node::Path(builder)) = self.stack.last_mut() {
+            builder.end = tok.end;
+        }
+    }
+
     fn state_ref_module(&mut self, tok: &Token) -> Result<Action, Error> {
+        self.extend_path(tok);
         match tok.tok_type() {
             TokType::Identifier => Ok(Action::Shift(
                 State::RefComponent,
@@ -567,6 +1132,7 @@ impl Parser {
     }
 
     fn state_ref_object(&mut self, tok: &Token) -> Result<Action, Error> {
+        self.extend_path(tok);
         match tok.tok_type() {
             TokType::Identifier => Ok(Action::Shift(State::RefObjEnd, Node::Component(tok.into()))),
             _ => Err(ParseError),
@@ -595,16 +1161,26 @@ impl Parser {
     #[inline(always)]
     fn push_literal(&mut self, tok: &Token) -> Result<Action, Error> {
         match tok.tok_type() {
-            TokType::StringLiteral
-            | TokType::BooleanLiteral
-            | TokType::HexIntegerLiteral
-            | TokType::IntegerLiteral => {
+            TokType::StringLiteral | TokType::BooleanLiteral => {
                 self.push(Node::Loc(tok.loc()));
                 Ok(Action::Shift(
                     State::ReduceLiteralExpr,
                     Node::Literal(Val::from_token(tok)?),
                 ))
             }
+            TokType::HexIntegerLiteral | TokType::IntegerLiteral => {
+                /* A unary minus is folded in to the literal, so the range check sees the sign */
+                let val = if let Some(Node::Neg(loc)) = self.stack.last() {
+                    let loc = *loc;
+                    let _ = self.pop();
+                    self.push(Node::Loc(loc));
+                    Val::from_neg_token(tok)?
+                } else {
+                    self.push(Node::Loc(tok.loc()));
+                    Val::from_token(tok)?
+                };
+                Ok(Action::Shift(State::ReduceLiteralExpr, Node::Literal(val)))
+            }
             TokType::IPv4Literal => {
                 self.push(Node::Loc(tok.loc()));
                 Ok(Action::Shift(
@@ -619,6 +1195,7 @@ impl Parser {
     fn state_expr_arg(&mut self, tok: &Token) -> Result<Action, Error> {
         Ok(match tok.tok_type() {
             TokType::Identifier => Action::Shift(State::ArgName, Node::ArgName(Some(tok.into()))),
+            TokType::Star => Action::Shift(State::ArgVal, Node::Splat),
             _ => {
                 self.push(Node::ArgName(None));
                 Action::Goto(State::ArgVal)
@@ -630,11 +1207,12 @@ impl Parser {
         Ok(match tok.tok_type() {
             TokType::Colon => Action::Discard(State::ArgVal),
             _ => {
-                let component: Option<String> = self.pop().into();
+                let component = Option::<String>::from(self.pop()).unwrap();
+                let start = Loc::new(self.end.line(), self.end.col() - component.len());
                 self.push(Node::ArgName(None));
                 self.push_goto(State::ReduceArg);
-                self.push(Node::Path(PathBuilder::new(tok.loc())));
-                self.push(Node::Component(component.unwrap()));
+                self.push(Node::Path(PathBuilder::new(Span::new(start, self.end))));
+                self.push(Node::Component(component));
                 Action::Goto(State::RefComponent)
             }
         })
@@ -643,25 +1221,15 @@ impl Parser {
     fn state_arg_val(&mut self, tok: &Token) -> Result<Action, Error> {
         self.push_goto(State::ReduceArg);
         match tok.tok_type() {
-            TokType::Identifier => {
-                self.push(Node::Path(PathBuilder::new(tok.loc())));
-                Ok(Action::Shift(
-                    State::RefComponent,
-                    Node::Component(tok.into()),
-                ))
-            }
-            TokType::StringLiteral
-            | TokType::BooleanLiteral
-            | TokType::HexIntegerLiteral
-            | TokType::IntegerLiteral
-            | TokType::IPv4Literal => Ok(self.push_literal(tok)?),
             TokType::RParen => {
                 let st = self.pop();
-                let _ = self.pop();
+                if let Node::Splat = self.pop() {
+                    return Err(ParseError);
+                }
                 self.push(st);
                 Ok(Action::Discard(State::ReduceCall))
             }
-            _ => Err(ParseError),
+            _ => self.state_expr(tok),
         }
     }
 
@@ -673,7 +1241,7 @@ impl Parser {
     fn state_expr(&mut self, tok: &Token) -> Result<Action, Error> {
         match tok.tok_type() {
             TokType::Identifier => {
-                self.push(Node::Path(PathBuilder::new(tok.loc())));
+                self.push(Node::Path(PathBuilder::new(tok.span())));
                 Ok(Action::Shift(
                     State::RefComponent,
                     Node::Component(tok.into()),
@@ -684,6 +1252,62 @@ impl Parser {
             | TokType::HexIntegerLiteral
             | TokType::IntegerLiteral
             | TokType::IPv4Literal => Ok(self.push_literal(tok)?),
+            TokType::LParen => {
+                self.push_goto(State::ParenEnd);
+                Ok(Action::Discard(State::Expr))
+            }
+            TokType::LBracket => {
+                self.push(Node::Loc(tok.loc()));
+                Ok(Action::Shift(State::ListItem, Node::ExprList(Vec::new())))
+            }
+            TokType::Minus => Ok(Action::Shift(State::Expr, Node::Neg(tok.loc()))),
+            _ => Err(ParseError),
+        }
+    }
+
+    fn state_list_item(&mut self, tok: &Token) -> Result<Action, Error> {
+        match tok.tok_type() {
+            TokType::RBracket => Ok(Action::Discard(State::ReduceList)),
+            _ => {
+                self.push_goto(State::ReduceListItem);
+                self.state_expr(tok)
+            }
+        }
+    }
+
+    fn state_reduce_list_item(&mut self, _tok: &Token) -> Result<Action, Error> {
+        self.reduce_list_item();
+        Ok(Action::Goto(State::ListNext))
+    }
+
+    fn state_list_next(&mut self, tok: &Token) -> Result<Action, Error> {
+        match tok.tok_type() {
+            TokType::Comma => Ok(Action::Discard(State::ListItem)),
+            TokType::RBracket => Ok(Action::Discard(State::ReduceList)),
+            _ => Err(ParseError),
+        }
+    }
+
+    fn state_reduce_list(&mut self, _tok: &Token) -> Result<Action, Error> {
+        self.reduce_list();
+        Ok(Action::Goto(State::BinOp))
+    }
+
+    fn state_index_end(&mut self, tok: &Token) -> Result<Action, Error> {
+        match tok.tok_type() {
+            TokType::RBracket => Ok(Action::Discard(State::ReduceIndex)),
+            _ => Err(ParseError),
+        }
+    }
+
+    fn state_reduce_index(&mut self, _tok: &Token) -> Result<Action, Error> {
+        self.reduce_index();
+        Ok(Action::Goto(State::BinOp))
+    }
+
+    fn state_paren_end(&mut self, tok: &Token) -> Result<Action, Error> {
+        match tok.tok_type() {
+            TokType::RParen => Ok(Action::Discard(State::BinOp)),
             _ => Err(ParseError),
         }
     }
@@ -704,10 +1328,11 @@ impl Parser {
         match tok.tok_type() {
             TokType::IntegerLiteral => {
                 self.push(Node::Loc(tok.loc()));
-                Ok(Action::Shift(
-                    State::ReduceSockAddr,
-                    Node::Literal(Val::from_token(tok)?),
-                ))
+                let port = Val::from_token(tok)?.cast(ValType::U16).map_err(|err| {
+                    err.with_msg(format!("Port number out of range: {}", tok.val()))
+                        .with_span(tok.span())
+                })?;
+                Ok(Action::Shift(State::ReduceSockAddr, Node::Literal(port)))
             }
             _ => Err(ParseError),
         }
@@ -720,28 +1345,50 @@ impl Parser {
 
     fn state_reduce_literal_expr(&mut self, _tok: &Token) -> Result<Action, Error> {
         self.reduce_literal_expr();
-        Ok(Action::Goto(State::Slash))
+        Ok(Action::Goto(State::BinOp))
     }
 
     fn state_reduce_ref_expr(&mut self, _tok: &Token) -> Result<Action, Error> {
         self.reduce_ref_expr();
-        Ok(Action::Goto(State::Slash))
+        Ok(Action::Goto(State::BinOp))
     }
 
     fn state_reduce_call_expr(&mut self, _tok: &Token) -> Result<Action, Error> {
         self.reduce_call_expr();
-        Ok(Action::Goto(State::Slash))
+        Ok(Action::Goto(State::BinOp))
     }
 
-    fn state_slash(&mut self, tok: &Token) -> Result<Action, Error> {
-        Ok(match tok.tok_type() {
-            TokType::Slash => {
-                self.push(Node::Slash);
-                self.push_goto(State::ReduceBop);
-                Action::Discard(State::Expr)
+    fn state_binop(&mut self, tok: &Token) -> Result<Action, Error> {
+        /* Subscripts bind more tightly than any binary operator */
+        if let TokType::LBracket = tok.tok_type() {
+            self.push(Node::Loc(tok.loc()));
+            self.push_goto(State::IndexEnd);
+            return Ok(Action::Discard(State::Expr));
+        }
+
+        if self.reduce_unary() {
+            return Ok(Action::Goto(State::BinOp));
+        }
+
+        let op = match BinOp::from_tok(tok.tok_type()) {
+            Some(op) => op,
+            None => return Ok(Action::Goto(State::ReduceExpr)),
+        };
+
+        /* If the operator to our left binds at least as tightly, then it gets the operand */
+        if let Some(prev) = self.pending_op() {
+            if prev.precedence() >= op.precedence() {
+                let b = self.pop();
+                let _ = self.pop();
+                self.push(b);
+                self.reduce_bop_expr();
+                return Ok(Action::Goto(State::BinOp));
             }
-            _ => Action::Goto(State::ReduceExpr),
-        })
+        }
+
+        self.push(Node::Op(tok.loc(), op));
+        self.push_goto(State::ReduceBop);
+        Ok(Action::Discard(State::Expr))
     }
 
     fn state_reduce_expr(&mut self, _tok: &Token) -> Result<Action, Error> {
@@ -765,6 +1412,21 @@ impl Parser {
     fn state_expr_stmt_end(&mut self, tok: &Token) -> Result<Action, Error> {
         match tok.tok_type() {
             TokType::SemiColon => Ok(Action::Discard(State::ReduceExprStmt)),
+            TokType::Equals => {
+                /* The expression turned out to be the target of a reassignment, which may only
+                 * be a plain variable name.
+                 */
+                let obj = match self.pop() {
+                    Node::Expr(Expr::ObjectRef(obj)) => obj,
+                    _ => return Err(ParseError),
+                };
+                if !obj.modules.is_empty() || obj.components.len() != 1 {
+                    return Err(ParseError);
+                }
+                self.push(Node::Loc(obj.loc));
+                self.push(Node::ReassignTo(obj.components[0].clone()));
+                Ok(Action::Discard(State::ExprRvalue))
+            }
             _ => Err(ParseError),
         }
     }
@@ -797,8 +1459,7 @@ impl Parser {
     }
 
     fn state_reduce_stmt(&mut self, _tok: &Token) -> Result<Action, Error> {
-        self.reduce_stmt();
-        Ok(Action::Goto(State::Initial))
+        Ok(Action::Goto(self.reduce_stmt()))
     }
 
     fn dispatch(&mut self, tok: &Token) -> Result<Action, Error> {
@@ -808,10 +1469,38 @@ impl Parser {
             State::Import => self.state_import(tok),
             State::ImportEnd => self.state_import_end(tok),
             State::ReduceImport => self.state_reduce_import(tok),
+            State::ImportAs => self.state_import_as(tok),
+            State::ImportName => self.state_import_name(tok),
+            State::ImportPathEnd => self.state_import_path_end(tok),
+            State::ReduceImportPath => self.state_reduce_import_path(tok),
 
             State::Let => self.state_let(tok),
+            State::LetMut => self.state_let_mut(tok),
             State::Assign => self.state_assign(tok),
 
+            State::Func => self.state_func(tok),
+            State::FuncLParen => self.state_func_lparen(tok),
+            State::FuncParam => self.state_func_param(tok),
+            State::FuncParamName => self.state_func_param_name(tok),
+            State::ReduceParam => self.state_reduce_param(tok),
+            State::FuncParamNext => self.state_func_param_next(tok),
+            State::FuncBody => self.state_func_body(tok),
+            State::ReduceFunc => self.state_reduce_func(tok),
+
+            State::For => self.state_for(tok),
+            State::ForIn => self.state_for_in(tok),
+            State::ForBody => self.state_for_body(tok),
+            State::ReduceFor => self.state_reduce_for(tok),
+
+            State::IfBody => self.state_if_body(tok),
+            State::IfElse => self.state_if_else(tok),
+            State::ElseBody => self.state_else_body(tok),
+            State::ReduceElseIf => self.state_reduce_else_if(tok),
+            State::ReduceIf => self.state_reduce_if(tok),
+
+            State::Block => self.state_block(tok),
+            State::ReduceBlock => self.state_reduce_block(tok),
+
             State::RefComponent => self.state_ref_component(tok),
             State::ReduceModule => self.state_reduce_module(tok),
             State::RefModule => self.state_ref_module(tok),
@@ -838,7 +1527,14 @@ impl Parser {
             State::ReduceLiteralExpr => self.state_reduce_literal_expr(tok),
             State::ReduceRefExpr => self.state_reduce_ref_expr(tok),
             State::ReduceCallExpr => self.state_reduce_call_expr(tok),
-            State::Slash => self.state_slash(tok),
+            State::ParenEnd => self.state_paren_end(tok),
+            State::ListItem => self.state_list_item(tok),
+            State::ReduceListItem => self.state_reduce_list_item(tok),
+            State::ListNext => self.state_list_next(tok),
+            State::ReduceList => self.state_reduce_list(tok),
+            State::IndexEnd => self.state_index_end(tok),
+            State::ReduceIndex => self.state_reduce_index(tok),
+            State::BinOp => self.state_binop(tok),
             State::ReduceExpr => self.state_reduce_expr(tok),
             State::ReduceSockAddr => self.state_reduce_sockaddr(tok),
 
@@ -858,17 +1554,105 @@ impl Parser {
         }
     }
 
+    /// Feed in the next token. After returning an error, the parser resynchronises at the end of
+    /// the broken statement, so that parsing can continue and any further errors can be found.
     pub fn feed(&mut self, tok: &Token) -> Result<(), Error> {
+        if self.recovery.is_some() && self.skip(tok) {
+            return Ok(());
+        }
+
+        let err = match self.step(tok) {
+            Ok(()) => return Ok(()),
+            Err(err) => err,
+        };
+
+        self.recover();
+
+        /* The token which caused the error may also be the one which ends the statement, in
+         * which case it's fed back in. If it's still no good then it's a stray and is dropped.
+         */
+        if !self.skip(tok) && self.step(tok).is_err() {
+            self.recover();
+            self.recovery = None;
+        }
+
+        Err(err)
+    }
+
+    /// Unwind to the innermost enclosing block and start skipping tokens
+    fn recover(&mut self) {
+        let base = self
+            .stack
+            .iter()
+            .rposition(|node| matches!(node, Node::Block(_)))
+            .map_or(0, |pos| pos + 1);
+
+        if let [Node::Loc(_), Node::AssignTo(name) | Node::MutAssignTo(name), ..] =
+            &self.stack[base..]
+        {
+            self.poisoned.push(name.clone());
+        }
+
+        self.stack.truncate(base);
+        self.state = match base {
+            0 => State::Initial,
+            _ => State::Block,
+        };
+        self.recovery = Some(Recovery::default());
+    }
+
+    /// Returns true if the token was skipped, or false if recovery is finished and the token
+    /// needs to be parsed.
+    fn skip(&mut self, tok: &Token) -> bool {
+        let Some(rec) = self.recovery.as_mut() else {
+            return false;
+        };
+
+        let skipped = match tok.tok_type() {
+            TokType::Eof => {
+                self.stack.clear();
+                self.state = State::Initial;
+                false
+            }
+            TokType::ElseKeyword if rec.closed => {
+                rec.closed = false;
+                return true;
+            }
+            _ if rec.closed => false,
+            TokType::LBrace => {
+                rec.depth += 1;
+                return true;
+            }
+            TokType::RBrace if rec.depth == 0 => false,
+            TokType::RBrace => {
+                rec.depth -= 1;
+                rec.closed = rec.depth == 0;
+                return true;
+            }
+            TokType::SemiColon if rec.depth == 0 => true,
+            _ => return true,
+        };
+
+        self.recovery = None;
+        skipped
+    }
+
+    fn step(&mut self, tok: &Token) -> Result<(), Error> {
         loop {
-            let action = self.dispatch(tok)?;
+            let action = self.dispatch(tok).map_err(|err| {
+                err.with_msg(format!("Unexpected {}", tok.describe()))
+                    .with_span(tok.span())
+            })?;
             match action {
                 Action::Discard(st) => {
                     self.state = st;
+                    self.end = tok.end;
                     //println!("");
                 }
                 Action::Shift(st, frag) => {
                     self.push(frag);
                     self.state = st;
+                    self.end = tok.end;
                     //println!("");
                 }
                 Action::Goto(st) => {
@@ -884,7 +1668,42 @@ impl Parser {
         }
     }
 
+    /// Finish off any statement which has been fed in up to its closing `;` or `}`. Usually that
+    /// happens when the next token arrives, but when running interactively, that may not be for a
+    /// while. An `if` can't be finished until it's known whether there's an `else`.
+    pub fn flush(&mut self) {
+        while matches!(
+            self.state,
+            State::ReduceImport
+                | State::ReduceImportPath
+                | State::ReduceFunc
+                | State::ReduceFor
+                | State::ReduceBlock
+                | State::ReduceAssign
+                | State::ReduceAssignStmt
+                | State::ReduceExprStmt
+                | State::ReduceStmt
+        ) {
+            match self.dispatch(&EOF) {
+                Ok(Action::Goto(st)) => self.state = st,
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    /// Returns true if the parser is between statements, ie. it isn't waiting for the rest of
+    /// one which has only partially been fed in.
+    pub fn is_idle(&self) -> bool {
+        self.stack.is_empty() && self.recovery.is_none()
+    }
+
     pub fn get_results(&mut self) -> Vec<Stmt> {
         std::mem::take(&mut self.stmts)
     }
+
+    /// The names of any `let` or `fn` bindings which were lost to syntax errors. These should be
+    /// [poisoned](crate::Program::poison) so that using them doesn't produce further errors.
+    pub fn get_poisoned(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.poisoned)
+    }
 }
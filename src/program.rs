@@ -1,52 +1,181 @@
-use crate::args::{ArgExpr, ArgSpec};
+use crate::args::{ArgExpr, ArgSpec, Args};
 use crate::err::Error;
-use crate::err::Error::{ImportError, MultipleAssignError, NameError, TypeError};
-use crate::libapi::{FuncDef, Module};
-use crate::loc::Loc;
+use crate::err::Error::{
+    CircularImportError, ImportError, MultipleAssignError, NameError, PoisonError, RuntimeError,
+    TypeError,
+};
+use crate::lex::{Lexer, EOF};
+use crate::libapi::{FuncDef, Module, Signature};
+use crate::lint::Lint;
+use crate::loc::{Loc, Span};
 use crate::object::ObjRef;
-use crate::parse::{Assign, Call, Expr, Import, ObjectRef, Stmt};
-use crate::stdlib::toplevel_module;
+use crate::ops;
+use crate::parse::{Assign, Call, Expr, For, Func, If, Import, ObjectRef, Parser, Stmt};
+use crate::stdlib::{builtin, linktype_arg, toplevel_module, LINKTYPE, PARAM};
+use crate::str::Buf;
 use crate::sym::Symbol;
+use crate::trace::{Frame, Origin};
+use crate::userfunc::UserFunc;
 use crate::val::{Typed, Val, ValType};
 
-use pkt::PcapWriter;
+use pkt::{CaptureWriter, LinkType, Packet};
 
-use std::collections::HashMap;
-use std::net::SocketAddrV4;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
-type WarningCallback<'a> = &'a mut dyn FnMut(Loc, &str);
+type WarningCallback<'a> = &'a mut dyn FnMut(Lint, Loc, &str);
+type PacketCallback<'a> = &'a mut dyn FnMut(u64, &Packet, &Origin);
+
+/// Limit on nested calls to user-defined functions, so that runaway recursion in a resynth
+/// program is reported as an error rather than overflowing the interpreter's stack.
+const MAX_CALL_DEPTH: usize = 256;
+
+/// Local variables for a block of statements. The first scope of a call to a user-defined
+/// function is marked as a frame, and name resolution doesn't look past it in to the caller's
+/// locals, only in to the globals.
+#[derive(Debug, Default)]
+struct Scope {
+    vars: HashMap<String, Val>,
+    /// Names which were bound with `let mut` and may be reassigned
+    mutable: HashSet<String>,
+    /// Where each `let` binding was made, until it's first referenced
+    unused: RefCell<HashMap<String, Loc>>,
+    frame: bool,
+}
+
+/// Something which has been imported, either from the stdlib or from another resynth source file
+#[derive(Debug, Clone)]
+enum Namespace {
+    Stdlib(&'static Module),
+    User(Rc<RefCell<Globals>>),
+}
+
+/// The top-level bindings and imports of a single resynth source file. User-defined functions keep
+/// a reference to the globals of the file they were defined in, so that they can be called from
+/// other files.
+#[derive(Debug, Default)]
+pub struct Globals {
+    /// Relative imports are resolved from the directory containing this file
+    path: Option<PathBuf>,
+    regs: HashMap<String, Val>,
+    mutable: HashSet<String>,
+    imports: HashMap<String, Namespace>,
+    /// Where each global `let` binding and import was made, until it's first referenced
+    unused: HashMap<String, Loc>,
+    unused_imports: HashMap<String, Loc>,
+    /// Names whose definitions failed, so that uses of them aren't reported as further errors
+    poisoned: HashSet<String>,
+    /// Names which were bound on the command line rather than with `let`, which may replace them
+    /// with the value of a `param()`
+    defined: HashSet<String>,
+}
+
+impl Globals {
+    fn new(path: Option<PathBuf>) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(Self {
+            path,
+            ..Default::default()
+        }))
+    }
+}
+
+/// Lex and parse a whole resynth source file
+fn parse_file(path: &Path) -> Result<Vec<Stmt>, Error> {
+    let src = fs::read_to_string(path)?;
+    let mut lex = Lexer::default();
+    let mut parse = Parser::default();
+
+    for (lno, line) in src.lines().enumerate() {
+        let toks = lex.line(lno + 1, line).map_err(|err| err.with_file(path))?;
+
+        for tok in toks {
+            parse.feed(&tok).map_err(|err| err.with_file(path))?;
+        }
+    }
+
+    parse
+        .feed(&EOF)
+        .map_err(|err| err.with_span(lex.loc()).with_file(path))?;
+
+    Ok(parse.get_results())
+}
 
 /// The interpreter and program-state
 pub struct Program<'a> {
     now: u64,
-    regs: HashMap<String, Val>,
-    imports: HashMap<String, &'static Module>,
-    wr: Option<PcapWriter>,
+    globals: Rc<RefCell<Globals>>,
+    scopes: Vec<Scope>,
+    /// User modules which have already been imported, by canonical path
+    modules: HashMap<PathBuf, Rc<RefCell<Globals>>>,
+    /// The files which are in the middle of being imported, for detecting circular imports
+    loading: Vec<PathBuf>,
+    wr: Option<Box<dyn CaptureWriter>>,
+    /// Record the origin of each packet as a comment on it
+    comments: bool,
+    /// The linktype was chosen on the command line, so the script can't change it
+    linktype_fixed: bool,
     loc: Loc,
     warning: Option<WarningCallback<'a>>,
+    packet: Option<PacketCallback<'a>>,
+    /// Flows which have been opened but not yet closed, and where they were opened
+    open_flows: Vec<(ObjRef, Loc)>,
+    /// Lints in loops and functions are only reported the first time around
+    linted: HashSet<(Lint, Loc)>,
+    /// Calls to user-defined functions which are in progress, outermost first
+    calls: Vec<Frame>,
+    /// Values which were defined on the command line, for `param()`
+    defines: HashMap<String, Val>,
+    unused_defines: RefCell<HashSet<String>>,
+    /// Names which have been asked for with `param()`, only these may replace a definition
+    params: HashSet<String>,
 }
 
 impl<'a> Program<'a> {
     pub fn dummy() -> Result<Self, Error> {
         Ok(Program {
             now: 0,
-            regs: HashMap::new(),
-            imports: HashMap::new(),
+            globals: Globals::new(None),
+            scopes: Vec::new(),
+            modules: HashMap::new(),
+            loading: Vec::new(),
             wr: None,
+            comments: false,
+            linktype_fixed: false,
             loc: Loc::nil(),
             warning: None,
+            packet: None,
+            open_flows: Vec::new(),
+            linted: HashSet::new(),
+            calls: Vec::new(),
+            defines: HashMap::new(),
+            unused_defines: RefCell::new(HashSet::new()),
+            params: HashSet::new(),
         })
     }
 
-    pub fn with_pcap_writer(wr: PcapWriter) -> Result<Self, Error> {
+    pub fn with_pcap_writer<W: CaptureWriter + 'static>(wr: W) -> Result<Self, Error> {
         Ok(Program {
             now: 0,
-            regs: HashMap::new(),
-            imports: HashMap::new(),
-            wr: Some(wr),
+            globals: Globals::new(None),
+            scopes: Vec::new(),
+            modules: HashMap::new(),
+            loading: Vec::new(),
+            wr: Some(Box::new(wr)),
+            comments: false,
+            linktype_fixed: false,
             loc: Loc::nil(),
             warning: None,
+            packet: None,
+            open_flows: Vec::new(),
+            linted: HashSet::new(),
+            calls: Vec::new(),
+            defines: HashMap::new(),
+            unused_defines: RefCell::new(HashSet::new()),
+            params: HashSet::new(),
         })
     }
 
@@ -58,36 +187,214 @@ impl<'a> Program<'a> {
         self.warning = Some(warning);
     }
 
-    pub fn execute(stmts: Vec<Stmt>, wr: PcapWriter) -> Result<Self, Error> {
+    /// Have each packet passed to a callback as it's emitted, along with its timestamp and the
+    /// statement which emitted it
+    pub fn set_packet_callback(&mut self, packet: PacketCallback<'a>) {
+        self.packet = Some(packet);
+    }
+
+    /// Have the writer record where each packet came from as a comment on the packet. Only some
+    /// capture formats, such as pcapng, can store comments.
+    pub fn set_comments(&mut self, comments: bool) {
+        self.comments = comments;
+    }
+
+    /// Choose the linktype of the capture file, overriding any call to `linktype()` in the script
+    pub fn set_linktype(&mut self, linktype: LinkType) -> Result<(), Error> {
+        if let Some(ref mut wr) = self.wr {
+            wr.set_linktype(linktype)?;
+        }
+        self.linktype_fixed = true;
+        Ok(())
+    }
+
+    /// Finish off the capture file, reporting any error in writing out the last of it
+    pub fn flush(&mut self) -> Result<(), Error> {
+        if let Some(ref mut wr) = self.wr {
+            wr.finish()?;
+        }
+        Ok(())
+    }
+
+    pub fn execute<W: CaptureWriter + 'static>(stmts: Vec<Stmt>, wr: W) -> Result<Self, Error> {
         let mut prog = Self::with_pcap_writer(wr)?;
         prog.add_stmts(stmts)?;
         Ok(prog)
     }
 
-    fn import(&mut self, name: &str, module: &'static Module) -> Result<(), Error> {
-        self.imports.insert(name.to_owned(), module);
-        Ok(())
+    /// Set the path of the source file which is being executed, relative imports are resolved
+    /// from the directory which contains it.
+    pub fn set_path(&mut self, path: &Path) {
+        if let Ok(canonical) = path.canonicalize() {
+            self.loading.push(canonical);
+        }
+        self.globals.borrow_mut().path = Some(path.to_owned());
+    }
+
+    /// Bind a global variable before the program runs, as with `-D name=value` on the command
+    /// line. It's also the value of any `param()` with the same name.
+    pub fn define(&mut self, name: &str, val: Val) {
+        self.defines.insert(name.to_owned(), val.clone());
+        self.unused_defines.get_mut().insert(name.to_owned());
+
+        let mut globals = self.globals.borrow_mut();
+        globals.regs.insert(name.to_owned(), val);
+        globals.defined.insert(name.to_owned());
+    }
+
+    /// Check if a name is bound in the innermost set of variables, which is where any new
+    /// bindings go
+    fn is_bound(&self, name: &str) -> bool {
+        match self.scopes.last() {
+            Some(scope) => scope.vars.contains_key(name),
+            None => self.globals.borrow().regs.contains_key(name),
+        }
     }
 
     fn store(&mut self, name: &str, val: Val) -> Result<(), Error> {
         //println!("let {} := {:?}", name, val);
         //println!();
-        self.regs.insert(name.to_owned(), val);
+        match self.scopes.last_mut() {
+            Some(scope) => scope.vars.insert(name.to_owned(), val),
+            None => self.globals.borrow_mut().regs.insert(name.to_owned(), val),
+        };
         Ok(())
     }
 
-    pub fn eval_extern_ref(&self, obj: ObjectRef) -> Result<Val, Error> {
+    fn lookup(&self, name: &str) -> Option<Val> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(val) = scope.vars.get(name) {
+                scope.unused.borrow_mut().remove(name);
+                return Some(val.clone());
+            }
+            if scope.frame {
+                break;
+            }
+        }
+
+        let mut globals = self.globals.borrow_mut();
+        globals.unused.remove(name);
+        if globals.defined.contains(name) {
+            self.unused_defines.borrow_mut().remove(name);
+        }
+        globals.regs.get(name).cloned()
+    }
+
+    fn lint(&mut self, lint: Lint, loc: Loc, msg: &str) {
+        if let Some(ref mut func) = self.warning {
+            (func)(lint, loc, msg);
+        }
+    }
+
+    /// Warn about bindings which were never referenced, each is only reported once no matter
+    /// how many times the block which contains it is executed.
+    fn lint_unused(&mut self, unused: HashMap<String, Loc>, vars: &HashMap<String, Val>) {
+        let mut unused: Vec<(String, Loc)> = unused.into_iter().collect();
+        unused.sort_by_key(|(_, loc)| (loc.line(), loc.col()));
+
+        for (name, loc) in unused {
+            let (lint, msg) = match vars.get(&name) {
+                Some(Val::Pkt(_)) | Some(Val::PktGen(_)) => (
+                    Lint::UnusedPacket,
+                    format!("packet `{}` was built but never emitted", name),
+                ),
+                _ => (Lint::UnusedVariable, format!("unused variable `{}`", name)),
+            };
+
+            if self.linted.insert((lint, loc)) {
+                self.lint(lint, loc, &msg);
+            }
+        }
+    }
+
+    /// Leave a block, linting its bindings unless it failed, since then they may not have had
+    /// the chance to be used
+    fn pop_scope(&mut self, lint: bool) {
+        let scope = self.scopes.pop().unwrap();
+        if lint {
+            self.lint_unused(scope.unused.into_inner(), &scope.vars);
+        }
+    }
+
+    /// Report anything which was left unused once the whole program has run
+    pub fn finish(&mut self) {
+        let globals = self.globals.clone();
+        let (unused, unused_imports) = {
+            let mut globals = globals.borrow_mut();
+            (
+                std::mem::take(&mut globals.unused),
+                std::mem::take(&mut globals.unused_imports),
+            )
+        };
+
+        let mut unused_imports: Vec<(String, Loc)> = unused_imports.into_iter().collect();
+        unused_imports.sort_by_key(|(_, loc)| (loc.line(), loc.col()));
+        for (name, loc) in unused_imports {
+            self.lint(
+                Lint::UnusedImport,
+                loc,
+                &format!("unused import `{}`", name),
+            );
+        }
+
+        self.lint_unused(unused, &globals.borrow().regs);
+
+        let mut unused_defines: Vec<String> = self.unused_defines.take().into_iter().collect();
+        unused_defines.sort();
+        for name in unused_defines {
+            self.lint(
+                Lint::UnusedDefine,
+                Loc::nil(),
+                &format!("`{}` is defined but never used", name),
+            );
+        }
+
+        for (_, loc) in std::mem::take(&mut self.open_flows) {
+            if !self.linted.insert((Lint::UnclosedFlow, loc)) {
+                continue;
+            }
+            self.lint(
+                Lint::UnclosedFlow,
+                loc,
+                "flow is opened here but never closed or reset",
+            );
+        }
+    }
+
+    /// Keep track of which flows are open, so that any which are never closed can be linted
+    fn track_flow(&mut self, obj: ObjRef, method: &FuncDef, loc: Loc) {
+        let (opens, closes) = {
+            let obj = obj.borrow();
+            (obj.opens(method), obj.closes(method))
+        };
+
+        if closes {
+            self.open_flows.retain(|(flow, _)| !flow.ptr_eq(&obj));
+        } else if opens && !self.open_flows.iter().any(|(flow, _)| flow.ptr_eq(&obj)) {
+            self.open_flows.push((obj, loc));
+        }
+    }
+
+    pub fn eval_extern_ref(&self, obj: &ObjectRef) -> Result<Val, Error> {
         let toplevel = &obj.modules[0];
 
         //println!("eval extern {:?}", obj);
 
         /* Lookup the first item in the imports table */
-        let mut top = match self.imports.get(toplevel) {
+        let ns = {
+            let mut globals = self.globals.borrow_mut();
+            globals.unused_imports.remove(toplevel);
+            globals.imports.get(toplevel).cloned()
+        };
+        let mut top = match ns {
+            None if self.globals.borrow().poisoned.contains(toplevel) => {
+                return Err(PoisonError(toplevel.to_owned()));
+            }
             None => {
-                println!("You have not imported {}", toplevel);
-                return Err(NameError);
+                return Err(NameError.with_msg(format!("You have not imported {}", toplevel)));
             }
-            Some(module) => module,
+            Some(Namespace::User(globals)) => return Self::eval_user_ref(&globals, obj),
+            Some(Namespace::Stdlib(module)) => module,
         };
 
         /* All the double-colon components must be submodules */
@@ -95,12 +402,10 @@ impl<'a> Program<'a> {
             top = match top.get(c) {
                 Some(Symbol::Module(module)) => module,
                 None => {
-                    println!("Can't find module component: {}", c);
-                    return Err(NameError);
+                    return Err(NameError.with_msg(format!("Can't find module component: {}", c)));
                 }
                 _ => {
-                    println!("Component is not module: {}", c);
-                    return Err(TypeError);
+                    return Err(TypeError.with_msg(format!("Component is not module: {}", c)));
                 }
             }
         }
@@ -110,48 +415,80 @@ impl<'a> Program<'a> {
             Some(Symbol::Val(valdef)) => (*valdef).into(),
             Some(Symbol::Func(fndef)) => (*fndef).into(),
             Some(Symbol::Module(_)) => {
-                println!("Component is a module, cannot be a variable: {}", topvar);
-                return Err(TypeError);
+                return Err(TypeError.with_msg(format!(
+                    "Component is a module, cannot be a variable: {}",
+                    topvar
+                )));
             }
             Some(Symbol::Class(_)) => {
-                println!("Component is a class, cannot be a variable: {}", topvar);
-                return Err(TypeError);
+                return Err(TypeError.with_msg(format!(
+                    "Component is a class, cannot be a variable: {}",
+                    topvar
+                )));
             }
             None => {
-                println!("Can't find ref component: {}", topvar);
-                return Err(NameError);
+                return Err(NameError.with_msg(format!("Can't find ref component: {}", topvar)));
             }
         };
 
         /* We only support functions and string variables in stdlib right now */
-        if obj.components.len() > 1 {
-            for c in obj.components.iter().skip(1) {
-                println!(" > comp: lookup {}", c);
-            }
-            unreachable!();
+        if let Some(c) = obj.components.get(1) {
+            return Err(TypeError.with_msg(format!("Can't lookup component {} of {}", c, topvar)));
         }
 
         Ok(ret)
     }
 
-    pub fn eval_local_ref(&self, obj: ObjectRef) -> Result<Val, Error> {
+    /// Lookup a top-level binding in an imported resynth source file
+    fn eval_user_ref(globals: &RefCell<Globals>, obj: &ObjectRef) -> Result<Val, Error> {
+        if obj.modules.len() > 1 || obj.components.len() > 2 {
+            return Err(NameError.with_msg("Too many components in object"));
+        }
+
+        let var_name = &obj.components[0];
+        let val = match globals.borrow().regs.get(var_name) {
+            Some(val) => val.clone(),
+            None => {
+                return Err(
+                    NameError.with_msg(format!("Can't find {} in {}", var_name, obj.modules[0]))
+                );
+            }
+        };
+
+        if obj.components.len() == 1 {
+            return Ok(val);
+        }
+
+        let method_name = &obj.components[1];
+        val.method_lookup(method_name)
+    }
+
+    pub fn eval_local_ref(&self, obj: &ObjectRef) -> Result<Val, Error> {
         if obj.components.len() > 2 {
-            println!("too many components in object: {:?}", obj);
-            return Err(NameError);
+            return Err(NameError.with_msg("Too many components in object"));
         }
 
         let var_name = &obj.components[0];
-        let val = self.regs.get(var_name).ok_or(NameError)?;
+        let val = match self.lookup(var_name) {
+            Some(val) => val,
+            None => match builtin(var_name) {
+                Some(Symbol::Func(fndef)) => Val::Func(fndef),
+                _ if self.globals.borrow().poisoned.contains(var_name) => {
+                    return Err(PoisonError(var_name.to_owned()));
+                }
+                _ => return Err(NameError.with_msg(format!("Can't find variable: {}", var_name))),
+            },
+        };
 
         if obj.components.len() == 1 {
-            return Ok(val.clone());
+            return Ok(val);
         }
 
         let method_name = &obj.components[1];
         val.method_lookup(method_name)
     }
 
-    pub fn eval_obj_ref(&self, obj: ObjectRef) -> Result<Val, Error> {
+    pub fn eval_obj_ref(&self, obj: &ObjectRef) -> Result<Val, Error> {
         if !obj.modules.is_empty() {
             self.eval_extern_ref(obj)
         } else if !obj.components.is_empty() {
@@ -161,13 +498,28 @@ impl<'a> Program<'a> {
         }
     }
 
-    fn eval_args(&mut self, argexprs: Vec<ArgExpr>) -> Result<Vec<ArgSpec>, Error> {
+    fn eval_args(&mut self, argexprs: &[ArgExpr]) -> Result<Vec<ArgSpec>, Error> {
         let mut ret = Vec::new();
 
         for x in argexprs {
-            let ArgExpr { name, expr } = x;
+            let ArgExpr { name, expr, splat } = x;
             let val = self.eval(expr)?;
-            ret.push(ArgSpec::new(name, val));
+
+            if !*splat {
+                ret.push(ArgSpec::new(name.clone(), val));
+                continue;
+            }
+
+            match val {
+                Val::List(items) => {
+                    ret.extend(items.iter().map(|item| ArgSpec::new(None, item.clone())));
+                }
+                other => {
+                    return Err(TypeError
+                        .with_msg(format!("Can only splat a list, not {}", other.val_type()))
+                        .with_span(expr.span()));
+                }
+            }
         }
 
         ret.shrink_to_fit();
@@ -178,18 +530,30 @@ impl<'a> Program<'a> {
         &mut self,
         func: &'static FuncDef,
         this: Option<ObjRef>,
-        argexprs: Vec<ArgExpr>,
+        argexprs: &[ArgExpr],
     ) -> Result<Val, Error> {
         //dbg!(func);
         //dbg!(&argexprs);
 
+        let loc = self.loc;
+        let flow = this.clone();
+
         let argvals = self.eval_args(argexprs)?;
         //dbg!(&argvals);
 
-        let args = func.args(this, argvals)?;
+        let args = func
+            .args(this, argvals)
+            .map_err(|err| Self::locate_arg(err, func, argexprs))?;
         //dbg!(&args);
 
         /* Finally, we're ready to make the call */
+        /* These are statics so that they can be told apart by address */
+        if std::ptr::eq(func, &PARAM) {
+            return self.param(args);
+        }
+        if std::ptr::eq(func, &LINKTYPE) {
+            return self.linktype(args);
+        }
         let ret = (func.exec)(args)?;
 
         /* This is an assert because the stdlib is not user-defined */
@@ -197,26 +561,159 @@ impl<'a> Program<'a> {
         debug_assert!(ret.val_type() == func.return_type);
         //println!();
 
+        if let Some(obj) = flow {
+            self.track_flow(obj, func, loc);
+        }
+
         Ok(ret)
     }
 
-    pub fn eval_call(&mut self, call: Call) -> Result<Val, Error> {
-        match self.eval_obj_ref(call.obj)? {
-            Val::Func(f) => self.eval_callable(f, None, call.args),
-            Val::Method(obj, f) => self.eval_callable(f, Some(obj), call.args),
-            other => {
-                println!("Not callable: {:?}", other);
-                Err(TypeError)
+    /// The builtin `param()` is the only one which depends on the program rather than just its
+    /// arguments
+    fn param(&mut self, mut args: Args) -> Result<Val, Error> {
+        let name: Buf = args.next().into();
+        let name = String::from_utf8_lossy(name.as_ref());
+        let dfl = args.next();
+
+        self.params.insert(name.to_string());
+
+        let val = match self.defines.get(name.as_ref()) {
+            Some(val) => val.clone(),
+            None => return Ok(dfl),
+        };
+        self.unused_defines.get_mut().remove(name.as_ref());
+
+        let (want, got) = (dfl.val_type(), val.val_type());
+        if want == got {
+            return Ok(val);
+        }
+        if want.is_int() && got.is_int() {
+            return val.cast(want).map_err(|err| {
+                err.with_msg(format!("param: {} is out of range for {}", name, want))
+            });
+        }
+
+        Err(TypeError
+            .with_msg(format!("param: {} should be {}, not {}", name, want, got))
+            .with_types(want, got))
+    }
+
+    fn linktype(&mut self, mut args: Args) -> Result<Val, Error> {
+        let linktype = linktype_arg(&mut args)?;
+
+        if let (false, Some(ref mut wr)) = (self.linktype_fixed, &mut self.wr) {
+            wr.set_linktype(linktype)
+                .map_err(|err| RuntimeError.with_msg(format!("linktype: {}", err)))?;
+        }
+
+        Ok(Val::Nil)
+    }
+
+    /// If binding the args failed because of a specific argument, then point at its expression
+    pub(crate) fn locate_arg<S: Signature + ?Sized>(
+        err: Error,
+        sig: &S,
+        argexprs: &[ArgExpr],
+    ) -> Error {
+        let name = match err.diag().and_then(|d| d.arg.as_deref()) {
+            Some(name) => name,
+            None => return err,
+        };
+
+        let by_name = argexprs
+            .iter()
+            .find(|arg| arg.name.as_deref() == Some(name));
+        let by_pos = sig.arg_pos(name).and_then(|idx| {
+            argexprs[..idx.min(argexprs.len())]
+                .iter()
+                .all(|arg| arg.name.is_none() && !arg.splat)
+                .then(|| argexprs.get(idx))
+                .flatten()
+                .filter(|arg| arg.name.is_none() && !arg.splat)
+        });
+
+        match by_name.or(by_pos) {
+            Some(arg) => err.with_span(arg.expr.span()),
+            None => err,
+        }
+    }
+
+    fn eval_user_call(&mut self, func: Rc<UserFunc>, argexprs: &[ArgExpr]) -> Result<Val, Error> {
+        let call = Frame {
+            func: func.name.clone(),
+            file: self.globals.borrow().path.clone(),
+            loc: self.loc,
+        };
+
+        let argvals = self.eval_args(argexprs)?;
+        let mut args = func
+            .args(None, argvals)
+            .map_err(|err| Self::locate_arg(err, func.as_ref(), argexprs))?;
+
+        if self.scopes.iter().filter(|scope| scope.frame).count() >= MAX_CALL_DEPTH {
+            args.void();
+            return Err(
+                RuntimeError.with_msg(format!("{}: Maximum call depth exceeded", func.name))
+            );
+        }
+
+        let mut frame = Scope {
+            frame: true,
+            ..Default::default()
+        };
+
+        for (name, _) in func.args.iter() {
+            frame.vars.insert(name.clone(), args.next());
+        }
+
+        /* The file which defined the function outlives any values taken from it */
+        let globals = func.globals.upgrade().unwrap();
+        let saved = std::mem::replace(&mut self.globals, globals);
+
+        self.scopes.push(frame);
+        self.calls.push(call);
+        let ret = self.exec_block(&func.body);
+        self.calls.pop();
+        self.pop_scope(ret.is_ok());
+
+        /* Errors in the body of a function which was imported from elsewhere are located in the
+         * file which defined it
+         */
+        let path = self.globals.borrow().path.clone();
+        let ret = match path {
+            Some(path) if !Rc::ptr_eq(&self.globals, &saved) => {
+                ret.map_err(|err| err.with_file(&path))
             }
+            _ => ret,
+        };
+
+        self.globals = saved;
+        ret?;
+
+        Ok(Val::Nil)
+    }
+
+    pub fn eval_call(&mut self, call: &Call) -> Result<Val, Error> {
+        match self.eval_obj_ref(&call.obj)? {
+            Val::Func(f) => self.eval_callable(f, None, &call.args),
+            Val::Method(obj, f) => self.eval_callable(f, Some(obj), &call.args),
+            Val::UserFunc(f) => self.eval_user_call(f, &call.args),
+            other => Err(TypeError.with_msg(format!("{} is not callable", other.val_type()))),
         }
     }
 
-    pub fn eval(&mut self, expr: Expr) -> Result<Val, Error> {
+    /// Evaluate an expression, any errors are located at the innermost expression which failed
+    pub fn eval(&mut self, expr: &Expr) -> Result<Val, Error> {
+        self.eval_expr(expr)
+            .map_err(|err| err.with_span(expr.span()))
+    }
+
+    fn eval_expr(&mut self, expr: &Expr) -> Result<Val, Error> {
         Ok(match expr {
             Expr::Nil => Val::Nil,
-            Expr::Literal(loc, lit) => {
-                self.loc = loc;
-                lit
+            Expr::Literal(span, lit) => {
+                self.loc = span.start;
+                lit.clone()
             }
             Expr::ObjectRef(obj) => {
                 self.loc = obj.loc;
@@ -226,23 +723,38 @@ impl<'a> Program<'a> {
                 self.loc = call.obj.loc;
                 self.eval_call(call)?
             }
-            Expr::Slash(a, b) => {
-                let a = self.eval(*a)?;
-                if !a.is_type(ValType::Ip4) {
-                    return Err(TypeError);
-                }
+            Expr::Neg(loc, operand) => {
+                let val = self.eval(operand)?;
+
+                self.loc = *loc;
+
+                ops::neg(val)?
+            }
+            Expr::BinOp(loc, op, a, b) => {
+                let a = self.eval(a)?;
+                let b = self.eval(b)?;
 
-                let a_loc = self.loc;
+                self.loc = *loc;
 
-                let b = self.eval(*b)?;
-                if !b.is_integral() {
-                    return Err(TypeError);
+                op.eval(a, b)?
+            }
+            Expr::List(span, exprs) => {
+                let mut items = Vec::with_capacity(exprs.len());
+                for expr in exprs {
+                    items.push(self.eval(expr)?);
                 }
 
-                /* TODO: Perhaps use location of the operator? */
-                self.loc = a_loc;
+                self.loc = span.start;
+
+                Val::from(items)
+            }
+            Expr::Index(span, target, index) => {
+                let target = self.eval(target)?;
+                let index = self.eval(index)?;
+
+                self.loc = span.start;
 
-                Val::Sock4(SocketAddrV4::new(a.into(), b.into()))
+                ops::index(target, index)?
             }
         })
     }
@@ -255,52 +767,326 @@ impl<'a> Program<'a> {
         Ok(())
     }
 
+    /// Execute a top-level statement. If it fails then whatever it was going to bind is
+    /// [poisoned](Self::poison), so the program can carry on and report any unrelated errors.
     pub fn add_stmt(&mut self, stmt: Stmt) -> Result<(), Error> {
         //println!("{:?}", stmt);
-        match stmt {
+        let (name, ret) = match stmt {
             //Stmt::Nop => self,
-            Stmt::Import(import) => self.add_import(import)?,
+            Stmt::Import(import) => (import.module.clone(), self.add_import(import)),
+            Stmt::Func(func) => (func.name.clone(), self.add_func(func)),
+            Stmt::Assign(assign) => (assign.target.clone(), self.add_assign(&assign)),
+            stmt => return self.exec_stmt(&stmt),
+        };
+
+        if ret.is_err() {
+            self.poison(&name);
+        }
+
+        ret
+    }
+
+    /// Mark a name as being undefined because of an earlier error. Any use of it fails
+    /// with a [PoisonError], which shouldn't be reported to the user.
+    pub fn poison(&mut self, name: &str) {
+        let mut globals = self.globals.borrow_mut();
+        if !globals.regs.contains_key(name) && !globals.imports.contains_key(name) {
+            globals.poisoned.insert(name.to_owned());
+        }
+    }
+
+    /// Execute a statement which may appear inside of a block
+    fn exec_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
+        match stmt {
             Stmt::Assign(assign) => self.add_assign(assign)?,
+            Stmt::Reassign(assign) => self.add_reassign(assign)?,
             Stmt::Expr(expr) => self.add_expr(expr)?,
+            Stmt::For(stmt) => self.add_for(stmt)?,
+            Stmt::If(stmt) => self.add_if(stmt)?,
+            Stmt::Import(_) | Stmt::Func(_) => {
+                /* The parser only allows these at the top-level */
+                unreachable!();
+            }
         };
         Ok(())
     }
 
+    fn exec_block(&mut self, stmts: &[Stmt]) -> Result<(), Error> {
+        for stmt in stmts {
+            self.exec_stmt(stmt)?;
+        }
+
+        Ok(())
+    }
+
     pub fn add_import(&mut self, import: Import) -> Result<(), Error> {
         let name = &import.module;
 
         self.loc = import.loc;
 
-        if self.imports.contains_key(name) {
-            println!("Multiple imports of {:?}", name);
+        if self.globals.borrow().imports.contains_key(name) {
+            self.lint(
+                Lint::UnusedImport,
+                import.loc,
+                &format!("`{}` is already imported", name),
+            );
             return Ok(());
         }
 
-        match toplevel_module(name) {
-            None => {
-                return Err(ImportError(name.to_owned()));
+        let ns = match &import.path {
+            Some(path) => {
+                let ret = self.load_module(path);
+                self.loc = import.loc;
+                Namespace::User(
+                    ret.map_err(|err| err.with_span(Span::word(import.loc, path.len() + 2)))?,
+                )
             }
-            Some(module) => {
-                self.import(name, module)?;
+            None => match toplevel_module(name) {
+                None => {
+                    return Err(
+                        ImportError(name.to_owned()).with_span(Span::word(import.loc, name.len()))
+                    );
+                }
+                Some(module) => Namespace::Stdlib(module),
+            },
+        };
+
+        let mut globals = self.globals.borrow_mut();
+        globals.imports.insert(name.to_owned(), ns);
+        globals.unused_imports.insert(name.to_owned(), import.loc);
+
+        Ok(())
+    }
+
+    /// Execute a resynth source file in its own namespace. Each file is only ever executed once,
+    /// no matter how many times it's imported.
+    fn load_module(&mut self, spec: &str) -> Result<Rc<RefCell<Globals>>, Error> {
+        let path = match &self.globals.borrow().path {
+            Some(importer) => importer.parent().unwrap_or(Path::new("")).join(spec),
+            None => PathBuf::from(spec),
+        };
+
+        let canonical = path.canonicalize().map_err(|_| {
+            ImportError(spec.to_owned()).with_msg(format!("Can't find {}", path.display()))
+        })?;
+
+        if self.loading.contains(&canonical) {
+            return Err(CircularImportError(spec.to_owned()));
+        }
+
+        if let Some(globals) = self.modules.get(&canonical) {
+            return Ok(globals.clone());
+        }
+
+        let stmts = parse_file(&path)?;
+
+        let globals = Globals::new(Some(path.clone()));
+        let saved = std::mem::replace(&mut self.globals, globals.clone());
+
+        self.loading.push(canonical.clone());
+        let ret = self.add_stmts(stmts);
+        self.loading.pop();
+
+        self.globals = saved;
+
+        if let Err(err) = ret {
+            return Err(err.with_span(self.loc).with_file(&path));
+        }
+
+        self.modules.insert(canonical, globals.clone());
+
+        Ok(globals)
+    }
+
+    pub fn add_for(&mut self, stmt: &For) -> Result<(), Error> {
+        self.loc = stmt.loc;
+
+        /* Replayed packets keep their spacing from wherever the loop started */
+        let start = self.now;
+        let items: Rc<Vec<Val>> = match self.eval(&stmt.iter)? {
+            val @ Val::List(..) => val.into(),
+            Val::PktGen(gen) => Rc::new(gen.iter().cloned().map(Val::from).collect()),
+            other => {
+                return Err(TypeError
+                    .with_msg(format!("Not iterable: {}", other.val_type()))
+                    .with_span(stmt.iter.span()));
             }
+        };
+
+        for item in items.iter() {
+            if let Val::Pkt(pkt) = item {
+                if let Some(off) = pkt.meta().offset {
+                    self.now = self.now.max(start + off);
+                }
+            }
+
+            /* Each iteration gets a fresh scope so that the body can use let */
+            let mut scope = Scope::default();
+            scope.vars.insert(stmt.var.clone(), item.clone());
+
+            self.scopes.push(scope);
+            let ret = self.exec_block(&stmt.body);
+            self.pop_scope(ret.is_ok());
+            ret?;
         }
 
         Ok(())
     }
 
-    pub fn add_assign(&mut self, assign: Assign) -> Result<(), Error> {
+    pub fn add_if(&mut self, stmt: &If) -> Result<(), Error> {
+        self.loc = stmt.loc;
+
+        let cond = match self.eval(&stmt.cond)? {
+            Val::Bool(b) => b,
+            other => {
+                return Err(TypeError
+                    .with_msg(format!("Condition must be bool, not {}", other.val_type()))
+                    .with_types(ValType::Bool, other.val_type())
+                    .with_span(stmt.cond.span()));
+            }
+        };
+
+        let body = if cond { &stmt.body } else { &stmt.orelse };
+
+        self.scopes.push(Scope::default());
+        let ret = self.exec_block(body);
+        self.pop_scope(ret.is_ok());
+
+        ret
+    }
+
+    pub fn add_func(&mut self, func: Func) -> Result<(), Error> {
+        let Func {
+            loc,
+            name,
+            params,
+            body,
+        } = func;
+
+        self.loc = loc;
+
+        if self.is_bound(&name) {
+            let span = Span::word(loc, name.len());
+            return Err(MultipleAssignError(name).with_span(span));
+        }
+
+        /* Default values are evaluated once, at the point of definition */
+        let mut args = Vec::with_capacity(params.len());
+        for param in params {
+            let dfl = match param.dfl {
+                Some(expr) => Some(self.eval(&expr)?),
+                None => None,
+            };
+            args.push((param.name, dfl));
+        }
+
+        self.loc = loc;
+
+        let globals = Rc::downgrade(&self.globals);
+        let func = UserFunc::new(loc, name.clone(), args, body, globals);
+        self.store(&name, Val::UserFunc(Rc::new(func)))
+    }
+
+    pub fn add_assign(&mut self, assign: &Assign) -> Result<(), Error> {
         let name = &assign.target;
 
         self.loc = assign.loc;
 
-        if self.regs.contains_key(name) {
-            return Err(MultipleAssignError(name.to_owned()));
+        /* A definition from the command line can only be replaced by the value of a param() */
+        let defined = self.scopes.is_empty() && self.globals.borrow_mut().defined.remove(name);
+
+        if !defined && self.is_bound(name) {
+            let span = Span::word(assign.loc, name.len());
+            return Err(MultipleAssignError(name.to_owned()).with_span(span));
         }
 
-        let val = self.eval(assign.rvalue)?;
+        let ret = self.eval(&assign.rvalue).and_then(|val| {
+            if defined && !self.params.contains(name) {
+                let span = Span::word(assign.loc, name.len());
+                return Err(MultipleAssignError(name.to_owned())
+                    .with_msg(format!(
+                        "`{}` is defined on the command line, use param() to give it a default",
+                        name
+                    ))
+                    .with_span(span));
+            }
+            Ok(val)
+        });
+        let val = match ret {
+            Ok(val) => val,
+            Err(err) => {
+                /* Otherwise the definition would hide the error from later uses */
+                if defined {
+                    self.globals.borrow_mut().regs.remove(name);
+                }
+                return Err(err);
+            }
+        };
 
         self.store(name, val)?;
 
+        if !name.starts_with('_') {
+            let unused = (name.to_owned(), assign.loc);
+            match self.scopes.last_mut() {
+                Some(scope) => scope.unused.get_mut().insert(unused.0, unused.1),
+                None => self.globals.borrow_mut().unused.insert(unused.0, unused.1),
+            };
+        }
+
+        if assign.mutable {
+            match self.scopes.last_mut() {
+                Some(scope) => scope.mutable.insert(name.to_owned()),
+                None => self.globals.borrow_mut().mutable.insert(name.to_owned()),
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Find the set of variables in which a name is bound, resolving it in the same way as
+    /// [lookup](Self::lookup) does. Returns `None` for the globals.
+    fn binding_scope(&self, name: &str) -> Result<Option<usize>, Error> {
+        for (i, scope) in self.scopes.iter().enumerate().rev() {
+            if scope.vars.contains_key(name) {
+                return match scope.mutable.contains(name) {
+                    true => Ok(Some(i)),
+                    false => Err(MultipleAssignError(name.to_owned())),
+                };
+            }
+            if scope.frame {
+                break;
+            }
+        }
+
+        let globals = self.globals.borrow();
+        if globals.poisoned.contains(name) {
+            return Err(PoisonError(name.to_owned()));
+        }
+        if !globals.regs.contains_key(name) {
+            return Err(NameError.with_msg(format!("Can't find variable: {}", name)));
+        }
+        match globals.mutable.contains(name) {
+            true => Ok(None),
+            false => Err(MultipleAssignError(name.to_owned())),
+        }
+    }
+
+    /// Rebind an existing variable which was declared with `let mut`
+    pub fn add_reassign(&mut self, assign: &Assign) -> Result<(), Error> {
+        let name = &assign.target;
+
+        self.loc = assign.loc;
+
+        let idx = self
+            .binding_scope(name)
+            .map_err(|err| err.with_span(Span::word(assign.loc, name.len())))?;
+        let val = self.eval(&assign.rvalue)?;
+
+        match idx {
+            Some(i) => self.scopes[i].vars.insert(name.to_owned(), val),
+            None => self.globals.borrow_mut().regs.insert(name.to_owned(), val),
+        };
+
         Ok(())
     }
 
@@ -310,43 +1096,137 @@ impl<'a> Program<'a> {
         self.now += ns;
     }
 
-    pub fn add_expr(&mut self, expr: Expr) -> Result<(), Error> {
+    /// Execute a top-level statement, returning the value of it if it was an expression. Packets
+    /// are emitted as usual, but other values are handed back instead of being warned about, which
+    /// is what's wanted when running interactively.
+    pub fn eval_stmt(&mut self, stmt: Stmt) -> Result<Option<Val>, Error> {
+        match stmt {
+            Stmt::Expr(expr) => {
+                let val = self.eval(&expr)?;
+                self.emit(&val, expr.span().start)
+                    .map_err(|err| err.with_span(expr.span()))?;
+                Ok(Some(val))
+            }
+            stmt => self.add_stmt(stmt).map(|_| None),
+        }
+    }
+
+    /// The current timestamp, in nanoseconds
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    pub fn add_expr(&mut self, expr: &Expr) -> Result<(), Error> {
         let val = self.eval(expr)?;
-        match val {
+        let emitted = self
+            .emit(&val, expr.span().start)
+            .map_err(|err| err.with_span(expr.span()))?;
+        if !emitted {
+            self.lint(
+                Lint::DiscardedValue,
+                self.loc,
+                &format!("discarded value {:?}", val),
+            );
+        }
+        Ok(())
+    }
+
+    /// Where a packet emitted by the statement at `loc` came from
+    fn origin(&self, loc: Loc) -> Origin {
+        Origin {
+            file: self.globals.borrow().path.clone(),
+            loc,
+            calls: self.calls.iter().rev().cloned().collect(),
+        }
+    }
+
+    /// Packets which can't be represented in the capture's linktype are a type error, anything
+    /// else is a genuine I/O error
+    fn write_error(err: io::Error) -> Error {
+        match err.kind() {
+            io::ErrorKind::InvalidData => TypeError.with_msg(err.to_string()),
+            _ => err.into(),
+        }
+    }
+
+    /// Write out the packets in a value, or advance time. Returns false if the value was of a
+    /// type which has no effect when it's discarded.
+    fn emit(&mut self, val: &Val, loc: Loc) -> Result<bool, Error> {
+        match val.clone() {
             Val::Nil => {}
             Val::Pkt(mut ptr) => {
+                /* A replayed packet is sent as soon as it's due, which a for loop over the
+                 * capture takes care of. The rest go after the time it takes to send them. */
+                let due = self.now;
                 self.update_time(ptr.bit_time());
+                let time = match ptr.meta().offset {
+                    Some(_) => due,
+                    None => self.now,
+                };
+
+                let origin = (self.packet.is_some() || self.comments).then(|| self.origin(loc));
+
+                if let (Some(ref mut func), Some(origin)) = (&mut self.packet, &origin) {
+                    (func)(time, &ptr, origin);
+                }
+
+                let comment = origin.filter(|_| self.comments).map(|o| o.to_string());
 
                 /* XXX: cloning the packet here is wasteful */
                 if let Some(ref mut wr) = self.wr {
                     let pkt = Rc::make_mut(&mut ptr);
 
-                    wr.write_packet(self.now, pkt)
-                        .expect("failed to write packet");
+                    if let Some(comment) = comment {
+                        pkt.meta_mut().comments.push(comment);
+                    }
+
+                    wr.write_packet(time, pkt).map_err(Self::write_error)?;
                 };
             }
             Val::PktGen(mut gen) => {
+                let start = self.now;
+
                 for pkt in gen.iter() {
                     self.update_time(pkt.bit_time());
                 }
 
+                /* Replayed packets keep their own spacing, the rest all go out together */
+                let times: Vec<u64> = gen
+                    .iter()
+                    .map(|pkt| pkt.meta().offset.map_or(self.now, |off| start + off))
+                    .collect();
+                for (pkt, time) in gen.iter().zip(times.iter()) {
+                    if pkt.meta().offset.is_some() {
+                        self.now = self.now.max(time + pkt.bit_time());
+                    }
+                }
+
+                let origin = (self.packet.is_some() || self.comments).then(|| self.origin(loc));
+
+                if let (Some(ref mut func), Some(origin)) = (&mut self.packet, &origin) {
+                    for (pkt, time) in gen.iter().zip(times.iter()) {
+                        (func)(*time, pkt, origin);
+                    }
+                }
+
+                let comment = origin.filter(|_| self.comments).map(|o| o.to_string());
+
                 /* XXX: cloning the packets here is wasteful */
                 if let Some(ref mut wr) = self.wr {
                     let inner = Rc::make_mut(&mut gen);
 
-                    for pkt in inner {
-                        wr.write_packet(self.now, pkt)
-                            .expect("failed to write packet");
+                    for (pkt, time) in inner.iter_mut().zip(times) {
+                        if let Some(ref comment) = comment {
+                            pkt.meta_mut().comments.push(comment.clone());
+                        }
+
+                        wr.write_packet(time, pkt).map_err(Self::write_error)?;
                     }
                 };
             }
             Val::TimeJump(ns) => self.update_time(ns),
-            _ => {
-                if let Some(ref mut func) = self.warning {
-                    (func)(self.loc, &format!("discarded value {:?}", val));
-                }
-            }
+            _ => return Ok(false),
         };
-        Ok(())
+        Ok(true)
     }
 }
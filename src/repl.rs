@@ -0,0 +1,241 @@
+//! An interactive shell which keeps a live [Program], printing the value of each expression and
+//! a hex dump of every packet as it's emitted.
+
+use crate::{report_error, ErrorLoc};
+
+use pkt::{Packet, PcapWriter};
+
+use resynth::stdlib::{complete, write_symbol_docs};
+use resynth::{error, ok, warn};
+use resynth::{Error, Lexer, Lint, Loc, Origin, Parser, Program, Val};
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::path::Path;
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+
+const HELP: &str = "\
+Enter resynth statements, each terminated with a semicolon. The value of each expression is
+printed, and any packets are kept so that they can be saved.
+
+:doc <path>    Show the documentation for a stdlib module, class or function
+:save <file>   Write every packet so far to a pcap file
+:help          Show this message
+:quit          Exit
+";
+
+/// Tab completion over the stdlib symbol tables
+struct StdlibHelper;
+
+impl Completer for StdlibHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let head = &line[..pos];
+        let start = head
+            .trim_end_matches(|c: char| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+            .len();
+
+        Ok((start, complete(&head[start..])))
+    }
+}
+
+impl Hinter for StdlibHelper {
+    type Hint = String;
+}
+
+impl Highlighter for StdlibHelper {}
+impl Validator for StdlibHelper {}
+impl Helper for StdlibHelper {}
+
+/// Write out the packets which have been emitted so far
+fn save(out: &Path, packets: &mut [(u64, Packet)]) -> Result<(), io::Error> {
+    let mut wr = PcapWriter::create(out)?;
+
+    for (ts, pkt) in packets.iter_mut() {
+        wr.write_packet(*ts, pkt)?;
+    }
+
+    Ok(())
+}
+
+/// Handle a `:command`, returns false if it's time to quit
+fn command(stdout: &mut StandardStream, cmd: &str, packets: &RefCell<Vec<(u64, Packet)>>) -> bool {
+    let (cmd, arg) = match cmd.split_once(char::is_whitespace) {
+        Some((cmd, arg)) => (cmd, arg.trim()),
+        None => (cmd, ""),
+    };
+
+    match (cmd, arg) {
+        ("q" | "quit", _) => return false,
+        ("h" | "help", _) => print!("{}", HELP),
+        ("doc", "") | ("save", "") => {
+            error!(stdout, "error");
+            println!(": :{} needs an argument", cmd);
+        }
+        ("doc", path) => match write_symbol_docs(path, &mut io::stdout().lock()) {
+            Ok(true) => {}
+            Ok(false) => {
+                error!(stdout, "error");
+                println!(": no such symbol: {}", path);
+            }
+            Err(err) => {
+                error!(stdout, "error");
+                println!(": {}", err);
+            }
+        },
+        ("save", out) => {
+            let mut packets = packets.borrow_mut();
+            match save(Path::new(out), &mut packets) {
+                Ok(()) => {
+                    let count = packets.len();
+                    print!(
+                        "{} packet{} -> {} ",
+                        count,
+                        if count == 1 { "" } else { "s" },
+                        out
+                    );
+                    ok!(stdout, "ok");
+                    println!();
+                }
+                Err(err) => {
+                    error!(stdout, "error");
+                    println!(": {}: {}", out, err);
+                }
+            }
+        }
+        _ => {
+            error!(stdout, "error");
+            println!(": unknown command :{}, try :help", cmd);
+        }
+    }
+
+    true
+}
+
+pub fn repl(color: ColorChoice) -> Result<(), ()> {
+    let mut stdout = StandardStream::stdout(color);
+    let mut warn_out = StandardStream::stdout(color);
+    let name = Path::new("<repl>");
+
+    let mut rl: Editor<StdlibHelper, DefaultHistory> = match Editor::new() {
+        Ok(rl) => rl,
+        Err(err) => {
+            error!(stdout, "error");
+            println!(": {}", err);
+            return Err(());
+        }
+    };
+    rl.set_helper(Some(StdlibHelper));
+
+    let packets = RefCell::new(Vec::new());
+    let mut packet = |ts: u64, pkt: &Packet, _: &Origin| {
+        println!("{:#?}", pkt);
+        packets.borrow_mut().push((ts, pkt.clone()));
+    };
+
+    let mut warning = |lint: Lint, loc: Loc, msg: &str| {
+        print!("{}:{}:{}: ", name.display(), loc.line(), loc.col());
+        warn!(warn_out, "warning");
+        println!(": {} [{}]", msg, lint);
+    };
+
+    let mut prog = Program::dummy().expect("create program");
+    prog.set_packet_callback(&mut packet);
+    prog.set_warning(&mut warning);
+
+    let mut lex = Lexer::default();
+    let mut parse = Parser::default();
+
+    /* Everything which has been entered, so that errors can quote it */
+    let mut src = String::new();
+    let mut lno = 0;
+
+    println!("resynth {}, type :help for help", clap::crate_version!());
+
+    loop {
+        let prompt = if parse.is_idle() { ">>> " } else { "... " };
+        let line = match rl.readline(prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => {
+                parse = Parser::default();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(err) => {
+                error!(stdout, "error");
+                println!(": {}", err);
+                return Err(());
+            }
+        };
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let _ = rl.add_history_entry(line.as_str());
+
+        if parse.is_idle() {
+            if let Some(cmd) = line.trim().strip_prefix(':') {
+                if !command(&mut stdout, cmd, &packets) {
+                    break;
+                }
+                continue;
+            }
+        }
+
+        lno += 1;
+        src.push_str(&line);
+        src.push('\n');
+
+        let mut errors = Vec::new();
+
+        match lex.line(lno, &line) {
+            Ok(toks) => {
+                for tok in toks {
+                    if let Err(err) = parse.feed(&tok) {
+                        errors.push(ErrorLoc::new(tok.loc(), err));
+                    }
+                }
+            }
+            Err(err) => errors.push(ErrorLoc::new(lex.loc(), err)),
+        }
+
+        parse.flush();
+
+        for name in parse.get_poisoned() {
+            prog.poison(&name);
+        }
+
+        for stmt in parse.get_results() {
+            match prog.eval_stmt(stmt) {
+                /* Packets have already been printed as they were emitted */
+                Ok(Some(Val::Pkt(_) | Val::PktGen(_) | Val::Nil)) | Ok(None) => {}
+                Ok(Some(val)) => println!("{:?}", val),
+                Err(err) if matches!(err.kind(), Error::PoisonError(_)) => {}
+                Err(err) => errors.push(ErrorLoc::new(prog.loc(), err)),
+            }
+        }
+
+        for error in errors {
+            report_error(&mut stdout, name, Some(&src), error);
+        }
+
+        io::stdout().flush().ok();
+    }
+
+    Ok(())
+}
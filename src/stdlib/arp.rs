@@ -1,7 +1,6 @@
 use pkt::arp::hrd;
 
 use crate::libapi::Module;
-use crate::module;
 use crate::sym::Symbol;
 
 const HRD: Module = module!(
@@ -0,0 +1,248 @@
+use crate::args::Args;
+use crate::err::Error;
+use crate::err::Error::{AssertionError, RuntimeError};
+use crate::libapi::{FuncDef, Module};
+use crate::str::Buf;
+use crate::sym::Symbol;
+use crate::val::{Val, ValDef};
+
+use pkt::LinkType;
+
+use std::rc::Rc;
+
+/// The whole list is built up front, so it can't be arbitrarily long
+const MAX_RANGE: u64 = 1 << 24;
+
+const RANGE: FuncDef = func!(
+    /// Return a list of integers from `start` up to, but not including, `end`. Mostly useful in
+    /// `for` loops, eg. `for port in range(1024, 2048) { ... }`
+    ///
+    /// ### Arguments
+    /// * `step: u64` The difference between each successive integer
+    resynth fn range(
+        start: U64,
+        end: U64,
+        =>
+        step: U64 = 1,
+        =>
+        Void
+    ) -> List
+    |mut args| {
+        let start: u64 = args.next().into();
+        let end: u64 = args.next().into();
+        let step: u64 = args.next().into();
+
+        if step == 0 {
+            return Err(RuntimeError.with_msg("range: step must not be zero"));
+        }
+
+        let count = end.saturating_sub(start).div_ceil(step);
+        if count > MAX_RANGE {
+            return Err(RuntimeError.with_msg(format!(
+                "range: {} items is too many, the limit is {}",
+                count, MAX_RANGE
+            )));
+        }
+
+        let items: Vec<Val> = (start..end).step_by(step as usize).map(Val::U64).collect();
+
+        Ok(Val::from(items))
+    }
+);
+
+const LEN: FuncDef = func!(
+    /// Return the number of items in a list
+    resynth fn len(
+        items: List,
+        =>
+        =>
+        Void
+    ) -> U64
+    |mut args| {
+        let items: Rc<Vec<Val>> = args.next().into();
+        Ok(Val::U64(items.len() as u64))
+    }
+);
+
+const ASSERT: FuncDef = func!(
+    /// Fail with an `AssertionError` if `cond` is false, so that a script can check its own
+    /// assumptions, eg. `assert(len(pool) > 2, "need at least three servers");`
+    ///
+    /// ### Arguments
+    /// * `msg: Str` Explains what went wrong
+    resynth fn assert(
+        cond: Bool,
+        =>
+        msg: Type = ValType::Str,
+        =>
+        Void
+    ) -> Void
+    |mut args| {
+        let cond: bool = args.next().into();
+        let msg: Option<Buf> = args.next().into();
+
+        if cond {
+            return Ok(Val::Nil);
+        }
+
+        Err(match msg {
+            Some(msg) => AssertionError.with_msg(String::from_utf8_lossy(msg.as_ref())),
+            None => AssertionError,
+        })
+    }
+);
+
+pub(crate) static PARAM: FuncDef = func!(
+    /// The value of a parameter which can be set on the command line with `-D name=value`, or
+    /// `default` if it wasn't. eg. `let server = param("server", 192.168.0.1);`
+    ///
+    /// Integer values are converted to the type of the default, otherwise the types must match.
+    resynth fn param(
+        name: Str,
+        default: Any,
+        =>
+        =>
+        Void
+    ) -> Any
+    |mut args| {
+        /* The program handles command-line definitions, so this only happens without one */
+        args.next();
+        Ok(args.next())
+    }
+);
+
+/// The linktype named by the argument to `linktype()`
+pub(crate) fn linktype_arg(args: &mut Args) -> Result<LinkType, Error> {
+    let name: Buf = args.next().into();
+    let name = String::from_utf8_lossy(name.as_ref());
+
+    LinkType::from_name(&name).ok_or_else(|| {
+        RuntimeError.with_msg(format!(
+            "linktype: unknown linktype `{}`, \
+            expected null, ethernet, raw, linux_sll or linux_sll2",
+            name
+        ))
+    })
+}
+
+pub(crate) static LINKTYPE: FuncDef = func!(
+    /// Choose the link-layer header type of the capture file, this must be done before any
+    /// packets are written. Packets are converted to suit it, eg. `linktype("raw")` strips
+    /// ethernet headers, and `linktype("linux_sll")` replaces them with a Linux cooked header.
+    /// The `--linktype` command-line option takes precedence.
+    ///
+    /// ### Arguments
+    /// * `name: Str` One of `null`, `ethernet`, `raw`, `linux_sll` or `linux_sll2`
+    resynth fn linktype(
+        name: Str,
+        =>
+        =>
+        Void
+    ) -> Void
+    |mut args| {
+        /* The program handles setting it, so this only happens with no capture file */
+        linktype_arg(&mut args)?;
+        Ok(Val::Nil)
+    }
+);
+
+/// Integer conversions are all the same apart from their return type
+macro_rules! cast {
+    ($(#[doc = $doc:literal])+ $name:ident => $typ:ident) => {
+        func!(
+            $(#[doc = $doc])+
+            resynth fn $name(
+                val: Any,
+                =>
+                =>
+                Void
+            ) -> $typ
+            |mut args| {
+                let val = args.next();
+                let from = $crate::val::Typed::val_type(&val);
+                let (min, max) = ValType::$typ.int_range().unwrap();
+                val.cast(ValType::$typ).map_err(|err| match val.as_int() {
+                    Some(int) => err.with_msg(format!(
+                        "{}: {} is out of range {}..={}",
+                        stringify!($name),
+                        int,
+                        min,
+                        max,
+                    )),
+                    None => err
+                        .with_msg(format!("{}: can't convert {}", stringify!($name), from))
+                        .with_types(ValType::$typ, from),
+                })
+            }
+        )
+    };
+}
+
+/// Whether a function is one of the integer casts, such as `u8()`
+pub(crate) fn is_cast(func: &FuncDef) -> bool {
+    [&U8, &U16, &U32, &U64, &I8, &I16, &I32, &I64]
+        .iter()
+        .any(|cast| ::std::ptr::eq(*cast, func))
+}
+
+static U8: FuncDef = cast!(
+    /// Convert an integer or bool to `u8`, it's an error if the value is out of range
+    u8 => U8
+);
+
+static U16: FuncDef = cast!(
+    /// Convert an integer or bool to `u16`, it's an error if the value is out of range
+    u16 => U16
+);
+
+static U32: FuncDef = cast!(
+    /// Convert an integer or bool to `u32`, it's an error if the value is out of range
+    u32 => U32
+);
+
+static U64: FuncDef = cast!(
+    /// Convert an integer or bool to `u64`, it's an error if the value is out of range
+    u64 => U64
+);
+
+static I8: FuncDef = cast!(
+    /// Convert an integer or bool to `i8`, it's an error if the value is out of range
+    i8 => I8
+);
+
+static I16: FuncDef = cast!(
+    /// Convert an integer or bool to `i16`, it's an error if the value is out of range
+    i16 => I16
+);
+
+static I32: FuncDef = cast!(
+    /// Convert an integer or bool to `i32`, it's an error if the value is out of range
+    i32 => I32
+);
+
+static I64: FuncDef = cast!(
+    /// Convert an integer or bool to `i64`, it's an error if the value is out of range
+    i64 => I64
+);
+
+pub const MODULE: Module = module! {
+    /// # Builtin Functions
+    ///
+    /// These functions are always available without needing to be imported or qualified with a
+    /// module name.
+    resynth mod builtin {
+        assert => Symbol::Func(&ASSERT),
+        len => Symbol::Func(&LEN),
+        linktype => Symbol::Func(&LINKTYPE),
+        param => Symbol::Func(&PARAM),
+        range => Symbol::Func(&RANGE),
+        u8 => Symbol::Func(&U8),
+        u16 => Symbol::Func(&U16),
+        u32 => Symbol::Func(&U32),
+        u64 => Symbol::Func(&U64),
+        i8 => Symbol::Func(&I8),
+        i16 => Symbol::Func(&I16),
+        i32 => Symbol::Func(&I32),
+        i64 => Symbol::Func(&I64),
+    }
+};
@@ -4,11 +4,13 @@ use std::io::Read;
 use std::os::unix::ffi::OsStrExt;
 use std::path::Path;
 
-use crate::func;
+use pkt::{LinkType, Packet, PcapReader};
+
+use crate::err::Error::{IndexError, RuntimeError};
 use crate::libapi::{Class, ClassDef, FuncDef, Module};
 use crate::str::Buf;
 use crate::sym::Symbol;
-use crate::val::Val;
+use crate::val::{Val, ValDef};
 
 const IO_FILE: FuncDef = func!(
     /// Load the contents of a file into a string
@@ -128,11 +130,185 @@ const BUFIO: FuncDef = func!(
     }
 );
 
+/// The packets in a capture file, with their timestamps in nanoseconds
+#[derive(Debug, PartialEq, Eq, Default)]
+struct PcapFile {
+    pkts: Vec<(u64, Packet)>,
+}
+
+impl PcapFile {
+    /// Packets are timed relative to the first one, so that they're replayed starting from
+    /// whenever they're emitted
+    fn replay(pkts: &[(u64, Packet)]) -> Val {
+        let start = pkts.first().map_or(0, |(time, _)| *time);
+
+        pkts.iter()
+            .map(|(time, pkt)| {
+                let mut pkt = pkt.clone();
+                pkt.meta_mut().offset = Some(time.saturating_sub(start));
+                pkt
+            })
+            .collect::<Vec<Packet>>()
+            .into()
+    }
+}
+
+const PCAP_PACKETS: FuncDef = func!(
+    /// All of the packets, which keep their original spacing in time when they're emitted
+    resynth fn packets(
+        =>
+        =>
+        Void
+    ) -> PktGen
+    |mut args| {
+        let obj = args.take_this();
+        let mut r = obj.borrow_mut();
+        let this: &mut PcapFile = r.as_mut_any().downcast_mut().unwrap();
+        Ok(PcapFile::replay(&this.pkts))
+    }
+);
+
+const PCAP_SLICE: FuncDef = func!(
+    /// The packets from index `start` up to, but not including, `end`. The first packet in the
+    /// file is index 0.
+    ///
+    /// ### Arguments
+    /// * `end: u64` Defaults to the end of the file
+    resynth fn slice(
+        start: U64,
+        =>
+        end: Type = ValType::U64,
+        =>
+        Void
+    ) -> PktGen
+    |mut args| {
+        let obj = args.take_this();
+        let mut r = obj.borrow_mut();
+        let this: &mut PcapFile = r.as_mut_any().downcast_mut().unwrap();
+        let len = this.pkts.len() as u64;
+        let start: u64 = args.next().into();
+        let end: Option<u64> = args.next().into();
+        let end = end.unwrap_or(len);
+
+        if start > end || end > len {
+            return Err(IndexError.with_msg(format!(
+                "slice: {}..{} out of range for {} packets",
+                start, end, len
+            )));
+        }
+
+        Ok(PcapFile::replay(&this.pkts[start as usize..end as usize]))
+    }
+);
+
+const PCAP_BETWEEN: FuncDef = func!(
+    /// The packets captured from `start_ms` up to, but not including, `end_ms` milliseconds after
+    /// the first packet in the file
+    ///
+    /// ### Arguments
+    /// * `end_ms: u64` Defaults to the end of the file
+    resynth fn between(
+        start_ms: U64,
+        =>
+        end_ms: Type = ValType::U64,
+        =>
+        Void
+    ) -> PktGen
+    |mut args| {
+        let obj = args.take_this();
+        let mut r = obj.borrow_mut();
+        let this: &mut PcapFile = r.as_mut_any().downcast_mut().unwrap();
+        let first = this.pkts.first().map_or(0, |(time, _)| *time);
+        let start: u64 = args.next().into();
+        let end: Option<u64> = args.next().into();
+
+        let start = first.saturating_add(start.saturating_mul(1_000_000));
+        let end = end.map_or(u64::MAX, |ms| first.saturating_add(ms.saturating_mul(1_000_000)));
+
+        let pkts: Vec<(u64, Packet)> = this
+            .pkts
+            .iter()
+            .filter(|(time, _)| (start..end).contains(time))
+            .cloned()
+            .collect();
+
+        Ok(PcapFile::replay(&pkts))
+    }
+);
+
+const PCAP_COUNT: FuncDef = func!(
+    /// The number of packets in the file
+    resynth fn count(
+        =>
+        =>
+        Void
+    ) -> U64
+    |mut args| {
+        let obj = args.take_this();
+        let mut r = obj.borrow_mut();
+        let this: &mut PcapFile = r.as_mut_any().downcast_mut().unwrap();
+        Ok(Val::U64(this.pkts.len() as u64))
+    }
+);
+
+const PCAP_CLASS: ClassDef = class!(
+    /// # Capture File
+    ///
+    /// Packets read from a pcap or pcapng file, to be replayed or spliced in to the output.
+    /// `for pkt in cap.packets() { ... }` iterates over them one at a time, and each one is sent
+    /// when it would have been if they'd all been emitted together.
+    resynth class Pcap {
+        packets => Symbol::Func(&PCAP_PACKETS),
+        slice => Symbol::Func(&PCAP_SLICE),
+        between => Symbol::Func(&PCAP_BETWEEN),
+        count => Symbol::Func(&PCAP_COUNT),
+    }
+);
+
+impl Class for PcapFile {
+    fn def(&self) -> &'static ClassDef {
+        &PCAP_CLASS
+    }
+}
+
+const PCAP: FuncDef = func!(
+    /// Read all of the packets from a pcap or pcapng file. Captures with a linktype other than
+    /// ethernet or raw IP are converted to ethernet.
+    resynth fn pcap(
+        filename: Str,
+        =>
+        =>
+        Void
+    ) -> Obj
+    |mut args| {
+        let arg: Buf = args.next().into();
+        let path = Path::new(OsStr::from_bytes(arg.as_ref()));
+        let mut ret = PcapFile::default();
+
+        for rec in PcapReader::open(path)? {
+            let rec = rec?;
+            let linktype = LinkType::from_u32(rec.linktype).ok_or_else(|| {
+                RuntimeError.with_msg(format!(
+                    "pcap: {}: unsupported linktype {}",
+                    path.display(),
+                    rec.linktype
+                ))
+            })?;
+
+            ret.pkts.push((rec.time, linktype.normalize(rec.pkt)?));
+        }
+
+        Ok(Val::from(ret))
+    }
+);
+
 pub const MODULE: Module = module!(
     /// # Buffers and File I/O
     resynth mod io {
         BufIO => Symbol::Class(&BUFIO_CLASS),
+        Pcap => Symbol::Class(&PCAP_CLASS),
         file => Symbol::Func(&IO_FILE),
         bufio => Symbol::Func(&BUFIO),
+        pcap => Symbol::Func(&PCAP),
     }
 );
@@ -1,4 +1,3 @@
-use crate::func;
 use crate::libapi::{Class, ClassDef, FuncDef, Module};
 use crate::str::Buf;
 use crate::sym::Symbol;
@@ -397,6 +396,17 @@ impl Class for TcpFlow {
     fn def(&self) -> &'static ClassDef {
         &TCP_FLOW
     }
+
+    fn opens(&self, method: &FuncDef) -> bool {
+        method.name == "open"
+    }
+
+    fn closes(&self, method: &FuncDef) -> bool {
+        matches!(
+            method.name,
+            "client_close" | "server_close" | "client_reset" | "server_reset"
+        )
+    }
 }
 
 const FLOW: FuncDef = func!(
@@ -4,12 +4,12 @@
 use crate::args::Args;
 use crate::err::Error;
 use crate::err::Error::RuntimeError;
-use crate::libapi::{Documented, Module, SymDesc};
+use crate::libapi::{ClassDef, Documented, FuncDef, Module, SymDesc};
 use crate::sym::Symbol;
-use crate::val::Val;
+use crate::val::{Typed, Val};
 
 use ::std::fs::{create_dir_all, File};
-use ::std::io::BufWriter;
+use ::std::io::{BufWriter, Error as IoError, Write};
 use ::std::path::{Path, PathBuf};
 
 pub fn unimplemented(mut args: Args) -> Result<Val, Error> {
@@ -19,6 +19,7 @@ pub fn unimplemented(mut args: Args) -> Result<Val, Error> {
 }
 
 mod arp;
+mod builtin;
 mod dhcp;
 mod dns;
 mod erspan1;
@@ -28,17 +29,21 @@ mod gre;
 mod io;
 mod ipv4;
 mod netbios;
+mod pcapng;
 mod std;
 mod text;
 mod time;
 mod tls;
 mod vxlan;
 
+pub(crate) use builtin::{is_cast, linktype_arg, LINKTYPE, PARAM};
+
 const STDLIB: Module = module! {
     /// # Resynth Standard Library
     ///
     /// These are all the basic functions included in resynth
     resynth mod stdlib {
+        builtin => Symbol::Module(&builtin::MODULE),
         std => Symbol::Module(&std::MODULE),
         text => Symbol::Module(&text::MODULE),
         io => Symbol::Module(&io::MODULE),
@@ -54,6 +59,7 @@ const STDLIB: Module = module! {
         erspan1 => Symbol::Module(&erspan1::MODULE),
         erspan2 => Symbol::Module(&erspan2::MODULE),
         time => Symbol::Module(&time::MODULE),
+        pcapng => Symbol::Module(&pcapng::MODULE),
     }
 };
 
@@ -68,6 +74,137 @@ pub fn toplevel_module(name: &str) -> Option<&'static Module> {
     }
 }
 
+/// Look up a function which is always in scope without being imported
+pub fn builtin(name: &str) -> Option<&'static Symbol> {
+    builtin::MODULE.get(name)
+}
+
+/// Find a symbol by its full path, eg. `ipv4::tcp::flow`. A bare name which isn't a module may
+/// also be a builtin.
+pub fn lookup(path: &str) -> Option<Symbol> {
+    let mut parts = path.split("::");
+    let first = parts.next()?;
+
+    let mut sym = match STDLIB.get(first).or_else(|| builtin(first)) {
+        Some(sym) => *sym,
+        None => return None,
+    };
+
+    for part in parts {
+        sym = match sym {
+            Symbol::Module(m) => *m.get(part)?,
+            Symbol::Class(c) => *c.get(part)?,
+            Symbol::Func(_) | Symbol::Val(_) => return None,
+        };
+    }
+
+    Some(sym)
+}
+
+/// All of the symbols whose full paths start with the given partial path, for tab completion
+pub fn complete(partial: &str) -> Vec<String> {
+    let (parent, prefix) = match partial.rfind("::") {
+        Some(pos) => (Some(&partial[..pos]), &partial[pos + 2..]),
+        None => (None, partial),
+    };
+
+    let symtab = match parent.map(lookup) {
+        None => STDLIB.symtab.iter().chain(builtin::MODULE.symtab).collect(),
+        Some(Some(Symbol::Module(m))) => m.symtab.iter().collect(),
+        Some(Some(Symbol::Class(c))) => c.symtab.iter().collect(),
+        Some(_) => Vec::new(),
+    };
+
+    let mut ret: Vec<String> = symtab
+        .into_iter()
+        .filter(|desc| desc.name.starts_with(prefix))
+        .map(|desc| match parent {
+            Some(parent) => format!("{}::{}", parent, desc.name),
+            None => desc.name.to_owned(),
+        })
+        .collect();
+
+    ret.sort();
+    ret.dedup();
+    ret
+}
+
+/// The full paths of every class in the stdlib
+pub fn classes() -> Vec<String> {
+    fn walk(ret: &mut Vec<String>, path: &str, m: &'static Module) {
+        for SymDesc { name, sym } in m.symtab.iter() {
+            let child = match path {
+                "" => name.to_string(),
+                _ => format!("{}::{}", path, name),
+            };
+            match sym {
+                Symbol::Module(m) => walk(ret, &child, m),
+                Symbol::Class(_) => ret.push(child),
+                Symbol::Func(_) | Symbol::Val(_) => {}
+            }
+        }
+    }
+
+    let mut ret = Vec::new();
+    walk(&mut ret, "", &STDLIB);
+    ret.sort();
+    ret
+}
+
+/// The classes which an object returned by a stdlib function might be an instance of: those in
+/// the same module as the function, or any of them if there are none there
+pub fn result_classes(func: &FuncDef) -> Vec<&'static ClassDef> {
+    type Found = Vec<(&'static Module, &'static ClassDef)>;
+
+    fn walk(
+        ret: &mut Found,
+        home: &mut Option<&'static Module>,
+        m: &'static Module,
+        func: &FuncDef,
+    ) {
+        for SymDesc { sym, .. } in m.symtab.iter() {
+            match sym {
+                Symbol::Module(child) => walk(ret, home, child, func),
+                Symbol::Class(class) => ret.push((m, class)),
+                Symbol::Func(f) if *f == func => *home = Some(m),
+                Symbol::Func(_) | Symbol::Val(_) => {}
+            }
+        }
+    }
+
+    let mut all = Vec::new();
+    let mut home = None;
+    walk(&mut all, &mut home, &STDLIB, func);
+
+    let ret: Vec<&'static ClassDef> = all
+        .iter()
+        .filter(|(m, _)| home.is_some_and(|home| ::std::ptr::eq(*m, home)))
+        .map(|(_, class)| *class)
+        .collect();
+
+    if ret.is_empty() {
+        all.into_iter().map(|(_, class)| class).collect()
+    } else {
+        ret
+    }
+}
+
+/// Write the documentation for a symbol, given its full path. Returns false if there's no such
+/// symbol.
+pub fn write_symbol_docs<W: Write>(path: &str, wr: &mut W) -> Result<bool, IoError> {
+    match lookup(path) {
+        None => return Ok(false),
+        Some(Symbol::Module(m)) => m.write_docs(wr)?,
+        Some(Symbol::Class(c)) => c.write_docs(wr)?,
+        Some(Symbol::Func(f)) => f.write_docs(wr)?,
+        Some(Symbol::Val(val)) => {
+            wr.write_all(format!("{}: ({}){}\n", path, val.val_type(), val).as_bytes())?
+        }
+    }
+
+    Ok(true)
+}
+
 /// Generate documentation for a module.. This needs a lot of work.
 pub fn recurse(out_dir: &Path, stk: &mut Vec<&'static str>, m: &'static Module) {
     let mut mod_path = PathBuf::from(out_dir);
@@ -100,6 +237,7 @@ pub fn recurse(out_dir: &Path, stk: &mut Vec<&'static str>, m: &'static Module)
                 let mut wr = BufWriter::new(f);
 
                 cls.write_docs(&mut wr).expect("class doc");
+                mod_path.pop();
             }
             _ => {}
         }
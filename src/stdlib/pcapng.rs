@@ -0,0 +1,73 @@
+use std::rc::Rc;
+
+use pkt::Packet;
+
+use crate::libapi::{FuncDef, Module};
+use crate::str::Buf;
+use crate::sym::Symbol;
+use crate::val::Val;
+
+const INTERFACE: FuncDef = func!(
+    /// Label packets as having been captured on a named interface. Each interface gets its own
+    /// Interface Description Block in the pcapng output.
+    ///
+    /// ### Arguments
+    /// * `name: Str` The interface name, eg. `"eth1"`
+    /// * `gen: PktGen` The packets to label
+    resynth fn interface(
+        name: Str,
+        gen: PktGen,
+        =>
+        =>
+        Void
+    ) -> PktGen
+    |mut args| {
+        let name: Buf = args.next().into();
+        let mut gen: Rc<Vec<Packet>> = args.next().into();
+        let name = String::from_utf8_lossy(name.as_ref()).into_owned();
+
+        for pkt in Rc::make_mut(&mut gen).iter_mut() {
+            pkt.meta_mut().interface = Some(name.clone());
+        }
+
+        Ok(Val::PktGen(gen))
+    }
+);
+
+const COMMENT: FuncDef = func!(
+    /// Attach a comment to packets. This is stored in the pcapng output, in addition to the
+    /// location of the statement which emitted them.
+    ///
+    /// ### Arguments
+    /// * `text: Str` The comment
+    /// * `gen: PktGen` The packets to comment on
+    resynth fn comment(
+        text: Str,
+        gen: PktGen,
+        =>
+        =>
+        Void
+    ) -> PktGen
+    |mut args| {
+        let text: Buf = args.next().into();
+        let mut gen: Rc<Vec<Packet>> = args.next().into();
+        let text = String::from_utf8_lossy(text.as_ref()).into_owned();
+
+        for pkt in Rc::make_mut(&mut gen).iter_mut() {
+            pkt.meta_mut().comments.push(text.clone());
+        }
+
+        Ok(Val::PktGen(gen))
+    }
+);
+
+pub const MODULE: Module = module! {
+    /// # pcapng Metadata
+    ///
+    /// Annotate packets with details which are recorded when writing pcapng files. They have no
+    /// effect on the contents of the packets, and are ignored when writing classic pcap files.
+    resynth mod pcapng {
+        interface => Symbol::Func(&INTERFACE),
+        comment => Symbol::Func(&COMMENT),
+    }
+};
@@ -1 +1,3 @@
 mod dns;
+mod symtab;
+mod text;
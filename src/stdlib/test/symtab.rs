@@ -0,0 +1,19 @@
+use crate::stdlib::{complete, lookup};
+use crate::sym::Symbol;
+
+#[test]
+fn lookup_path() {
+    assert!(matches!(lookup("ipv4"), Some(Symbol::Module(_))));
+    assert!(matches!(lookup("ipv4::tcp::flow"), Some(Symbol::Func(f)) if f.name == "flow"));
+    assert!(matches!(lookup("len"), Some(Symbol::Func(_))));
+    assert!(lookup("ipv4::nosuch").is_none());
+    assert!(lookup("ipv4::tcp::flow::x").is_none());
+}
+
+#[test]
+fn complete_path() {
+    assert_eq!(complete("ipv"), vec!["ipv4"]);
+    assert_eq!(complete("ipv4::ud"), vec!["ipv4::udp"]);
+    assert!(complete("ipv4::tcp::").contains(&"ipv4::tcp::flow".to_owned()));
+    assert!(complete("nosuch::").is_empty());
+}
@@ -0,0 +1,97 @@
+use crate::args::Args;
+use crate::err::Error;
+use crate::err::Error::TypeError;
+use crate::stdlib::text::FORMAT;
+use crate::val::{Val, ValDef};
+
+use std::net::{Ipv4Addr, SocketAddrV4};
+
+fn format(fmt: &'static [u8], vals: Vec<Val>) -> Result<Vec<u8>, Error> {
+    let ret = (FORMAT.exec)(Args::new(None, vec![Val::from(ValDef::Str(fmt))], vals))?;
+
+    let buf: &[u8] = ret.as_ref();
+
+    Ok(buf.to_owned())
+}
+
+/// Render an integer as decimal ASCII
+#[test]
+fn test_format_int() -> Result<(), Error> {
+    assert_eq!(
+        format(b"Content-Length: {}\r\n", vec![Val::U64(1234)])?,
+        b"Content-Length: 1234\r\n"
+    );
+    assert_eq!(
+        format(
+            b"{} {} {}",
+            vec![Val::U8(0), Val::I8(-128), Val::I64(i64::MIN)]
+        )?,
+        b"0 -128 -9223372036854775808"
+    );
+    Ok(())
+}
+
+/// Render integers and byte-strings in hex
+#[test]
+fn test_format_hex() -> Result<(), Error> {
+    assert_eq!(
+        format(
+            b"{:x} {:X} {:x}",
+            vec![Val::U32(0xdead), Val::U16(0xbeef), Val::I16(-16)]
+        )?,
+        b"dead BEEF -10"
+    );
+    assert_eq!(
+        format(b"{:x}", vec![Val::from(ValDef::Str(b"\x00\x01\xff"))])?,
+        b"0001ff"
+    );
+    Ok(())
+}
+
+/// Render addresses, bools, strings and lists
+#[test]
+fn test_format_types() -> Result<(), Error> {
+    assert_eq!(
+        format(
+            b"Host: {} ({}) {}",
+            vec![
+                Val::from(ValDef::Str(b"example.com")),
+                Val::Ip4(Ipv4Addr::new(10, 0, 0, 1)),
+                Val::Sock4(SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 2), 80)),
+            ]
+        )?,
+        b"Host: example.com (10.0.0.1) 10.0.0.2:80"
+    );
+    assert_eq!(
+        format(
+            b"{} {:?} {}",
+            vec![
+                Val::Bool(true),
+                Val::from(ValDef::Str(b"a\"\r\n\x00")),
+                Val::List(vec![Val::U8(1), Val::U8(2)].into()),
+            ]
+        )?,
+        b"true \"a\\\"\\r\\n\\x00\" [1, 2]"
+    );
+    Ok(())
+}
+
+/// Escaped braces, and mismatches between placeholders and arguments
+#[test]
+fn test_format_errors() -> Result<(), Error> {
+    assert_eq!(format(b"{{}} {{{}}}", vec![Val::U8(1)])?, b"{} {1}");
+    assert_eq!(format(b"{} {}", vec![Val::U8(1)]).err(), Some(TypeError));
+    assert_eq!(
+        format(b"{}", vec![Val::U8(1), Val::U8(2)]).err(),
+        Some(TypeError)
+    );
+    assert_eq!(format(b"{", vec![Val::U8(1)]).err(), Some(TypeError));
+    assert_eq!(format(b"}", vec![]).err(), Some(TypeError));
+    assert_eq!(format(b"{:b}", vec![Val::U8(1)]).err(), Some(TypeError));
+    assert_eq!(
+        format(b"{:x}", vec![Val::Bool(true)]).err(),
+        Some(TypeError)
+    );
+    assert_eq!(format(b"{}", vec![Val::Nil]).err(), Some(TypeError));
+    Ok(())
+}
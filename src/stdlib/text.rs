@@ -1,9 +1,25 @@
-use crate::func;
+use crate::err::Error;
+use crate::err::Error::{IndexError, RuntimeError, TypeError};
 use crate::libapi::{FuncDef, Module};
 use crate::str::Buf;
 use crate::sym::Symbol;
 use crate::val::{Val, ValDef};
 
+use std::io::Write;
+
+/// Upper bound on the length of strings built by repeat() and pad()
+const MAX_LEN: u64 = 1 << 24;
+
+fn check_len(func: &str, len: Option<u64>) -> Result<usize, Error> {
+    match len {
+        Some(len) if len <= MAX_LEN => Ok(len as usize),
+        _ => Err(RuntimeError.with_msg(format!(
+            "{}: result is longer than the limit of {} bytes",
+            func, MAX_LEN
+        ))),
+    }
+}
+
 const CONCAT: FuncDef = func!(
     /// Concatenate strings
     resynth fn concat(
@@ -41,12 +57,397 @@ const LEN: FuncDef = func!(
     }
 );
 
+const SLICE: FuncDef = func!(
+    /// Return the bytes of a string from `start` up to, but not including, `end`
+    ///
+    /// ### Arguments
+    /// * `end: u64` Defaults to the end of the string
+    resynth fn slice(
+        buf: Str,
+        start: U64,
+        =>
+        end: Type = ValType::U64,
+        =>
+        Void
+    ) -> Str
+    |mut args| {
+        let buf: Buf = args.next().into();
+        let start: u64 = args.next().into();
+        let end: Option<u64> = args.next().into();
+        let end = end.unwrap_or(buf.len() as u64);
+
+        if start > end || end > buf.len() as u64 {
+            return Err(IndexError.with_msg(format!(
+                "slice: {}..{} out of range for string of length {}",
+                start,
+                end,
+                buf.len()
+            )));
+        }
+
+        Ok(Val::str(&buf.as_ref()[start as usize..end as usize]))
+    }
+);
+
+const REPEAT: FuncDef = func!(
+    /// Repeat a string `n` times
+    resynth fn repeat(
+        buf: Str,
+        n: U64,
+        =>
+        =>
+        Void
+    ) -> Str
+    |mut args| {
+        let buf: Buf = args.next().into();
+        let n: u64 = args.next().into();
+
+        check_len("repeat", (buf.len() as u64).checked_mul(n))?;
+
+        Ok(Val::str(buf.as_ref().repeat(n as usize)))
+    }
+);
+
+const PAD: FuncDef = func!(
+    /// Pad a string out to `len` bytes by appending `byte`. Strings which are already at least
+    /// that long are returned unchanged.
+    resynth fn pad(
+        buf: Str,
+        len: U64,
+        =>
+        byte: U8 = 0,
+        =>
+        Void
+    ) -> Str
+    |mut args| {
+        let buf: Buf = args.next().into();
+        let len: u64 = args.next().into();
+        let byte: u8 = args.next().into();
+
+        let mut v = buf.cow_buffer();
+        if (v.len() as u64) < len {
+            v.resize(check_len("pad", Some(len))?, byte);
+        }
+
+        Ok(Val::str(v))
+    }
+);
+
+/// Offset of the first occurrence of `needle` in `buf`
+fn find(buf: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(0);
+    }
+    buf.windows(needle.len()).position(|w| w == needle)
+}
+
+const FIND: FuncDef = func!(
+    /// Return the offset of the first occurrence of `needle` in a string, or -1 if it's not
+    /// present
+    resynth fn find(
+        buf: Str,
+        needle: Str,
+        =>
+        =>
+        Void
+    ) -> I64
+    |mut args| {
+        let buf: Buf = args.next().into();
+        let needle: Buf = args.next().into();
+
+        Ok(Val::I64(match find(buf.as_ref(), needle.as_ref()) {
+            Some(off) => off as i64,
+            None => -1,
+        }))
+    }
+);
+
+const REPLACE: FuncDef = func!(
+    /// Replace every occurrence of `from` in a string with `to`
+    resynth fn replace(
+        buf: Str,
+        from: Str,
+        to: Str,
+        =>
+        =>
+        Void
+    ) -> Str
+    |mut args| {
+        let buf: Buf = args.next().into();
+        let from: Buf = args.next().into();
+        let to: Buf = args.next().into();
+
+        if from.len() == 0 {
+            return Err(RuntimeError.with_msg("replace: can't replace an empty string"));
+        }
+
+        let mut count: u64 = 0;
+        let mut rest = buf.as_ref();
+        while let Some(off) = find(rest, from.as_ref()) {
+            count += 1;
+            rest = &rest[off + from.len()..];
+        }
+
+        let len = (to.len() as u64)
+            .checked_mul(count)
+            .and_then(|n| n.checked_add(buf.len() as u64 - count * from.len() as u64));
+
+        let mut ret: Vec<u8> = Vec::with_capacity(check_len("replace", len)?);
+        let mut rest = buf.as_ref();
+        while let Some(off) = find(rest, from.as_ref()) {
+            ret.extend(&rest[..off]);
+            ret.extend(to.as_ref());
+            rest = &rest[off + from.len()..];
+        }
+        ret.extend(rest);
+
+        Ok(Val::str(ret))
+    }
+);
+
+const SPLIT: FuncDef = func!(
+    /// Split a string in to a list of strings on each occurrence of `sep`
+    resynth fn split(
+        buf: Str,
+        sep: Str,
+        =>
+        =>
+        Void
+    ) -> List
+    |mut args| {
+        let buf: Buf = args.next().into();
+        let sep: Buf = args.next().into();
+
+        if sep.len() == 0 {
+            return Err(RuntimeError.with_msg("split: separator must not be empty"));
+        }
+
+        let mut ret: Vec<Val> = Vec::new();
+        let mut rest = buf.as_ref();
+        while let Some(off) = find(rest, sep.as_ref()) {
+            ret.push(Val::str(&rest[..off]));
+            rest = &rest[off + sep.len()..];
+        }
+        ret.push(Val::str(rest));
+
+        Ok(Val::from(ret))
+    }
+);
+
+const UPPER: FuncDef = func!(
+    /// Convert ASCII letters in a string to upper-case
+    resynth fn upper(
+        buf: Str,
+        =>
+        =>
+        Void
+    ) -> Str
+    |mut args| {
+        let buf: Buf = args.next().into();
+        Ok(Val::str(buf.as_ref().to_ascii_uppercase()))
+    }
+);
+
+const LOWER: FuncDef = func!(
+    /// Convert ASCII letters in a string to lower-case
+    resynth fn lower(
+        buf: Str,
+        =>
+        =>
+        Void
+    ) -> Str
+    |mut args| {
+        let buf: Buf = args.next().into();
+        Ok(Val::str(buf.as_ref().to_ascii_lowercase()))
+    }
+);
+
+const XOR: FuncDef = func!(
+    /// XOR a string with a key, which is repeated as many times as necessary to cover the
+    /// whole string
+    resynth fn xor(
+        buf: Str,
+        key: Str,
+        =>
+        =>
+        Void
+    ) -> Str
+    |mut args| {
+        let buf: Buf = args.next().into();
+        let key: Buf = args.next().into();
+
+        if key.len() == 0 {
+            return Err(RuntimeError.with_msg("xor: key must not be empty"));
+        }
+
+        let ret: Vec<u8> = buf
+            .as_ref()
+            .iter()
+            .zip(key.as_ref().iter().cycle())
+            .map(|(b, k)| b ^ k)
+            .collect();
+
+        Ok(Val::str(ret))
+    }
+);
+
+/// Render a single value according to a format spec, which is whatever came between the colon
+/// and the closing brace of the placeholder
+fn render(out: &mut Vec<u8>, val: &Val, spec: &[u8]) -> Result<(), Error> {
+    let hex = match spec {
+        b"" | b"?" => false,
+        b"x" | b"X" => true,
+        _ => {
+            return Err(TypeError.with_msg(format!(
+                "format: unknown format spec {:?}",
+                spec.escape_ascii().to_string()
+            )));
+        }
+    };
+
+    if let Some(i) = val.as_int() {
+        let sign = if i < 0 { "-" } else { "" };
+        let mag = i.unsigned_abs();
+        match spec {
+            b"x" => write!(out, "{}{:x}", sign, mag)?,
+            b"X" => write!(out, "{}{:X}", sign, mag)?,
+            _ => write!(out, "{}", i)?,
+        }
+        return Ok(());
+    }
+
+    match val {
+        Val::Str(buf) => match spec {
+            b"x" => buf
+                .as_ref()
+                .iter()
+                .try_for_each(|b| write!(out, "{:02x}", b))?,
+            b"X" => buf
+                .as_ref()
+                .iter()
+                .try_for_each(|b| write!(out, "{:02X}", b))?,
+            b"?" => write!(out, "\"{}\"", buf.as_ref().escape_ascii())?,
+            _ => out.extend(buf.as_ref()),
+        },
+        Val::Bool(b) if !hex => write!(out, "{}", b)?,
+        Val::Ip4(addr) if !hex => write!(out, "{}", addr)?,
+        Val::Sock4(sa) if !hex => write!(out, "{}", sa)?,
+        Val::List(items) => {
+            out.push(b'[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.extend(b", ");
+                }
+                render(out, item, spec)?;
+            }
+            out.push(b']');
+        }
+        _ => {
+            return Err(TypeError.with_msg(format!(
+                "format: can't render {:?} as {:?}",
+                val,
+                spec.escape_ascii().to_string()
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Expand each `{}` placeholder in a format string with the next of the given values
+fn format(fmt: &[u8], vals: &[Val]) -> Result<Val, Error> {
+    let mut out: Vec<u8> = Vec::with_capacity(fmt.len());
+    let mut vals = vals.iter();
+    let mut pos = 0;
+
+    while pos < fmt.len() {
+        match (fmt[pos], fmt.get(pos + 1)) {
+            (b'{', Some(b'{')) | (b'}', Some(b'}')) => {
+                out.push(fmt[pos]);
+                pos += 2;
+            }
+            (b'{', _) => {
+                let len = match fmt[pos..].iter().position(|c| *c == b'}') {
+                    Some(len) => len,
+                    None => {
+                        return Err(TypeError.with_msg("format: unterminated placeholder"));
+                    }
+                };
+                let spec = &fmt[pos + 1..pos + len];
+                let spec = match spec {
+                    [] => spec,
+                    [b':', spec @ ..] => spec,
+                    _ => {
+                        return Err(TypeError.with_msg(format!(
+                            "format: bad placeholder {:?}",
+                            spec.escape_ascii().to_string()
+                        )));
+                    }
+                };
+                let val = match vals.next() {
+                    Some(val) => val,
+                    None => {
+                        return Err(
+                            TypeError.with_msg("format: not enough arguments for format string")
+                        );
+                    }
+                };
+                render(&mut out, val, spec)?;
+                pos += len + 1;
+            }
+            (b'}', _) => {
+                return Err(TypeError.with_msg("format: unmatched '}' in format string"));
+            }
+            (c, _) => {
+                out.push(c);
+                pos += 1;
+            }
+        }
+    }
+
+    if vals.next().is_some() {
+        return Err(TypeError.with_msg("format: too many arguments for format string"));
+    }
+
+    Ok(Val::str(out))
+}
+
+pub(crate) const FORMAT: FuncDef = func!(
+    /// Format values in to a string. Each `{}` in the format string is replaced by the next
+    /// argument: integers in decimal, IP addresses and socket addresses in dotted notation and
+    /// strings verbatim. A `{:x}` or `{:X}` placeholder renders integers and strings in hex, and
+    /// `{:?}` renders strings quoted with any non-printable bytes escaped. Literal braces are
+    /// written as `{{` and `}}`.
+    resynth fn format(
+        fmt: Str,
+        =>
+        =>
+        Any
+    ) -> Str
+    |mut args| {
+        let fmt: Buf = args.next().into();
+        let vals = args.raw_extra_args();
+
+        format(fmt.as_ref(), &vals)
+    }
+);
+
 pub const MODULE: Module = module! {
     /// # Text / Byte-String Functions
     resynth mod text {
         concat => Symbol::Func(&CONCAT),
         crlflines => Symbol::Func(&CRLFLINES),
+        format => Symbol::Func(&FORMAT),
         len => Symbol::Func(&LEN),
+        slice => Symbol::Func(&SLICE),
+        repeat => Symbol::Func(&REPEAT),
+        pad => Symbol::Func(&PAD),
+        find => Symbol::Func(&FIND),
+        replace => Symbol::Func(&REPLACE),
+        split => Symbol::Func(&SPLIT),
+        upper => Symbol::Func(&UPPER),
+        lower => Symbol::Func(&LOWER),
+        xor => Symbol::Func(&XOR),
         CRLF => Symbol::Val(ValDef::Str(b"\r\n")),
     }
 };
@@ -64,11 +64,7 @@ where
 
 impl fmt::Debug for Buf {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        /* FIXME: allow printing of hex crap, data here does not have to be utf-8, and printing it
-         * like this could panic.
-         */
-        let s = std::str::from_utf8(self.inner.as_ref()).unwrap();
-        f.write_fmt(format_args!("Bytes<{:?}>", s))
+        f.write_fmt(format_args!("Bytes<\"{}\">", self.inner.escape_ascii()))
     }
 }
 
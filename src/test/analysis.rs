@@ -0,0 +1,105 @@
+use crate::analysis::{CompletionKind, Document};
+use crate::loc::{Loc, Span};
+
+use std::{env, fs, process};
+
+const SRC: &str = "
+import ipv4;
+let flow = ipv4::tcp::flow(1.2.3.4/1, 5.6.7.8/2);
+flow.open();
+";
+
+fn labels(doc: &Document, loc: Loc) -> Vec<String> {
+    doc.complete(loc).into_iter().map(|c| c.label).collect()
+}
+
+#[test]
+fn hover() {
+    let doc = Document::new(SRC, None);
+
+    let (span, text) = doc.hover(Loc::new(3, 24)).unwrap();
+    assert_eq!(span, Span::new(Loc::new(3, 23), Loc::new(3, 27)));
+    assert!(text.starts_with("## flow"));
+
+    let (_, text) = doc.hover(Loc::new(4, 7)).unwrap();
+    assert!(text.starts_with("## open"));
+
+    assert!(doc.hover(Loc::new(3, 19)).is_some());
+    assert!(doc.hover(Loc::new(3, 6)).is_none());
+}
+
+#[test]
+fn definition() {
+    let doc = Document::new(SRC, None);
+
+    let flow = Span::new(Loc::new(3, 5), Loc::new(3, 9));
+    assert_eq!(doc.definition(Loc::new(4, 2)), Some(flow));
+    assert_eq!(doc.definition(Loc::new(3, 6)), Some(flow));
+    assert_eq!(doc.definition(Loc::new(3, 24)), None);
+    assert_eq!(doc.definition(Loc::new(4, 7)), None);
+}
+
+#[test]
+fn complete() {
+    let doc = Document::new("import ipv4;\nipv4::tc", None);
+    let comp = doc.complete(Loc::new(2, 9));
+    assert_eq!(comp.len(), 1);
+    assert_eq!(comp[0].label, "tcp");
+    assert_eq!(comp[0].kind, CompletionKind::Module);
+
+    let doc = Document::new(
+        "let f = ipv4::udp::flow(1.2.3.4/1, 5.6.7.8/2);\nf.client_",
+        None,
+    );
+    let comp = doc.complete(Loc::new(2, 10));
+    assert!(comp.iter().any(|c| c.label == "client_dgram"));
+    assert!(comp
+        .iter()
+        .all(|c| c.label.starts_with("client_") && c.kind == CompletionKind::Method));
+
+    let doc = Document::new("let iface = 1;\ni", None);
+    let labels = labels(&doc, Loc::new(2, 2));
+    assert!(labels.contains(&"ipv4".to_owned()));
+    assert!(labels.contains(&"iface".to_owned()));
+}
+
+#[test]
+fn signature() {
+    let doc = Document::new("ipv4::tcp::flow(1.2.3.4/1, ", None);
+    let sig = doc.signature(Loc::new(1, 28)).unwrap();
+    assert!(sig.label.starts_with("flow(cl: Sock4, sv: Sock4"));
+    assert_eq!(sig.active, 1);
+    assert!(sig.label.contains(&sig.params[sig.active]));
+
+    let doc = Document::new("ipv4::udp::flow(1.2.3.4/1, [1, 2], f(x, y), raw: ", None);
+    let sig = doc.signature(Loc::new(1, 51)).unwrap();
+    assert_eq!(sig.params[sig.active], "raw: bool = false");
+
+    let doc = Document::new("ipv4::udp::flow(1.2.3.4/1, 5.6.7.8/2);", None);
+    assert!(doc.signature(Loc::new(1, 39)).is_none());
+}
+
+#[test]
+fn diagnostics() {
+    let doc = Document::new("let x = ;\nlet y = zz;\n", None);
+    assert_eq!(doc.diagnostics().len(), 2);
+}
+
+/// Imported resynth files are checked when the document has a path
+#[test]
+fn diagnostics_imports() {
+    let dir = env::temp_dir().join(format!("resynth-analysis-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("lib.rsyn"), "let x = zz;\n").unwrap();
+
+    let src = "import \"lib.rsyn\" as lib;\nlib::y;\n";
+    let doc = Document::new(src, Some(&dir.join("main.rsyn")));
+    fs::remove_dir_all(&dir).unwrap();
+
+    let errs = doc.diagnostics();
+    assert_eq!(errs.len(), 2);
+    assert_eq!(errs[0].diag().unwrap().file, None);
+    assert_eq!(errs[1].diag().unwrap().file, Some(dir.join("lib.rsyn")));
+
+    assert!(Document::new(src, None).diagnostics().is_empty());
+}
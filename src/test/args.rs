@@ -1,6 +1,6 @@
 use crate::args::{ArgSpec, ArgVec};
 use crate::err::Error;
-use crate::libapi::FuncDef;
+use crate::libapi::{FuncDef, Signature};
 use crate::str::Buf;
 use crate::val::{Val, ValDef};
 
@@ -380,3 +380,56 @@ fn argvec_optional_collect_u64() {
         OPTIONAL_COLLECT_U64.argvec(None, args)
     )
 }
+
+const NARROW: FuncDef = func! {
+    /// NARROW
+    resynth fn NARROW(
+        a: U8,
+        =>
+        b: U16 = 123,
+        c: Type = ValType::I32,
+        =>
+        U8
+    ) -> Void
+    |_args| {
+        Ok(Val::Nil)
+    }
+};
+
+/// Integer args are converted to the declared type
+#[test]
+fn narrow_args() {
+    let args = vec![
+        ArgSpec::from(1),
+        ArgSpec::from(("c", ValDef::U64(3))),
+        ArgSpec::from(4),
+        ArgSpec::from(true),
+    ];
+    assert_eq!(
+        Ok(ArgVec::new(
+            None,
+            vec!(Val::U8(1), Val::U16(123), Val::I32(3)),
+            vec!(Val::U8(4), Val::U8(1)),
+        )),
+        NARROW.argvec(None, args),
+    )
+}
+
+/// Integer args which don't fit in to the declared type are an error, rather than being truncated
+#[test]
+fn narrow_overflow() {
+    let args = vec![ArgSpec::from(300)];
+    assert_eq!(Err(Error::OverflowError), NARROW.argvec(None, args));
+
+    let args = vec![ArgSpec::from(1), ArgSpec::from(("b", ValDef::U64(65536)))];
+    assert_eq!(Err(Error::OverflowError), NARROW.argvec(None, args));
+
+    let args = vec![
+        ArgSpec::from(1),
+        ArgSpec::from(("c", ValDef::U64(0x80000000))),
+    ];
+    assert_eq!(Err(Error::OverflowError), NARROW.argvec(None, args));
+
+    let args = vec![ArgSpec::from(1), ArgSpec::from(256)];
+    assert_eq!(Err(Error::OverflowError), NARROW.argvec(None, args));
+}
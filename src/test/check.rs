@@ -0,0 +1,322 @@
+use crate::check::Checker;
+use crate::err::Error;
+use crate::err::Error::{
+    CircularImportError, ImportError, MultipleAssignError, NameError, ParseError, TypeError,
+};
+use crate::lex::{Lexer, EOF};
+use crate::loc::{Loc, Span};
+use crate::parse::Parser;
+use crate::val::{Val, ValType};
+
+use std::{env, fs, process};
+
+fn check(src: &str) -> Vec<Error> {
+    let mut lex = Lexer::default();
+    let mut parse = Parser::default();
+    let mut check = Checker::default();
+    let mut errors = Vec::new();
+
+    let toks = src
+        .lines()
+        .enumerate()
+        .flat_map(|(lno, line)| lex.line(lno + 1, line).unwrap())
+        .chain(std::iter::once(EOF));
+
+    for tok in toks {
+        if let Err(err) = parse.feed(&tok) {
+            errors.push(err);
+        }
+    }
+
+    for name in parse.get_poisoned() {
+        check.poison(&name);
+    }
+    check.add_stmts(parse.get_results());
+    errors.extend(check.finish());
+
+    errors
+}
+
+#[test]
+fn check_ok() {
+    let src = "
+        import ipv4;
+        import text;
+        fn f(a, b: 1) {
+            text::slice(a, b);
+            later;
+        }
+        let later = text::format(\"{}\", 1);
+        let mut x = text::find(later, \"1\");
+        x = 2;
+        let flow = ipv4::tcp::flow(1.1.1.1 / 1, 2.2.2.2 / 2);
+        flow.client_message(\"hello\", *[1, 2]);
+        f(\"abc\", b: x);
+        if x == 2 { f(*[later]); } else { text::upper(later); }
+    ";
+    assert_eq!(check(src), vec![]);
+}
+
+#[test]
+fn check_errors() {
+    let src = "
+        import nosuch;
+        import text;
+        let a = text::slice(\"abc\", \"1\");
+        let b = text::upper(\"a\", \"b\");
+        let c = zz;
+        fn f(x) {
+            x.y;
+            let z = 1;
+            z.y();
+            text::nope(x);
+        }
+        if \"a\" {
+            f(1, 2);
+        }
+        for i in 1 {
+            let c = i;
+            let c = i;
+        }
+        a = 1;
+        nosuch::anything();
+    ";
+    let errors = check(src);
+    assert_eq!(
+        errors,
+        vec![
+            ImportError("nosuch".to_owned()),
+            TypeError,
+            TypeError,
+            NameError,
+            TypeError,
+            NameError,
+            TypeError,
+            TypeError,
+            TypeError,
+            MultipleAssignError("c".to_owned()),
+            MultipleAssignError("a".to_owned()),
+        ]
+    );
+
+    let diag = errors[1].diag().unwrap();
+    assert_eq!(diag.arg.as_deref(), Some("start"));
+    assert_eq!(diag.expected, Some(ValType::U64));
+    assert_eq!(diag.received, Some(ValType::Str));
+    assert_eq!(diag.span, Some(Span::new(Loc::new(4, 36), Loc::new(4, 39))));
+}
+
+#[test]
+fn check_poisoned() {
+    let src = "
+        let a = (;
+        fn f() {
+            let b = ];
+            b;
+        }
+        let c = a;
+    ";
+    assert_eq!(check(src), vec![ParseError, ParseError]);
+}
+
+/// Command-line definitions are bound, and can only be replaced by a param()
+#[test]
+fn check_defined() {
+    let check = |src: &str| {
+        let mut check = Checker::default();
+        check.define("port", &Val::U64(8080));
+        check.check_source(src)
+    };
+
+    assert_eq!(check("let x = port + 1;"), vec![]);
+    assert_eq!(check("let port = param(\"port\", 80u16);"), vec![]);
+    assert_eq!(
+        check("let port = 80;"),
+        vec![MultipleAssignError("port".to_owned())]
+    );
+    assert_eq!(
+        check("let port = param(\"port\", 80); let port = param(\"port\", 80);"),
+        vec![MultipleAssignError("port".to_owned())]
+    );
+    assert_eq!(check("fn f() { let port = 80; } f();"), vec![]);
+}
+
+/// Operators are typed just as they're evaluated, so that errors carry on through expressions
+#[test]
+fn check_operators() {
+    let src = "
+        import text;
+        let a = 1u8 + 2u16;
+        let b = 10.0.0.1 / 80;
+        let c = [1, 2][0] + a;
+        let d = -a < 1;
+        if -a {}
+        text::upper(b == b);
+        text::upper(c);
+        let e = \"a\" + 1;
+        let f = -\"a\";
+        let g = 1[0];
+        let h = [1][\"a\"];
+        let i = a + (10.0.0.1 < 10.0.0.2);
+    ";
+    let errors = check(src);
+    assert_eq!(
+        errors,
+        vec![TypeError, TypeError, TypeError, TypeError, TypeError, TypeError, TypeError]
+    );
+
+    let diag = errors[0].diag().unwrap();
+    assert_eq!(diag.expected, Some(ValType::Bool));
+    assert_eq!(diag.received, Some(ValType::I16));
+    let diag = errors[1].diag().unwrap();
+    assert_eq!(diag.expected, Some(ValType::Str));
+    assert_eq!(diag.received, Some(ValType::Bool));
+    let diag = errors[6].diag().unwrap();
+    assert_eq!(
+        diag.msg.as_deref(),
+        Some("Unsupported operand types for +: u16 and bool")
+    );
+}
+
+/// Constant arguments are range-checked against the types of integer parameters
+#[test]
+fn check_literal_args() {
+    let src = "
+        import ipv4;
+        ipv4::datagram(1.1.1.1, 2.2.2.2, ttl: 255u64);
+        ipv4::datagram(1.1.1.1, 2.2.2.2, ttl: 300);
+        ipv4::datagram(1.1.1.1, 2.2.2.2, ttl: -1);
+        let ttl = 300;
+        ipv4::datagram(1.1.1.1, 2.2.2.2, ttl: ttl);
+    ";
+    let errors = check(src);
+    assert_eq!(errors, vec![Error::OverflowError, Error::OverflowError]);
+
+    let diag = errors[0].diag().unwrap();
+    assert_eq!(diag.arg.as_deref(), Some("ttl"));
+    assert_eq!(diag.expected, Some(ValType::U8));
+    assert_eq!(diag.received, Some(ValType::U64));
+    assert_eq!(diag.span, Some(Span::new(Loc::new(4, 47), Loc::new(4, 50))));
+}
+
+/// Integer casts are checked as though their argument had the type being cast to
+#[test]
+fn check_cast_args() {
+    let src = "
+        let a = u8(255);
+        let b = i8(-128);
+        let c = u32(true);
+        let d = u8(300);
+        let e = i8(-129);
+        let f = u16(a);
+    ";
+    let errors = check(src);
+    assert_eq!(errors, vec![Error::OverflowError, Error::OverflowError]);
+
+    let diag = errors[0].diag().unwrap();
+    assert_eq!(diag.expected, Some(ValType::U8));
+    assert_eq!(diag.span, Some(Span::new(Loc::new(5, 20), Loc::new(5, 23))));
+}
+
+/// Objects returned by stdlib functions are assumed to be instances of a class from the same
+/// module, and method calls are checked against each of them
+#[test]
+fn check_methods() {
+    let src = "
+        import io;
+        import ipv4;
+        import text;
+        let flow = ipv4::tcp::flow(1.1.1.1 / 1, 2.2.2.2 / 2);
+        flow.client_hole(10);
+        flow.client_hole(\"a\");
+        flow.client_hole(0x100000000);
+        flow.nope();
+        let f = io::pcap(\"x.pcap\");
+        f.read(10);
+        f.slice(\"a\");
+        let hole = flow.client_hole;
+        hole(1, 2);
+        text::upper(flow.client_message(\"a\"));
+    ";
+    let errors = check(src);
+    assert_eq!(
+        errors,
+        vec![
+            TypeError,
+            Error::OverflowError,
+            NameError,
+            TypeError,
+            TypeError,
+            TypeError,
+        ]
+    );
+
+    let diag = errors[0].diag().unwrap();
+    assert_eq!(diag.arg.as_deref(), Some("bytes"));
+    assert_eq!(diag.expected, Some(ValType::U32));
+    assert_eq!(diag.received, Some(ValType::Str));
+    let diag = errors[2].diag().unwrap();
+    assert_eq!(diag.msg.as_deref(), Some("TcpFlow has no method \"nope\""));
+}
+
+/// Resynth source files are imported relative to the file being checked, and checked too
+#[test]
+fn check_imports() {
+    let dir = env::temp_dir().join(format!("resynth-check-imports-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("lib")).unwrap();
+
+    let main = "
+        import ipv4;
+        import \"lib/hosts.rsyn\" as hosts;
+        import \"nosuch.rsyn\" as nosuch;
+        import \"loop.rsyn\" as lp;
+        let x = hosts::base + 1;
+        hosts::show(1, 2);
+        hosts::nope;
+        nosuch::anything();
+        ipv4::datagram(hosts::addr, 2.2.2.2, ttl: 300);
+    ";
+    let hosts = "
+        let base = 1;
+        let addr = 1.1.1.1;
+        fn show(i) {
+            let y = base + i;
+        }
+        let z = \"a\" + 1;
+    ";
+    fs::write(dir.join("main.rsyn"), main).unwrap();
+    fs::write(dir.join("lib/hosts.rsyn"), hosts).unwrap();
+    fs::write(dir.join("loop.rsyn"), "import \"main.rsyn\" as m;").unwrap();
+
+    let path = dir.join("main.rsyn");
+    let mut check = Checker::default();
+    check.set_path(&path);
+    let errors = check.check_source(main);
+    assert_eq!(
+        errors,
+        vec![
+            ImportError("nosuch.rsyn".to_owned()),
+            TypeError,
+            NameError,
+            Error::OverflowError,
+            TypeError,
+            CircularImportError("main.rsyn".to_owned()),
+        ]
+    );
+
+    let file = |err: &Error| err.diag().unwrap().file.clone();
+    assert_eq!(file(&errors[0]), None);
+    assert_eq!(file(&errors[4]), Some(dir.join("lib/hosts.rsyn")));
+    assert_eq!(file(&errors[5]), Some(dir.join("loop.rsyn")));
+    assert_eq!(
+        errors[2].diag().unwrap().msg.as_deref(),
+        Some("Can't find nope in hosts")
+    );
+
+    /* Without a path, imported files aren't checked */
+    assert_eq!(
+        Checker::default().check_source(main),
+        vec![Error::OverflowError]
+    );
+}
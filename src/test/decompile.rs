@@ -0,0 +1,128 @@
+use crate::decompile::decompile;
+use crate::lex::{Lexer, EOF};
+use crate::parse::Parser;
+use crate::program::Program;
+use crate::trace::Origin;
+
+use pkt::{Packet, PcapReader, PcapWriter};
+
+/// Run a program, returning the packets it emits along with their timestamps
+fn run(src: &str) -> Vec<(u64, Vec<u8>)> {
+    let mut lex = Lexer::default();
+    let mut parse = Parser::default();
+
+    for (lno, line) in src.lines().enumerate() {
+        for tok in lex.line(lno + 1, line).unwrap() {
+            parse.feed(&tok).unwrap();
+        }
+    }
+    parse.feed(&EOF).unwrap();
+
+    let mut pkts = Vec::new();
+    let mut packet = |ts: u64, pkt: &Packet, _: &Origin| pkts.push((ts, pkt.to_vec()));
+
+    let mut prog = Program::dummy().unwrap();
+    prog.set_packet_callback(&mut packet);
+    prog.add_stmts(parse.get_results()).unwrap();
+    drop(prog);
+
+    pkts
+}
+
+/// Write the packets to a capture and decompile it
+fn dec(pkts: &[(u64, Vec<u8>)]) -> String {
+    let mut buf = Vec::new();
+
+    {
+        let mut wr = PcapWriter::new(&mut buf);
+        for (ts, bytes) in pkts {
+            let mut pkt = Packet::default();
+            pkt.push_bytes(bytes);
+            wr.write_packet(*ts, &mut pkt).unwrap();
+        }
+    }
+
+    decompile(PcapReader::new(&buf[..]).unwrap()).unwrap()
+}
+
+#[test]
+fn round_trip() {
+    let src = "\
+import eth;
+import ipv4;
+import time;
+
+let http = ipv4::tcp::flow(192.168.0.1/32768, 10.0.0.1/80, cl_seq: 4294967290);
+http.open();
+http.client_message(\"GET / HTTP/1.1|0d 0a 0d 0a|\");
+time::jump_millis(250);
+http.server_message(\"|00 01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f 10|\");
+http.server_hole(100);
+http.server_message(\"bye\");
+let dns = ipv4::udp::flow(192.168.0.1/1024, 8.8.8.8/53);
+dns.client_dgram(\"query\");
+eth::frame(\"|00 00 00 00 00 01|\", \"|ff ff ff ff ff ff|\", ethertype: 0x0806, \"arp\");
+time::jump_seconds(2);
+http.client_close();
+";
+
+    let out = dec(&run(src));
+
+    assert_eq!(
+        out,
+        "\
+import eth;
+import ipv4;
+import time;
+
+let tcp1 = ipv4::tcp::flow(192.168.0.1/32768, 10.0.0.1/80, cl_seq: 4294967290);
+tcp1.open();
+tcp1.client_message(\"GET / HTTP/1.1|0d 0a|\", \"|0d 0a|\");
+time::jump_millis(250);
+tcp1.server_message(\"|00 01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f|\", \"|10|\");
+tcp1.server_hole(100);
+tcp1.server_message(\"bye\");
+
+let udp2 = ipv4::udp::flow(192.168.0.1/1024, 8.8.8.8/53);
+udp2.client_dgram(\"query\");
+eth::frame(\"|00 00 00 00 00 01|\", \"|ff ff ff ff ff ff|\", ethertype: 0x0806, \"arp\");
+time::jump_seconds(2);
+tcp1.client_close();
+"
+    );
+
+    assert_eq!(run(&out), run(src));
+}
+
+#[test]
+fn mid_stream() {
+    let src = "\
+import ipv4;
+
+let f = ipv4::tcp::flow(1.1.1.1/1000, 2.2.2.2/2000, cl_seq: 100, sv_seq: 200);
+f.client_message(\"a\");
+f.server_message(\"b\");
+f.server_reset();
+";
+    let pkts = run(src);
+
+    /* Retransmissions are dropped, and so are any packets after the reset */
+    let mut noisy = pkts.clone();
+    noisy.insert(1, pkts[0].clone());
+    noisy.push(pkts[2].clone());
+
+    let out = dec(&noisy);
+
+    assert_eq!(
+        out,
+        "\
+import ipv4;
+
+let tcp1 = ipv4::tcp::flow(1.1.1.1/1000, 2.2.2.2/2000, cl_seq: 100, sv_seq: 200);
+tcp1.client_message(\"a\");
+tcp1.server_message(\"b\");
+tcp1.server_reset();
+"
+    );
+    assert_eq!(run(&out), pkts);
+}
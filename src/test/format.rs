@@ -0,0 +1,48 @@
+use crate::err::Error::ParseError;
+use crate::format::format_source;
+
+fn fmt(src: &str) -> String {
+    let out = format_source(src).unwrap();
+    assert_eq!(format_source(&out).unwrap(), out, "not idempotent");
+    out
+}
+
+#[test]
+fn spacing() {
+    assert_eq!(
+        fmt("let  x=f( 1,2 ,-3,*y ) ;\nh(1.2.3.4 : 80, a/b, c:d, [ 1 , 2, ]);"),
+        "let x = f(1, 2, -3, *y);\nh(1.2.3.4:80, a/b, c: d, [1, 2]);\n"
+    );
+}
+
+#[test]
+fn blocks() {
+    assert_eq!(
+        fmt("if x {\ng(x - 1);\n}\nelse { }\nfn f(a,b) { a; }"),
+        "if x {\n  g(x - 1);\n} else {}\nfn f(a, b) {\n  a;\n}\n"
+    );
+}
+
+#[test]
+fn comments() {
+    let src = "# top\nimport ipv4; // why\n\n\n\nf(1, # one\n  2);\nif x { # x\n  # alone\n}\n";
+    let out = "# top\nimport ipv4; // why\n\nf(\n  1, # one\n  2,\n);\nif x { # x\n  # alone\n}\n";
+    assert_eq!(fmt(src), out);
+}
+
+#[test]
+fn split_args() {
+    assert_eq!(
+        fmt("f(\n  1, 2,\n\n  \"a\"\n    \"b\");"),
+        "f(\n  1,\n  2,\n\n  \"a\"\n  \"b\",\n);\n"
+    );
+
+    let long = format!("f(\"{}\", \"{}\");", "a".repeat(50), "b".repeat(50));
+    assert_eq!(fmt(&long).lines().count(), 4);
+}
+
+#[test]
+fn errors() {
+    assert_eq!(format_source("f(1;").unwrap_err().kind(), &ParseError);
+    assert!(format_source("let = 1;").is_err());
+}
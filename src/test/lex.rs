@@ -1,5 +1,6 @@
 use crate::lex::{Lexer, TokType, Token};
 use crate::loc::Loc;
+use crate::val::Val;
 use std::borrow::Cow;
 
 #[test]
@@ -19,12 +20,14 @@ fn lex_backslash() {
     let got = lex.line(1, "\"\\\";").expect("failed to lex");
     let expected: Vec<Token> = vec![
         Token {
-            loc: Loc::new(1, 4),
+            loc: Loc::new(1, 1),
+            end: Loc::new(1, 4),
             typ: TokType::StringLiteral,
             val: Some(Cow::Borrowed("\\")),
         },
         Token {
             loc: Loc::new(1, 4),
+            end: Loc::new(1, 5),
             typ: TokType::SemiColon,
             val: None,
         },
@@ -55,7 +58,8 @@ fn lex_string_chars() {
         .expect("failed to lex");
     let expected: Vec<Token> = vec![
         Token {
-            loc: Loc::new(1, 94),
+            loc: Loc::new(1, 1),
+            end: Loc::new(1, 94),
             typ: TokType::StringLiteral,
             val: Some(Cow::Borrowed(concat!(
                 "!#$%&'()*+,-./", // " is not allowed
@@ -69,6 +73,7 @@ fn lex_string_chars() {
         },
         Token {
             loc: Loc::new(1, 94),
+            end: Loc::new(1, 95),
             typ: TokType::SemiColon,
             val: None,
         },
@@ -86,16 +91,60 @@ fn lex_hex() {
         .expect("failed to lex");
     let expected: Vec<Token> = vec![
         Token {
-            loc: Loc::new(1, 22),
+            loc: Loc::new(1, 1),
+            end: Loc::new(1, 22),
             typ: TokType::StringLiteral,
             val: Some(Cow::Borrowed("|78:24:af:23:f0:a9|")),
         },
         Token {
             loc: Loc::new(1, 22),
+            end: Loc::new(1, 23),
+            typ: TokType::SemiColon,
+            val: None,
+        },
+    ];
+
+    assert_eq!(got, expected,)
+}
+
+#[test]
+fn lex_concat_span() {
+    let mut lex = Lexer::default();
+
+    let first = lex.line(1, "  \"a\" \"b\"").expect("failed to lex");
+    let got = lex.line(2, "\"c\";").expect("failed to lex");
+    let expected: Vec<Token> = vec![
+        Token {
+            loc: Loc::new(1, 3),
+            end: Loc::new(2, 4),
+            typ: TokType::StringLiteral,
+            val: Some(Cow::Borrowed("abc")),
+        },
+        Token {
+            loc: Loc::new(2, 4),
+            end: Loc::new(2, 5),
             typ: TokType::SemiColon,
             val: None,
         },
     ];
 
+    assert!(first.is_empty());
     assert_eq!(got, expected,)
 }
+
+/// Values given on the command line, where a minus sign may precede an integer
+#[test]
+fn lex_value_literal() {
+    assert_eq!(Val::from_literal("5"), Some(Ok(Val::U64(5))));
+    assert_eq!(Val::from_literal("-5"), Some(Ok(Val::I64(-5))));
+    assert_eq!(Val::from_literal("-128i8"), Some(Ok(Val::I8(-128))));
+    assert_eq!(Val::from_literal("-0x10"), Some(Ok(Val::I64(-16))));
+    assert_eq!(
+        Val::from_literal("10.0.0.1"),
+        Some(Ok(Val::Ip4("10.0.0.1".parse().unwrap())))
+    );
+    assert_eq!(Val::from_literal("-129i8").map(|r| r.is_err()), Some(true));
+    assert_eq!(Val::from_literal("-x"), None);
+    assert_eq!(Val::from_literal("-\"x\""), None);
+    assert_eq!(Val::from_literal("a b"), None);
+}
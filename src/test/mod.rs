@@ -1,4 +1,9 @@
+mod analysis;
 mod args;
+mod check;
+mod decompile;
+mod format;
 mod lex;
 mod object;
+mod program;
 mod str;
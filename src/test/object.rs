@@ -5,7 +5,7 @@ use crate::sym::Symbol;
 use crate::val::{Val, ValDef};
 
 #[derive(Debug, PartialEq, Eq)]
-pub(self) struct Tcp {
+struct Tcp {
     pub cl_seq: u32,
     pub sv_seq: u32,
 }
@@ -25,13 +25,13 @@ impl Class for Tcp {
 }
 
 impl Tcp {
-    pub(self) fn new(cl_seq: u32, sv_seq: u32) -> Self {
+    fn new(cl_seq: u32, sv_seq: u32) -> Self {
         Self { cl_seq, sv_seq }
     }
 }
 
 #[derive(Debug, PartialEq, Eq)]
-pub(self) struct Udp {}
+struct Udp {}
 
 const UDP: ClassDef = class!(
     /// UDP Session
@@ -48,7 +48,7 @@ impl Class for Udp {
 }
 
 impl Udp {
-    pub(self) fn new() -> Self {
+    fn new() -> Self {
         Self {}
     }
 }
@@ -0,0 +1,1411 @@
+use crate::err::Error::{
+    AssertionError, CircularImportError, ImportError, IndexError, MultipleAssignError, NameError,
+    OverflowError, ParseError, PoisonError, RuntimeError, TypeError, ZeroDivisionError,
+};
+use crate::err::{Diagnostic, Error};
+use crate::lex::{Lexer, EOF};
+use crate::lint::Lint;
+use crate::loc::{Loc, Span};
+use crate::parse::{Parser, Stmt};
+use crate::program::Program;
+use crate::trace::{Frame, Origin};
+use crate::val::{Val, ValType};
+
+use pkt::{CaptureWriter, Direction, LinkType, Meta, Packet, PcapWriter};
+
+use std::cell::RefCell;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::{env, fs, process};
+
+fn parse(src: &str) -> Result<Vec<Stmt>, Error> {
+    let mut lex = Lexer::default();
+    let mut parse = Parser::default();
+
+    for (lno, line) in src.lines().enumerate() {
+        for tok in lex.line(lno + 1, line)? {
+            parse.feed(&tok)?;
+        }
+    }
+
+    parse.feed(&EOF)?;
+
+    Ok(parse.get_results())
+}
+
+/// Run a program, returning each discarded value as a string
+fn run(src: &str) -> Result<Vec<String>, Error> {
+    let stmts = parse(src)?;
+    let mut discarded = Vec::new();
+    let mut warning = |lint: Lint, _: Loc, msg: &str| {
+        if lint == Lint::DiscardedValue {
+            discarded.push(msg.strip_prefix("discarded value ").unwrap().to_owned());
+        }
+    };
+
+    let mut prog = Program::dummy()?;
+    prog.set_warning(&mut warning);
+    prog.add_stmts(stmts)?;
+
+    Ok(discarded)
+}
+
+#[test]
+fn func_positional() {
+    let src = "
+        fn f(a, b) {
+            b;
+            a;
+        }
+        f(1, \"x\");
+    ";
+    assert_eq!(
+        run(src),
+        Ok(vec!["Str(Bytes<\"x\">)".to_owned(), "U64(1)".to_owned()])
+    );
+}
+
+#[test]
+fn func_named_and_default() {
+    let src = "
+        fn f(a, b: 2, c: true) {
+            a;
+            b;
+            c;
+        }
+        f(c: false, a: 1);
+    ";
+    assert_eq!(
+        run(src),
+        Ok(vec![
+            "U64(1)".to_owned(),
+            "U64(2)".to_owned(),
+            "Bool(false)".to_owned()
+        ])
+    );
+}
+
+#[test]
+fn func_binding_errors() {
+    assert_eq!(run("fn f(a) {} f();"), Err(TypeError));
+    assert_eq!(run("fn f(a) {} f(1, 2);"), Err(TypeError));
+    assert_eq!(run("fn f(a) {} f(1, b: 2);"), Err(TypeError));
+    assert_eq!(run("fn f(a, a) {} f(1, 2);"), Err(ParseError));
+    assert_eq!(run("fn f(a, b: 1, b: 2) {} f(1);"), Err(ParseError));
+}
+
+#[test]
+fn func_default_before_positional() {
+    assert_eq!(parse("fn f(a: 1, b) {}").err(), Some(ParseError));
+}
+
+#[test]
+fn func_nested_decl() {
+    assert_eq!(parse("fn f() { fn g() {} }").err(), Some(ParseError));
+    assert_eq!(parse("fn f() { import ipv4; }").err(), Some(ParseError));
+}
+
+#[test]
+fn func_local_scope() {
+    assert_eq!(run("fn f(a) { let b = a; } f(1); b;"), Err(NameError));
+    assert_eq!(
+        run("fn f(a) { let a = 2; } f(1);"),
+        Err(MultipleAssignError("a".to_owned()))
+    );
+    assert_eq!(
+        run("let a = 1; fn f() { let a = 2; a; } f(); a;"),
+        Ok(vec!["U64(2)".to_owned(), "U64(1)".to_owned()])
+    );
+}
+
+#[test]
+fn func_sees_globals_not_caller_locals() {
+    assert_eq!(
+        run("let x = 1; fn f() { x; } f();"),
+        Ok(vec!["U64(1)".to_owned()])
+    );
+    assert_eq!(run("fn f() { y; } fn g(y) { f(); } g(1);"), Err(NameError));
+}
+
+#[test]
+fn func_redefine() {
+    assert_eq!(
+        run("let f = 1; fn f() {}"),
+        Err(MultipleAssignError("f".to_owned()))
+    );
+}
+
+#[test]
+fn for_range() {
+    assert_eq!(
+        run("for x in range(1, 4) { x; }"),
+        Ok(vec![
+            "U64(1)".to_owned(),
+            "U64(2)".to_owned(),
+            "U64(3)".to_owned()
+        ])
+    );
+    assert_eq!(
+        run("for x in range(0, 10, step: 4) { x; }"),
+        Ok(vec![
+            "U64(0)".to_owned(),
+            "U64(4)".to_owned(),
+            "U64(8)".to_owned()
+        ])
+    );
+    assert_eq!(run("for x in range(4, 1) { x; }"), Ok(vec![]));
+    assert_eq!(
+        run("for x in range(0, 1, step: 0) { x; }"),
+        Err(RuntimeError)
+    );
+    assert_eq!(
+        run("for x in range(0, 18446744073709551615) { x; }"),
+        Err(RuntimeError)
+    );
+    assert_eq!(
+        run("len(range(0, 18446744073709551615, step: 1152921504606846976));"),
+        Ok(vec!["U64(16)".to_owned()])
+    );
+}
+
+#[test]
+fn for_nested_in_func() {
+    let src = "
+        fn f(n) {
+            for i in range(0, n) {
+                let j = i;
+                for k in range(j, n) {
+                    k;
+                }
+            }
+        }
+        f(2);
+    ";
+    assert_eq!(
+        run(src),
+        Ok(vec![
+            "U64(0)".to_owned(),
+            "U64(1)".to_owned(),
+            "U64(1)".to_owned()
+        ])
+    );
+}
+
+#[test]
+fn for_scope() {
+    assert_eq!(run("for x in range(0, 1) {} x;"), Err(NameError));
+    assert_eq!(
+        run("let y = 1; for x in range(0, 2) { let y = x; }"),
+        Ok(vec![])
+    );
+    assert_eq!(run("for x in 1 {}"), Err(TypeError));
+}
+
+/// Evaluate a single expression
+fn eval(expr: &str) -> Result<String, Error> {
+    let mut ret = run(&format!("let ret = {};\nret;", expr))?;
+    assert_eq!(ret.len(), 1);
+    Ok(ret.pop().unwrap())
+}
+
+#[test]
+fn binop_precedence() {
+    assert_eq!(eval("1 + 2 * 3"), Ok("U64(7)".to_owned()));
+    assert_eq!(eval("(1 + 2) * 3"), Ok("U64(9)".to_owned()));
+    assert_eq!(eval("10 - 4 - 3"), Ok("U64(3)".to_owned()));
+    assert_eq!(eval("100 / 10 / 5"), Ok("U64(2)".to_owned()));
+    assert_eq!(eval("1 << 2 + 1"), Ok("U64(8)".to_owned()));
+    assert_eq!(eval("0xf0 | 0x0f & 0x3c ^ 0x01"), Ok("U64(253)".to_owned()));
+    assert_eq!(eval("1 + 2 * 3 == 7"), Ok("Bool(true)".to_owned()));
+    assert_eq!(eval("17 % 5 < 2 + 1"), Ok("Bool(true)".to_owned()));
+    assert_eq!(eval("((1))"), Ok("U64(1)".to_owned()));
+}
+
+#[test]
+fn binop_int_widths() {
+    assert_eq!(
+        run("import ipv4; let x = ipv4::proto::UDP << 4;"),
+        Err(OverflowError)
+    );
+    assert_eq!(
+        run("import ipv4; let x = ipv4::proto::TCP + 1; x;"),
+        Ok(vec!["U64(7)".to_owned()])
+    );
+    assert_eq!(
+        run("import ipv4; let x = ipv4::proto::TCP * 100; x;"),
+        Ok(vec!["U64(600)".to_owned()])
+    );
+    assert_eq!(
+        run("import ipv4; let x = ipv4::proto::UDP == 17; x;"),
+        Ok(vec!["Bool(true)".to_owned()])
+    );
+}
+
+#[test]
+fn binop_overflow() {
+    assert_eq!(eval("0 - 1"), Err(OverflowError));
+    assert_eq!(eval("0xffffffffffffffff + 1"), Err(OverflowError));
+    assert_eq!(eval("0x8000000000000000 * 2"), Err(OverflowError));
+    assert_eq!(eval("1 << 64"), Err(OverflowError));
+    assert_eq!(eval("1 / 0"), Err(ZeroDivisionError));
+    assert_eq!(eval("1 % 0"), Err(ZeroDivisionError));
+}
+
+#[test]
+fn binop_ip4() {
+    assert_eq!(eval("10.0.0.1 + 255"), Ok("Ip4(10.0.1.0)".to_owned()));
+    assert_eq!(eval("1 + 10.0.0.1"), Ok("Ip4(10.0.0.2)".to_owned()));
+    assert_eq!(eval("10.0.1.0 - 1"), Ok("Ip4(10.0.0.255)".to_owned()));
+    assert_eq!(
+        eval("(10.0.0.1 + 2) / 80"),
+        Ok("Sock4(10.0.0.3:80)".to_owned())
+    );
+    assert_eq!(
+        eval("10.0.0.1 / (8000 + 80)"),
+        Ok("Sock4(10.0.0.1:8080)".to_owned())
+    );
+    assert_eq!(eval("10.0.0.1 < 10.0.0.2"), Ok("Bool(true)".to_owned()));
+    assert_eq!(eval("255.255.255.255 + 1"), Err(OverflowError));
+    assert_eq!(eval("10.0.0.1 / 65536"), Err(OverflowError));
+    assert_eq!(eval("10.0.0.1 + 10.0.0.1"), Err(TypeError));
+}
+
+#[test]
+fn binop_types() {
+    assert_eq!(eval("\"a\" == \"a\""), Ok("Bool(true)".to_owned()));
+    assert_eq!(eval("\"a\" != 1"), Ok("Bool(true)".to_owned()));
+    assert_eq!(eval("true ^ true"), Ok("Bool(false)".to_owned()));
+    assert_eq!(eval("\"a\" + 1"), Err(TypeError));
+    assert_eq!(eval("\"a\" < \"b\""), Err(TypeError));
+    assert_eq!(eval("true + 1"), Err(TypeError));
+}
+
+#[test]
+fn binop_loc() {
+    let stmts = parse("let x = 1;\nlet y = x +\n  \"a\";").unwrap();
+    let mut prog = Program::dummy().unwrap();
+    assert_eq!(prog.add_stmts(stmts), Err(TypeError));
+    assert_eq!(prog.loc(), Loc::new(2, 11));
+}
+
+#[test]
+fn binop_in_args() {
+    let src = "
+        fn f(a, b: 1) {
+            a;
+            b;
+        }
+        f(1 + 2, b: (3 - 1) * 2);
+    ";
+    assert_eq!(run(src), Ok(vec!["U64(3)".to_owned(), "U64(4)".to_owned()]));
+}
+
+#[test]
+fn if_else() {
+    let src = "
+        let flag = true;
+        if flag {
+            let x = 1;
+            x;
+        } else {
+            let x = 2;
+            x;
+        }
+        if flag == false {
+            let x = 3;
+            x;
+        }
+    ";
+    assert_eq!(run(src), Ok(vec!["U64(1)".to_owned()]));
+}
+
+#[test]
+fn if_else_if() {
+    let src = "
+        fn classify(n) {
+            if n < 10 {
+                let x = \"small\";
+                x;
+            } else if n < 100 {
+                let x = \"medium\";
+                x;
+            } else {
+                let x = \"large\";
+                x;
+            }
+        }
+        for n in range(5, 500, step: 90) {
+            classify(n);
+        }
+    ";
+    assert_eq!(
+        run(src),
+        Ok(vec![
+            "Str(Bytes<\"small\">)".to_owned(),
+            "Str(Bytes<\"medium\">)".to_owned(),
+            "Str(Bytes<\"large\">)".to_owned(),
+            "Str(Bytes<\"large\">)".to_owned(),
+            "Str(Bytes<\"large\">)".to_owned(),
+            "Str(Bytes<\"large\">)".to_owned(),
+        ])
+    );
+}
+
+#[test]
+fn if_scope() {
+    assert_eq!(run("if true { let x = 1; } x;"), Err(NameError));
+    assert_eq!(
+        run("let x = 1; if true { let x = 2; x; } x;"),
+        Ok(vec!["U64(2)".to_owned(), "U64(1)".to_owned()])
+    );
+}
+
+#[test]
+fn if_errors() {
+    assert_eq!(run("if 1 { }"), Err(TypeError));
+    assert_eq!(run("if true { } else"), Err(ParseError));
+    assert_eq!(run("if true { } else true { }"), Err(ParseError));
+    assert_eq!(run("if true"), Err(ParseError));
+    assert_eq!(run("if true { import ipv4; }"), Err(ParseError));
+}
+
+#[test]
+fn mut_reassign() {
+    assert_eq!(
+        run("let mut x = 1; x = x + 1; x;"),
+        Ok(vec!["U64(2)".to_owned()])
+    );
+    assert_eq!(
+        run("let mut n = 0; for i in range(0, 4) { n = n + i; } n;"),
+        Ok(vec!["U64(6)".to_owned()])
+    );
+    assert_eq!(
+        run("let mut s = \"a\"; if true { s = \"b\"; } s;"),
+        Ok(vec!["Str(Bytes<\"b\">)".to_owned()])
+    );
+    assert_eq!(
+        run("let mut x = 1; fn f() { x = 5; } f(); x;"),
+        Ok(vec!["U64(5)".to_owned()])
+    );
+}
+
+#[test]
+fn mut_reassign_errors() {
+    assert_eq!(
+        run("let x = 1; x = 2;"),
+        Err(MultipleAssignError("x".to_owned()))
+    );
+    assert_eq!(
+        run("for i in range(0, 2) { i = 1; }"),
+        Err(MultipleAssignError("i".to_owned()))
+    );
+    assert_eq!(
+        run("let mut x = 1; let x = 2;"),
+        Err(MultipleAssignError("x".to_owned()))
+    );
+    assert_eq!(run("x = 2;"), Err(NameError));
+    assert_eq!(
+        run("let mut x = 1; fn f(x) { x = 5; } f(1);"),
+        Err(MultipleAssignError("x".to_owned()))
+    );
+    assert_eq!(run("ipv4::x = 1;"), Err(ParseError));
+    assert_eq!(run("f() = 1;"), Err(ParseError));
+    assert_eq!(run("let mut = 1;"), Err(ParseError));
+}
+
+#[test]
+fn list_literal() {
+    assert_eq!(eval("[]"), Ok("List([])".to_owned()));
+    assert_eq!(
+        eval("[1, \"a\", 1.2.3.4,]"),
+        Ok("List([U64(1), Str(Bytes<\"a\">), Ip4(1.2.3.4)])".to_owned())
+    );
+    assert_eq!(
+        eval("[[1 + 1], [], range(0, 1)]"),
+        Ok("List([List([U64(2)]), List([]), List([U64(0)])])".to_owned())
+    );
+    assert_eq!(run("let x = [1, 2;"), Err(ParseError));
+    assert_eq!(run("let x = [1 2];"), Err(ParseError));
+    assert_eq!(run("let x = [,];"), Err(ParseError));
+}
+
+#[test]
+fn list_index() {
+    assert_eq!(eval("[1, 2, 3][1]"), Ok("U64(2)".to_owned()));
+    assert_eq!(eval("[[1, 2], [3, 4]][1][0]"), Ok("U64(3)".to_owned()));
+    assert_eq!(eval("1 + [1, 2, 3][1 + 1] * 2"), Ok("U64(7)".to_owned()));
+    assert_eq!(eval("range(10, 20)[5]"), Ok("U64(15)".to_owned()));
+    assert_eq!(
+        run("let x = [10.0.0.1]; let y = x[0] + 1; y;"),
+        Ok(vec!["Ip4(10.0.0.2)".to_owned()])
+    );
+    assert_eq!(eval("[1, 2, 3][3]"), Err(IndexError));
+    assert_eq!(eval("[][0]"), Err(IndexError));
+    assert_eq!(eval("[1, 2, 3][\"a\"]"), Err(TypeError));
+    assert_eq!(eval("1[0]"), Err(TypeError));
+    assert_eq!(run("let x = [1][0;"), Err(ParseError));
+}
+
+#[test]
+fn list_len() {
+    assert_eq!(eval("len([])"), Ok("U64(0)".to_owned()));
+    assert_eq!(eval("len([1, [2, 3]])"), Ok("U64(2)".to_owned()));
+    assert_eq!(eval("len(range(0, 100))"), Ok("U64(100)".to_owned()));
+    assert_eq!(eval("len(1)"), Err(TypeError));
+}
+
+#[test]
+fn list_splat() {
+    let src = "
+        fn f(a, b: 2) {
+            a;
+            b;
+        }
+        let args = [5, 6];
+        f(*args);
+        f(*[7]);
+        f(*[5], b: 8);
+    ";
+    assert_eq!(
+        run(src),
+        Ok(vec![
+            "U64(5)".to_owned(),
+            "U64(6)".to_owned(),
+            "U64(7)".to_owned(),
+            "U64(2)".to_owned(),
+            "U64(5)".to_owned(),
+            "U64(8)".to_owned(),
+        ])
+    );
+
+    /* Into the variadic args of a stdlib function */
+    let src = "
+        import tls;
+        let names = [\"a.com\", \"b.com\"];
+        let x = tls::sni(*names) == tls::sni(\"a.com\", \"b.com\");
+        x;
+        let y = tls::sni(\"a.com\", *[\"b.com\"]) == tls::sni(\"a.com\", \"b.com\");
+        y;
+    ";
+    assert_eq!(
+        run(src),
+        Ok(vec!["Bool(true)".to_owned(), "Bool(true)".to_owned()])
+    );
+
+    assert_eq!(run("fn f(a) { } f(*[1, 2]);"), Err(TypeError));
+    assert_eq!(
+        run("import dns; let x = dns::host(1.1.1.1, \"a.com\", *[\"x\"]);"),
+        Err(TypeError)
+    );
+    assert_eq!(run("fn f(a) { } f(*1);"), Err(TypeError));
+    assert_eq!(run("fn f(a) { } f(*);"), Err(ParseError));
+}
+
+#[test]
+fn int_literals() {
+    assert_eq!(eval("5u16"), Ok("U16(5)".to_owned()));
+    assert_eq!(eval("0xffu8"), Ok("U8(255)".to_owned()));
+    assert_eq!(eval("0x7fffffffi32"), Ok("I32(2147483647)".to_owned()));
+    assert_eq!(eval("-1i32"), Ok("I32(-1)".to_owned()));
+    assert_eq!(eval("-1"), Ok("I64(-1)".to_owned()));
+    assert_eq!(eval("-(2 + 3) * 2"), Ok("I64(-10)".to_owned()));
+    assert_eq!(eval("--5u8"), Ok("I8(5)".to_owned()));
+    assert_eq!(eval("-[1, 2][1]"), Ok("I64(-2)".to_owned()));
+    assert_eq!(eval("2 * -3i8 + 1"), Ok("I64(-5)".to_owned()));
+    assert_eq!(eval("2i8 * -3i8 + 1i8"), Ok("I8(-5)".to_owned()));
+    assert_eq!(eval("256u8"), Err(OverflowError));
+    assert_eq!(eval("0x80i8"), Err(OverflowError));
+    assert_eq!(eval("-128i8"), Ok("I8(-128)".to_owned()));
+    assert_eq!(eval("-0x8000i16"), Ok("I16(-32768)".to_owned()));
+    assert_eq!(eval("-2147483648i32"), Ok("I32(-2147483648)".to_owned()));
+    assert_eq!(
+        eval("-9223372036854775808i64"),
+        Ok("I64(-9223372036854775808)".to_owned())
+    );
+    assert_eq!(
+        eval("-9223372036854775808"),
+        Ok("I64(-9223372036854775808)".to_owned())
+    );
+    assert_eq!(eval("-129i8"), Err(OverflowError));
+    assert_eq!(eval("-128u8"), Ok("I8(-128)".to_owned()));
+    assert_eq!(eval("- -128i8"), Err(OverflowError));
+    assert_eq!(
+        eval("-0x8000000000000000"),
+        Ok("I64(-9223372036854775808)".to_owned())
+    );
+    assert_eq!(eval("-0x8000000000000001"), Err(OverflowError));
+    assert_eq!(eval("-true"), Err(TypeError));
+    assert_eq!(eval("5u7"), Err(ParseError));
+}
+
+#[test]
+fn int_promotion() {
+    assert_eq!(eval("1u8 + 1u16"), Ok("U16(2)".to_owned()));
+    assert_eq!(eval("255u8 + 1u8"), Err(OverflowError));
+    assert_eq!(eval("1u32 - 2i8"), Ok("I32(-1)".to_owned()));
+    assert_eq!(eval("-1 < 0"), Ok("Bool(true)".to_owned()));
+    assert_eq!(eval("-1i8 == 255u8"), Ok("Bool(false)".to_owned()));
+    assert_eq!(eval("-8i8 >> 1"), Ok("I8(-4)".to_owned()));
+    assert_eq!(eval("1u8 << 8"), Err(OverflowError));
+    assert_eq!(eval("10.0.0.1 + -1"), Ok("Ip4(10.0.0.0)".to_owned()));
+}
+
+#[test]
+fn int_casts() {
+    assert_eq!(eval("u8(255)"), Ok("U8(255)".to_owned()));
+    assert_eq!(eval("u8(300)"), Err(OverflowError));
+    assert_eq!(eval("u16(-1)"), Err(OverflowError));
+    assert_eq!(eval("i8(-128)"), Ok("I8(-128)".to_owned()));
+    assert_eq!(eval("i64(0xffffffffffffffff)"), Err(OverflowError));
+    assert_eq!(eval("u64(-1i8 + 1)"), Ok("U64(0)".to_owned()));
+    assert_eq!(eval("u32(true)"), Ok("U32(1)".to_owned()));
+    assert_eq!(eval("u8(\"a\")"), Err(TypeError));
+
+    /* A range failure gives the value and the range, not a type mismatch */
+    let err = eval("u8(300)").unwrap_err();
+    let diag = err.diag().unwrap();
+    assert_eq!(diag.msg.as_deref(), Some("u8: 300 is out of range 0..=255"));
+    assert_eq!((diag.expected, diag.received), (None, None));
+    let err = eval("i16(-0x8001)").unwrap_err();
+    assert_eq!(
+        err.diag().unwrap().msg.as_deref(),
+        Some("i16: -32769 is out of range -32768..=32767")
+    );
+    let err = eval("u8(\"a\")").unwrap_err();
+    assert_eq!(err.diag().unwrap().received, Some(ValType::Str));
+}
+
+#[test]
+fn int_arg_range() {
+    let src = "
+        import ipv4;
+        let x = ipv4::datagram(1.1.1.1, 2.2.2.2, ttl: 300);
+    ";
+    assert_eq!(run(src), Err(OverflowError));
+
+    let src = "
+        import ipv4;
+        let x = ipv4::datagram(1.1.1.1, 2.2.2.2, ttl: -1);
+    ";
+    assert_eq!(run(src), Err(OverflowError));
+
+    let src = "
+        import ipv4;
+        let x = ipv4::datagram(1.1.1.1, 2.2.2.2, ttl: 255u64, id: 65535);
+    ";
+    assert_eq!(run(src), Ok(vec![]));
+
+    assert_eq!(run("let x = 1.2.3.4:65535;"), Ok(vec![]));
+    assert_eq!(run("let x = 1.2.3.4:65536;"), Err(OverflowError));
+}
+
+/// Write out a set of source files in to a fresh temporary directory
+fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = env::temp_dir().join(format!("resynth-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    for (path, src) in files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, src).unwrap();
+    }
+
+    dir
+}
+
+/// Run a source file, returning each discarded value as a string
+fn run_file(path: &Path) -> Result<Vec<String>, Error> {
+    let stmts = parse(&fs::read_to_string(path).unwrap())?;
+    let mut discarded = Vec::new();
+    let mut warning = |lint: Lint, _: Loc, msg: &str| {
+        if lint == Lint::DiscardedValue {
+            discarded.push(msg.strip_prefix("discarded value ").unwrap().to_owned());
+        }
+    };
+
+    let mut prog = Program::dummy()?;
+    prog.set_warning(&mut warning);
+    prog.set_path(path);
+    prog.add_stmts(stmts)?;
+
+    Ok(discarded)
+}
+
+#[test]
+fn import_module() {
+    let dir = write_files(
+        "import_module",
+        &[
+            (
+                "main.rsyn",
+                "
+                let base = 1;
+                import \"common/hosts.rsyn\" as hosts;
+                let x = hosts::base + base;
+                x;
+                hosts::show(5);
+                let f = hosts::show;
+                f(6);
+                ",
+            ),
+            (
+                "common/hosts.rsyn",
+                "
+                import \"net.rsyn\" as net;
+                let base = net::prefix + 0x100;
+                fn show(i) {
+                    let x = base + i;
+                    x;
+                }
+                ",
+            ),
+            ("common/net.rsyn", "let prefix = 10.0.0.0;"),
+        ],
+    );
+
+    assert_eq!(
+        run_file(&dir.join("main.rsyn")),
+        Ok(vec![
+            "Ip4(10.0.1.1)".to_owned(),
+            "Ip4(10.0.1.5)".to_owned(),
+            "Ip4(10.0.1.6)".to_owned(),
+        ])
+    );
+}
+
+#[test]
+fn import_module_once() {
+    let dir = write_files(
+        "import_module_once",
+        &[
+            (
+                "main.rsyn",
+                "
+                import \"a.rsyn\" as a;
+                import \"b.rsyn\" as b;
+                import \"a.rsyn\" as c;
+                ",
+            ),
+            ("a.rsyn", "let x = 1; x;"),
+            ("b.rsyn", "import \"a.rsyn\" as a;"),
+        ],
+    );
+
+    assert_eq!(
+        run_file(&dir.join("main.rsyn")),
+        Ok(vec!["U64(1)".to_owned()])
+    );
+}
+
+#[test]
+fn import_module_errors() {
+    let dir = write_files(
+        "import_module_errors",
+        &[
+            ("cycle.rsyn", "import \"a.rsyn\" as a;"),
+            ("a.rsyn", "import \"b.rsyn\" as b;"),
+            ("b.rsyn", "import \"a.rsyn\" as a;"),
+            ("self.rsyn", "import \"self.rsyn\" as me;"),
+            ("missing.rsyn", "import \"nope.rsyn\" as nope;"),
+            ("name.rsyn", "import \"a2.rsyn\" as a; let y = a::y;"),
+            ("a2.rsyn", "let x = 1;"),
+            ("broken.rsyn", "import \"syntax.rsyn\" as s;"),
+            ("syntax.rsyn", "let x = ;"),
+        ],
+    );
+
+    assert_eq!(
+        run_file(&dir.join("cycle.rsyn")),
+        Err(CircularImportError("a.rsyn".to_owned()))
+    );
+    assert_eq!(
+        run_file(&dir.join("self.rsyn")),
+        Err(CircularImportError("self.rsyn".to_owned()))
+    );
+    assert_eq!(
+        run_file(&dir.join("missing.rsyn")),
+        Err(ImportError("nope.rsyn".to_owned()))
+    );
+    assert_eq!(run_file(&dir.join("name.rsyn")), Err(NameError));
+    assert_eq!(run_file(&dir.join("broken.rsyn")), Err(ParseError));
+    assert_eq!(run("import \"x.rsyn\";"), Err(ParseError));
+    assert_eq!(run("import \"x.rsyn\" as;"), Err(ParseError));
+}
+
+#[test]
+fn text_format() {
+    assert_eq!(
+        run("import text; text::format(\"{} {}/{}\", 10.0.0.1 / 80, 1 + 1, -1i8);"),
+        Ok(vec!["Str(Bytes<\"10.0.0.1:80 2/-1\">)".to_owned()])
+    );
+    assert_eq!(run("import text; text::format.x;"), Err(TypeError));
+}
+
+#[test]
+fn text_manipulation() {
+    let src = "
+        import text;
+        text::slice(\"hello world\", 6);
+        text::slice(\"hello world\", 0, 5);
+        text::repeat(\"AB\", 3);
+        text::pad(\"A\", 4, byte: 0x42);
+        text::pad(\"ABCD\", 2);
+        text::find(\"hello world\", \"o\");
+        text::find(\"hello\", \"z\");
+        text::replace(\"a.b.c\", \".\", \"::\");
+        text::split(\"a,b,,c\", \",\");
+        text::upper(\"GET /a?b=c\");
+        text::lower(\"Host\");
+        text::xor(\"|00 01 02 03|\", \"|ff 0f|\");
+    ";
+    assert_eq!(
+        run(src),
+        Ok(vec![
+            "Str(Bytes<\"world\">)".to_owned(),
+            "Str(Bytes<\"hello\">)".to_owned(),
+            "Str(Bytes<\"ABABAB\">)".to_owned(),
+            "Str(Bytes<\"ABBB\">)".to_owned(),
+            "Str(Bytes<\"ABCD\">)".to_owned(),
+            "I64(4)".to_owned(),
+            "I64(-1)".to_owned(),
+            "Str(Bytes<\"a::b::c\">)".to_owned(),
+            "List([Str(Bytes<\"a\">), Str(Bytes<\"b\">), Str(Bytes<\"\">), Str(Bytes<\"c\">)])"
+                .to_owned(),
+            "Str(Bytes<\"GET /A?B=C\">)".to_owned(),
+            "Str(Bytes<\"host\">)".to_owned(),
+            "Str(Bytes<\"\\xff\\x0e\\xfd\\x0c\">)".to_owned(),
+        ])
+    );
+
+    assert_eq!(
+        run("import text; text::repeat(\"AB\", 9223372036854775808);"),
+        Err(RuntimeError)
+    );
+    assert_eq!(
+        run("import text; text::repeat(\"A\", 16777217);"),
+        Err(RuntimeError)
+    );
+    assert_eq!(
+        run("import text; text::pad(\"A\", 18446744073709551615);"),
+        Err(RuntimeError)
+    );
+    assert_eq!(
+        run("
+            import text;
+            let a = text::repeat(\"a\", 4096);
+            let b = text::replace(a, \"a\", a);
+            text::replace(b, \"a\", b);
+        "),
+        Err(RuntimeError)
+    );
+    assert_eq!(
+        run("import text; text::repeat(\"\", 18446744073709551615);"),
+        Ok(vec!["Str(Bytes<\"\">)".to_owned()])
+    );
+}
+
+#[test]
+fn text_manipulation_errors() {
+    assert_eq!(
+        run("import text; text::slice(\"abc\", 2, 1);"),
+        Err(IndexError)
+    );
+    assert_eq!(
+        run("import text; text::slice(\"abc\", 0, 4);"),
+        Err(IndexError)
+    );
+    assert_eq!(
+        run("import text; text::split(\"abc\", \"\");"),
+        Err(RuntimeError)
+    );
+    assert_eq!(
+        run("import text; text::replace(\"abc\", \"\", \"x\");"),
+        Err(RuntimeError)
+    );
+    assert_eq!(
+        run("import text; text::xor(\"abc\", \"\");"),
+        Err(RuntimeError)
+    );
+}
+
+fn diagnose(src: &str) -> Diagnostic {
+    let err = parse(src)
+        .and_then(|stmts| Program::dummy()?.add_stmts(stmts))
+        .unwrap_err();
+    *match err {
+        Error::Diag(diag) => diag,
+        err => panic!("{:?} has no diagnostic", err),
+    }
+}
+
+#[test]
+fn diag_arg_type() {
+    let diag = diagnose("import text;\nlet x = text::slice(\"abc\", start: \"1\");");
+    assert_eq!(diag.err, TypeError);
+    assert_eq!(diag.arg.as_deref(), Some("start"));
+    assert_eq!(diag.expected, Some(ValType::U64));
+    assert_eq!(diag.received, Some(ValType::Str));
+    assert_eq!(diag.span, Some(Span::new(Loc::new(2, 35), Loc::new(2, 38))));
+
+    let diag = diagnose("fn f(a, b) {}\nf(1);");
+    assert_eq!(diag.err, TypeError);
+    assert_eq!(diag.span, Some(Span::new(Loc::new(2, 1), Loc::new(2, 5))));
+}
+
+#[test]
+fn diag_spans() {
+    let diag = diagnose("let x = 1;\nlet y = [x, x +\n  \"a\"];");
+    assert_eq!(diag.err, TypeError);
+    assert_eq!(diag.span, Some(Span::new(Loc::new(2, 13), Loc::new(3, 6))));
+
+    let diag = diagnose("let x = 1;\nlet y = zz;");
+    assert_eq!(diag.err, NameError);
+    assert_eq!(diag.msg.as_deref(), Some("Can't find variable: zz"));
+    assert_eq!(diag.span, Some(Span::new(Loc::new(2, 9), Loc::new(2, 11))));
+
+    let diag = diagnose("let x = 1;\nlet x = 2;");
+    assert_eq!(diag.span, Some(Span::new(Loc::new(2, 5), Loc::new(2, 6))));
+
+    let diag = diagnose("let x = (;");
+    assert_eq!(diag.err, ParseError);
+    assert_eq!(diag.msg.as_deref(), Some("Unexpected `;`"));
+    assert_eq!(diag.span, Some(Span::new(Loc::new(1, 10), Loc::new(1, 11))));
+}
+
+/// Parse and run a whole program, carrying on after errors, returning the kinds of all of them
+fn run_all(src: &str) -> Vec<String> {
+    let mut lex = Lexer::default();
+    let mut parse = Parser::default();
+    let mut prog = Program::dummy().unwrap();
+    let mut errors = Vec::new();
+
+    let toks = src
+        .lines()
+        .enumerate()
+        .flat_map(|(lno, line)| lex.line(lno + 1, line).unwrap())
+        .chain(std::iter::once(EOF));
+
+    for tok in toks {
+        if let Err(err) = parse.feed(&tok) {
+            errors.push(err);
+        }
+        for name in parse.get_poisoned() {
+            prog.poison(&name);
+        }
+        for stmt in parse.get_results() {
+            if let Err(err) = prog.add_stmt(stmt) {
+                errors.push(err);
+            }
+        }
+    }
+
+    errors.iter().map(|err| err.kind().to_string()).collect()
+}
+
+#[test]
+fn recover_parse_errors() {
+    let src = "
+        let a = (;
+        let b = 1;
+        fn f(x) {
+            let y = x + ;
+            if x { let z = ]; } else { x; }
+            x;
+        }
+        let c = a + f(b);
+        for i in [1] { i + ; } }
+        let d = 2
+        let e = 3;
+    ";
+    assert_eq!(
+        run_all(src),
+        vec![
+            "Parse Error",
+            "Parse Error",
+            "Parse Error",
+            "'a' is undefined due to an earlier error",
+            "Parse Error",
+            "Parse Error",
+            "Parse Error",
+        ]
+    );
+}
+
+#[test]
+fn recover_poisoned() {
+    let src = "
+        import nosuch;
+        let a = 1 + \"x\";
+        let b = a + 1;
+        let mut c = nosuch::thing;
+        c = b;
+        let d = zz;
+        fn f(x: zz) {}
+        f();
+    ";
+    assert_eq!(
+        run_all(src),
+        vec![
+            "Import Error: Unknown module 'nosuch'",
+            "Type Error",
+            "'a' is undefined due to an earlier error",
+            "'nosuch' is undefined due to an earlier error",
+            "'c' is undefined due to an earlier error",
+            "Name Error",
+            "Name Error",
+            "'f' is undefined due to an earlier error",
+        ]
+    );
+
+    let mut prog = Program::dummy().unwrap();
+    prog.poison("x");
+    let stmts = parse("let y = x;").unwrap();
+    assert_eq!(prog.add_stmts(stmts), Err(PoisonError("x".to_owned())));
+}
+
+/// Run a program to completion, returning each lint other than discarded values
+fn lints(src: &str) -> Vec<(Lint, usize, String)> {
+    let stmts = parse(src).unwrap();
+    let mut lints = Vec::new();
+    let mut warning = |lint: Lint, loc: Loc, msg: &str| {
+        if lint != Lint::DiscardedValue {
+            lints.push((lint, loc.line(), msg.to_owned()));
+        }
+    };
+
+    let mut prog = Program::dummy().unwrap();
+    prog.set_warning(&mut warning);
+    prog.add_stmts(stmts).unwrap();
+    prog.finish();
+    drop(prog);
+
+    lints
+}
+
+#[test]
+fn lint_unused_variable() {
+    let src = "
+        let a = 1;
+        let _b = 2;
+        let c = 3;
+        fn f(x) {
+            let y = x;
+            let z = 4;
+            z;
+        }
+        f(c);
+        f(c);
+        for i in range(0, 3) {
+            let w = i;
+        }
+    ";
+    assert_eq!(
+        lints(src),
+        vec![
+            (Lint::UnusedVariable, 6, "unused variable `y`".to_owned()),
+            (Lint::UnusedVariable, 13, "unused variable `w`".to_owned()),
+            (Lint::UnusedVariable, 2, "unused variable `a`".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn lint_unused_import() {
+    let src = "
+        import ipv4;
+        import text;
+        import dns;
+        import dns;
+        dns::name(\"x\");
+    ";
+    assert_eq!(
+        lints(src),
+        vec![
+            (
+                Lint::UnusedImport,
+                5,
+                "`dns` is already imported".to_owned()
+            ),
+            (Lint::UnusedImport, 2, "unused import `ipv4`".to_owned()),
+            (Lint::UnusedImport, 3, "unused import `text`".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn lint_unclosed_flow() {
+    let src = "
+        import ipv4;
+        let closed = ipv4::tcp::flow(1.2.3.4/1, 5.6.7.8/2);
+        closed.open();
+        closed.client_close();
+        let reset = ipv4::tcp::flow(1.2.3.4/3, 5.6.7.8/4);
+        reset.open();
+        reset.server_reset();
+        let open = ipv4::tcp::flow(1.2.3.4/5, 5.6.7.8/6);
+        open.open();
+        open.open();
+    ";
+    assert_eq!(
+        lints(src),
+        vec![(
+            Lint::UnclosedFlow,
+            10,
+            "flow is opened here but never closed or reset".to_owned()
+        )]
+    );
+}
+
+#[test]
+fn lint_unused_packet() {
+    let src = "
+        import ipv4;
+        let flow = ipv4::udp::flow(1.2.3.4/1, 5.6.7.8/2);
+        let sent = flow.client_dgram(\"a\");
+        sent;
+        let lost = flow.client_dgram(\"b\");
+    ";
+    assert_eq!(
+        lints(src),
+        vec![(
+            Lint::UnusedPacket,
+            6,
+            "packet `lost` was built but never emitted".to_owned()
+        )]
+    );
+}
+
+#[test]
+fn interactive() {
+    let mut lex = Lexer::default();
+    let mut parse = Parser::default();
+    let mut prog = Program::dummy().unwrap();
+    let mut vals = Vec::new();
+
+    for (lno, line) in ["let x = 1;", "x + 1;", "fn f(a) {", "a; }", "f(x);"]
+        .iter()
+        .enumerate()
+    {
+        for tok in lex.line(lno + 1, line).unwrap() {
+            parse.feed(&tok).unwrap();
+        }
+
+        /* Each statement is available as soon as it's complete */
+        parse.flush();
+        assert_eq!(parse.is_idle(), lno != 2);
+
+        for stmt in parse.get_results() {
+            if let Some(val) = prog.eval_stmt(stmt).unwrap() {
+                vals.push(format!("{:?}", val));
+            }
+        }
+    }
+
+    assert_eq!(vals, vec!["U64(2)", "Nil"]);
+}
+
+#[test]
+fn packet_origins() {
+    let src = "\
+import ipv4;
+let f = ipv4::udp::flow(1.1.1.1/1, 2.2.2.2/2);
+fn send(x) {
+  f.client_dgram(x);
+}
+send(\"a\");
+f.server_dgram(\"b\");
+";
+    let stmts = parse(src).unwrap();
+    let mut origins = Vec::new();
+    let mut packet = |_: u64, _: &Packet, origin: &Origin| origins.push(origin.clone());
+
+    let mut prog = Program::dummy().unwrap();
+    prog.set_packet_callback(&mut packet);
+    prog.add_stmts(stmts).unwrap();
+    drop(prog);
+
+    let send = Frame {
+        func: "send".to_owned(),
+        file: None,
+        loc: Loc::new(6, 1),
+    };
+    assert_eq!(
+        origins,
+        vec![
+            Origin {
+                file: None,
+                loc: Loc::new(4, 3),
+                calls: vec![send],
+            },
+            Origin {
+                file: None,
+                loc: Loc::new(7, 1),
+                calls: vec![],
+            },
+        ]
+    );
+    assert_eq!(origins[0].to_string(), "4:3, in send() from 6:1");
+}
+
+/// Keeps the metadata of the packets written to it
+struct MetaWriter(Rc<RefCell<Vec<Meta>>>);
+
+impl CaptureWriter for MetaWriter {
+    fn write_packet(&mut self, _: u64, pkt: &mut Packet) -> Result<(), io::Error> {
+        self.0.borrow_mut().push(pkt.meta().clone());
+        Ok(())
+    }
+
+    fn set_linktype(&mut self, _: LinkType) -> Result<(), io::Error> {
+        match self.0.borrow().is_empty() {
+            true => Ok(()),
+            false => Err(io::Error::other("too late")),
+        }
+    }
+
+    fn finish(&mut self) -> Result<(), io::Error> {
+        Ok(())
+    }
+}
+
+#[test]
+fn packet_meta() {
+    let src = "\
+import ipv4;
+import pcapng;
+let f = ipv4::udp::flow(1.1.1.1/1, 2.2.2.2/2, raw: true);
+pcapng::interface(\"tap0\", pcapng::comment(\"hello\", f.client_dgram(\"a\")));
+f.server_dgram(\"b\");
+";
+    let metas = Rc::new(RefCell::new(Vec::new()));
+    let mut prog = Program::with_pcap_writer(MetaWriter(metas.clone())).unwrap();
+    prog.set_comments(true);
+    prog.add_stmts(parse(src).unwrap()).unwrap();
+    drop(prog);
+
+    assert_eq!(
+        *metas.borrow(),
+        vec![
+            Meta {
+                interface: Some("tap0".to_owned()),
+                comments: vec!["hello".to_owned(), "4:1".to_owned()],
+                direction: Some(Direction::Outbound),
+                offset: None,
+                raw: true,
+            },
+            Meta {
+                interface: None,
+                comments: vec!["5:1".to_owned()],
+                direction: Some(Direction::Inbound),
+                offset: None,
+                raw: true,
+            },
+        ]
+    );
+}
+
+#[test]
+fn assertions() {
+    assert_eq!(
+        run("assert(1 + 1 == 2); assert(true, \"msg\");"),
+        Ok(vec![])
+    );
+
+    let err = run("let x = 3;\nassert(x < 2, \"x is too big\");").unwrap_err();
+    assert_eq!(err, AssertionError);
+    let diag = err.diag().unwrap();
+    assert_eq!(diag.msg.as_deref(), Some("x is too big"));
+    assert_eq!(diag.span.unwrap().start, Loc::new(2, 1));
+
+    assert_eq!(run("assert(false);"), Err(AssertionError));
+    assert_eq!(run("assert(\"x\");"), Err(TypeError));
+}
+
+#[test]
+fn linktypes() {
+    let run_with = |src: &str, fixed: Option<LinkType>| {
+        let mut prog = Program::with_pcap_writer(MetaWriter(Default::default()))?;
+        if let Some(linktype) = fixed {
+            prog.set_linktype(linktype)?;
+        }
+        prog.add_stmts(parse(src)?)
+    };
+    let pkt = "\
+import ipv4;
+let f = ipv4::udp::flow(1.1.1.1/1, 2.2.2.2/2);
+f.client_dgram(\"a\");
+";
+
+    assert_eq!(run("linktype(\"raw\");"), Ok(vec![]));
+    assert_eq!(run("linktype(\"token_ring\");"), Err(RuntimeError));
+
+    assert_eq!(
+        run_with(&format!("linktype(\"raw\");\n{}", pkt), None),
+        Ok(())
+    );
+
+    let err = run_with(&format!("{}linktype(\"raw\");", pkt), None).unwrap_err();
+    assert_eq!(err, RuntimeError);
+    assert_eq!(err.diag().unwrap().span.unwrap().start, Loc::new(4, 1));
+
+    /* The command line wins, so the script can't get it wrong */
+    let fixed = Some(LinkType::LinuxSll);
+    assert_eq!(
+        run_with(&format!("{}linktype(\"raw\");", pkt), fixed),
+        Ok(())
+    );
+}
+
+#[test]
+fn replay_pcap() {
+    let dir = write_files("replay", &[]);
+    let cap = dir.join("cap.pcap");
+    fs::create_dir_all(&dir).unwrap();
+    {
+        let mut wr = PcapWriter::create(&cap).unwrap();
+        for (time, payload) in [(5_000, b"a"), (7_000, b"b"), (3_000_000, b"c")] {
+            let mut pkt = Packet::default().with_raw(true);
+            pkt.push_bytes(b"\x45\x00\x00\x15\0\0\0\0\x40\x11\0\0\x01\x01\x01\x01\x02\x02\x02\x02");
+            pkt.push_bytes(payload);
+            wr.write_packet(time, &mut pkt).unwrap();
+        }
+    }
+
+    let src = format!(
+        "\
+import io;
+import time;
+let cap = io::pcap(\"{}\");
+time::jump_seconds(1);
+cap.packets();
+cap.slice(1, 2);
+cap.between(2);
+time::jump_seconds(1);
+for pkt in cap.packets() {{
+  pkt;
+}}
+for pkt in cap.slice(2) {{
+  pkt;
+}}
+cap.slice(2, 1);
+",
+        cap.display()
+    );
+    let mut sent = Vec::new();
+    let mut packet =
+        |ts: u64, pkt: &Packet, _: &Origin| sent.push((ts, *pkt.to_vec().last().unwrap()));
+
+    let mut prog = Program::dummy().unwrap();
+    prog.set_packet_callback(&mut packet);
+    let err = prog.add_stmts(parse(&src).unwrap()).unwrap_err();
+    drop(prog);
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(err, IndexError);
+
+    /* Each batch starts where the last finished, but keeps its own spacing, whether it's emitted
+     * all at once or a packet at a time */
+    let sec = 1_000_000_000;
+    /* They were written with an ethernet header, since the capture is ethernet */
+    let bits = 8 * (14 + 21 + 24);
+    let last = sec + 2_995_000 + bits;
+    let looped = last + 2 * bits + sec;
+    assert_eq!(
+        sent,
+        vec![
+            (sec, b'a'),
+            (sec + 2_000, b'b'),
+            (sec + 2_995_000, b'c'),
+            (last, b'b'),
+            (last + bits, b'c'),
+            (looped, b'a'),
+            (looped + 2_000, b'b'),
+            (looped + 2_995_000, b'c'),
+            (looped + 2_995_000 + bits, b'c'),
+        ]
+    );
+}
+
+/// Run a program with some values defined as if on the command line, returning the lints
+fn run_defined(src: &str, defines: &[(&str, Val)]) -> Result<Vec<String>, Error> {
+    let stmts = parse(src)?;
+    let mut lints = Vec::new();
+    let mut warning = |lint: Lint, _: Loc, msg: &str| lints.push(format!("{}: {}", lint, msg));
+
+    let mut prog = Program::dummy()?;
+    prog.set_warning(&mut warning);
+    for (name, val) in defines {
+        prog.define(name, val.clone());
+    }
+    prog.add_stmts(stmts)?;
+    prog.finish();
+    drop(prog);
+
+    Ok(lints)
+}
+
+#[test]
+fn params() {
+    let src = "
+        let port = param(\"port\", 80u16);
+        assert(port == 8080u16);
+        assert(host == \"example.com\");
+        assert(param(\"server\", 1.2.3.4) == 1.2.3.4);
+    ";
+    let defines = [
+        ("port", Val::U64(8080)),
+        ("host", Val::str("example.com")),
+        ("unused", Val::Bool(true)),
+    ];
+    assert_eq!(
+        run_defined(src, &defines),
+        Ok(vec![
+            "unused_define: `unused` is defined but never used".to_owned()
+        ])
+    );
+
+    let src = "let port = param(\"port\", 80u16);";
+    assert_eq!(
+        run_defined(src, &[("port", Val::U64(0x10000))]),
+        Err(OverflowError)
+    );
+    assert_eq!(
+        run_defined(src, &[("port", Val::str("http"))]),
+        Err(TypeError)
+    );
+
+    /* Only definitions can be replaced by a let, and only with the value of a param() */
+    assert_eq!(
+        run_defined("let x = 1; let x = param(\"x\", 2);", &[]),
+        Err(MultipleAssignError("x".to_owned()))
+    );
+    assert_eq!(
+        run_defined("let port = 80;", &[("port", Val::U64(8080))]),
+        Err(MultipleAssignError("port".to_owned()))
+    );
+    assert_eq!(
+        run_defined("port; let port = 80;", &[("port", Val::U64(8080))]),
+        Err(MultipleAssignError("port".to_owned()))
+    );
+
+    /* Uses of the let which replaced a definition don't count as uses of the definition */
+    assert_eq!(
+        run_defined(
+            "let port = param(\"port\", 80); assert(port == 80);",
+            &[("prot", Val::U64(8080))]
+        ),
+        Ok(vec![
+            "unused_define: `prot` is defined but never used".to_owned()
+        ])
+    );
+    assert_eq!(
+        run_defined(
+            "fn f() { let port = 80; assert(port == 80); } f();",
+            &[("port", Val::U64(8080))]
+        ),
+        Ok(vec![
+            "unused_define: `port` is defined but never used".to_owned()
+        ])
+    );
+}
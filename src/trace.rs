@@ -0,0 +1,47 @@
+use crate::loc::Loc;
+
+use std::fmt;
+use std::path::PathBuf;
+
+/// A call to a user-defined function which was in progress when a packet was emitted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// The name of the function which was called
+    pub func: String,
+    /// The file containing the call, [None] if it wasn't read from a file
+    pub file: Option<PathBuf>,
+    /// Where it was called from
+    pub loc: Loc,
+}
+
+/// Where a packet came from: the statement which emitted it, and the chain of function calls
+/// which led there.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Origin {
+    /// The file containing the statement, [None] if it wasn't read from a file
+    pub file: Option<PathBuf>,
+    pub loc: Loc,
+    /// Calls to user-defined functions, innermost first
+    pub calls: Vec<Frame>,
+}
+
+fn write_site(f: &mut fmt::Formatter, file: &Option<PathBuf>, loc: Loc) -> fmt::Result {
+    if let Some(file) = file {
+        write!(f, "{}:", file.display())?;
+    }
+    write!(f, "{}:{}", loc.line(), loc.col())
+}
+
+impl fmt::Display for Origin {
+    /// eg. `http.rsyn:12:3, in get() from main.rsyn:30:1`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_site(f, &self.file, self.loc)?;
+
+        for frame in self.calls.iter() {
+            write!(f, ", in {}() from ", frame.func)?;
+            write_site(f, &frame.file, frame.loc)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+use crate::libapi::Signature;
+use crate::loc::Loc;
+use crate::parse::Stmt;
+use crate::program::Globals;
+use crate::val::{Val, ValType};
+
+use std::cell::RefCell;
+use std::rc::Weak;
+
+/// A function which was defined by a resynth program with a `fn` statement, as opposed to one
+/// which is provided by the stdlib in a [FuncDef](crate::libapi::FuncDef). Arguments are
+/// untyped, but otherwise they are bound using exactly the same rules as for stdlib functions.
+#[derive(Debug)]
+pub struct UserFunc {
+    pub loc: Loc,
+    pub name: String,
+    /// Invariant: All Positionals must come first, then all Optional
+    pub args: Vec<(String, Option<Val>)>,
+    /// minimum number of args: ie. number of positionals
+    pub min_args: usize,
+    pub body: Vec<Stmt>,
+    /// Names are resolved in the file where the function was defined, not where it was called
+    pub globals: Weak<RefCell<Globals>>,
+}
+
+impl UserFunc {
+    pub fn new(
+        loc: Loc,
+        name: String,
+        args: Vec<(String, Option<Val>)>,
+        body: Vec<Stmt>,
+        globals: Weak<RefCell<Globals>>,
+    ) -> Self {
+        let min_args = args.iter().take_while(|(_, dfl)| dfl.is_none()).count();
+        Self {
+            loc,
+            name,
+            args,
+            min_args,
+            body,
+            globals,
+        }
+    }
+}
+
+impl Eq for UserFunc {}
+impl PartialEq for UserFunc {
+    fn eq(&self, other: &UserFunc) -> bool {
+        std::ptr::eq(self as *const UserFunc, other as *const UserFunc)
+    }
+}
+
+impl Signature for UserFunc {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn nr_args(&self) -> usize {
+        self.args.len()
+    }
+
+    fn min_args(&self) -> usize {
+        self.min_args
+    }
+
+    fn arg_pos(&self, name: &str) -> Option<usize> {
+        self.args.iter().position(|(arg, _)| arg == name)
+    }
+
+    fn arg_name(&self, idx: usize) -> &str {
+        &self.args[idx].0
+    }
+
+    fn arg_default(&self, idx: usize) -> Option<Val> {
+        self.args[idx].1.clone()
+    }
+
+    fn arg_compatible(&self, _idx: usize, _typ: ValType) -> bool {
+        true
+    }
+
+    fn arg_type(&self, _idx: usize) -> ValType {
+        ValType::Any
+    }
+
+    fn collect_type(&self) -> ValType {
+        ValType::Void
+    }
+}
@@ -1,14 +1,16 @@
 use crate::err::Error;
-use crate::err::Error::{NameError, ParseError, TypeError};
-use crate::lex::{TokType, Token};
+use crate::err::Error::{NameError, OverflowError, ParseError, TypeError};
+use crate::lex::{Lexer, TokType, Token};
 use crate::libapi::FuncDef;
 use crate::object::{Obj, ObjRef};
 use crate::str::Buf;
 use crate::sym::Symbol;
 use crate::traits::Dispatchable;
+use crate::userfunc::UserFunc;
 
 use pkt::Packet;
 
+use std::convert::TryFrom;
 use std::fmt::{Debug, Display, Formatter};
 use std::net::{Ipv4Addr, SocketAddrV4};
 use std::rc::Rc;
@@ -22,10 +24,17 @@ pub enum ValType {
     U16,
     U32,
     U64,
+    I8,
+    I16,
+    I32,
+    I64,
     Ip4,
     Sock4,
     Str,
     Type,
+    List,
+    /// Only used for declaring arguments, any value at all is compatible
+    Any,
 
     Obj,
     Func,
@@ -45,10 +54,16 @@ impl Display for ValType {
             ValType::U16 => write!(f, "u16"),
             ValType::U32 => write!(f, "u32"),
             ValType::U64 => write!(f, "u64"),
+            ValType::I8 => write!(f, "i8"),
+            ValType::I16 => write!(f, "i16"),
+            ValType::I32 => write!(f, "i32"),
+            ValType::I64 => write!(f, "i64"),
             ValType::Ip4 => write!(f, "Ip4"),
             ValType::Sock4 => write!(f, "Sock4"),
             ValType::Str => write!(f, "bytes"),
             ValType::Type => write!(f, "type"),
+            ValType::List => write!(f, "list"),
+            ValType::Any => write!(f, "any"),
             _ => write!(f, "{:?}", self),
         }
     }
@@ -83,10 +98,7 @@ pub trait Typed {
     }
 
     fn is_integral(&self) -> bool {
-        matches!(self.val_type(), |ValType::Bool| ValType::U8
-            | ValType::U16
-            | ValType::U32
-            | ValType::U64)
+        self.is_type(ValType::Bool) || self.val_type().is_int()
     }
 
     fn is_string_coercible(&self) -> bool {
@@ -98,6 +110,10 @@ pub trait Typed {
                 | ValType::U16
                 | ValType::U32
                 | ValType::U64
+                | ValType::I8
+                | ValType::I16
+                | ValType::I32
+                | ValType::I64
                 | ValType::Ip4
         )
     }
@@ -107,7 +123,8 @@ pub trait Typed {
     }
 
     fn compatible_with<T: Typed>(&self, other: &T) -> bool {
-        self.type_matches(other)
+        self.is_type(ValType::Any)
+            || self.type_matches(other)
             || self.is_integral() && other.is_integral()
             || (self.is_str() && other.is_string_coercible())
             || (self.is_pktgen() && other.is_pktgen_coercible())
@@ -120,6 +137,58 @@ impl Typed for ValType {
     }
 }
 
+impl ValType {
+    /// Integer types, in order of increasing width
+    const UNSIGNED: [ValType; 4] = [ValType::U8, ValType::U16, ValType::U32, ValType::U64];
+    const SIGNED: [ValType; 4] = [ValType::I8, ValType::I16, ValType::I32, ValType::I64];
+
+    /// Look up an integer type by signedness and width in bits
+    pub fn int(signed: bool, bits: u32) -> Option<ValType> {
+        let types = if signed {
+            &Self::SIGNED
+        } else {
+            &Self::UNSIGNED
+        };
+        types.iter().copied().find(|t| t.int_bits() == Some(bits))
+    }
+
+    /// Parse the suffix of an integer literal, eg. the `u16` in `5u16`
+    pub fn from_suffix(suffix: &str) -> Option<ValType> {
+        Self::UNSIGNED
+            .iter()
+            .chain(Self::SIGNED.iter())
+            .copied()
+            .find(|t| t.to_string() == suffix)
+    }
+
+    pub fn is_int(self) -> bool {
+        self.int_bits().is_some()
+    }
+
+    pub fn is_signed(self) -> bool {
+        Self::SIGNED.contains(&self)
+    }
+
+    pub fn int_bits(self) -> Option<u32> {
+        match self {
+            ValType::U8 | ValType::I8 => Some(8),
+            ValType::U16 | ValType::I16 => Some(16),
+            ValType::U32 | ValType::I32 => Some(32),
+            ValType::U64 | ValType::I64 => Some(64),
+            _ => None,
+        }
+    }
+
+    /// The smallest and largest values of an integer type
+    pub fn int_range(self) -> Option<(i128, i128)> {
+        let bits = self.int_bits()?;
+        Some(match self.is_signed() {
+            true => (-(1 << (bits - 1)), (1 << (bits - 1)) - 1),
+            false => (0, (1 << bits) - 1),
+        })
+    }
+}
+
 /// Represents a static or const version of [Val]. This is used when defining constants in the
 /// stdlib, or when defining default arguments for functions.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -251,12 +320,18 @@ pub enum Val {
     U16(u16),
     U32(u32),
     U64(u64),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
     Ip4(Ipv4Addr),
     Sock4(SocketAddrV4),
     Str(Buf),
+    List(Rc<Vec<Val>>),
     Obj(ObjRef),
     Func(&'static FuncDef),
     Method(ObjRef, &'static FuncDef),
+    UserFunc(Rc<UserFunc>),
     Pkt(Rc<Packet>),
     PktGen(Rc<Vec<Packet>>),
     TimeJump(u64),
@@ -330,57 +405,36 @@ impl From<Val> for bool {
             Val::U8(u) => u != 0,
             Val::U16(u) => u != 0,
             Val::U32(u) => u != 0,
-            Val::U64(u) => u != 0,
-            _ => unreachable!(),
+            _ => v.as_int().unwrap() != 0,
         }
     }
 }
 
 impl From<Val> for u64 {
     fn from(v: Val) -> Self {
-        match v {
-            Val::U8(u) => u as u64,
-            Val::U16(u) => u as u64,
-            Val::U32(u) => u as u64,
-            Val::U64(u) => u,
-            _ => unreachable!(),
-        }
+        /* Args are range-checked when they're bound, so this never truncates */
+        v.as_int().and_then(|i| u64::try_from(i).ok()).unwrap()
     }
 }
 
 impl From<Val> for u32 {
     fn from(v: Val) -> Self {
-        match v {
-            Val::U8(u) => u as u32,
-            Val::U16(u) => u as u32,
-            Val::U32(u) => u,
-            Val::U64(u) => u as u32,
-            _ => unreachable!(),
-        }
+        /* Args are range-checked when they're bound, so this never truncates */
+        v.as_int().and_then(|i| u32::try_from(i).ok()).unwrap()
     }
 }
 
 impl From<Val> for u16 {
     fn from(v: Val) -> Self {
-        match v {
-            Val::U8(u) => u as u16,
-            Val::U16(u) => u,
-            Val::U32(u) => u as u16,
-            Val::U64(u) => u as u16,
-            _ => unreachable!(),
-        }
+        /* Args are range-checked when they're bound, so this never truncates */
+        v.as_int().and_then(|i| u16::try_from(i).ok()).unwrap()
     }
 }
 
 impl From<Val> for u8 {
     fn from(v: Val) -> Self {
-        match v {
-            Val::U8(u) => u,
-            Val::U16(u) => u as u8,
-            Val::U32(u) => u as u8,
-            Val::U64(u) => u as u8,
-            _ => unreachable!(),
-        }
+        /* Args are range-checked when they're bound, so this never truncates */
+        v.as_int().and_then(|i| u8::try_from(i).ok()).unwrap()
     }
 }
 
@@ -412,6 +466,10 @@ impl From<Val> for Buf {
             Val::U16(u) => Buf::from(&u.to_be_bytes()),
             Val::U32(u) => Buf::from(&u.to_be_bytes()),
             Val::U64(u) => Buf::from(&u.to_be_bytes()),
+            Val::I8(i) => Buf::from(&i.to_be_bytes()),
+            Val::I16(i) => Buf::from(&i.to_be_bytes()),
+            Val::I32(i) => Buf::from(&i.to_be_bytes()),
+            Val::I64(i) => Buf::from(&i.to_be_bytes()),
             Val::Ip4(ip) => Buf::from(&u32::from(ip).to_be_bytes()),
             _ => unreachable!(),
         }
@@ -432,6 +490,15 @@ impl From<Val> for Rc<Vec<Packet>> {
     }
 }
 
+impl From<Val> for Rc<Vec<Val>> {
+    fn from(v: Val) -> Self {
+        match v {
+            Val::List(l) => l,
+            _ => unreachable!(),
+        }
+    }
+}
+
 impl From<Val> for Rc<Packet> {
     fn from(v: Val) -> Self {
         match v {
@@ -522,12 +589,18 @@ impl Typed for Val {
             Val::U16(..) => U16,
             Val::U32(..) => U32,
             Val::U64(..) => U64,
+            Val::I8(..) => I8,
+            Val::I16(..) => I16,
+            Val::I32(..) => I32,
+            Val::I64(..) => I64,
             Val::Ip4(..) => Ip4,
             Val::Sock4(..) => Sock4,
             Val::Str(..) => Str,
+            Val::List(..) => List,
             Val::Obj(..) => Obj,
             Val::Func(..) => Func,
             Val::Method(..) => Method,
+            Val::UserFunc(..) => Func,
             Val::Pkt(..) => Pkt,
             Val::PktGen(..) => PktGen,
             Val::TimeJump(..) => TimeJump,
@@ -550,47 +623,143 @@ impl Val {
         match tok.tok_type() {
             StringLiteral => Ok(Str(v.parse().or(Err(ParseError))?)),
             IPv4Literal => Ok(Ip4(v.parse().or(Err(ParseError))?)),
-            IntegerLiteral => Ok(U64(v.parse().or(Err(ParseError))?)),
+            IntegerLiteral => Self::int_literal(&v, 10, false),
             BooleanLiteral => Ok(Bool(v.parse().or(Err(ParseError))?)),
-            HexIntegerLiteral => {
-                let hex = v.strip_prefix("0x").unwrap();
+            HexIntegerLiteral => Self::int_literal(v.strip_prefix("0x").unwrap(), 16, false),
+            _ => unreachable!(),
+        }
+    }
 
-                // XXX: This looks horrendously inefficient
-                let val: u64 = u64::from_str_radix(hex, 16).or(Err(ParseError))?;
+    /// Parse some text which is a single literal, such as a value given on the command line. An
+    /// integer may be preceded by a minus sign. Returns `None` if it's anything else.
+    pub fn from_literal(src: &str) -> Option<Result<Self, Error>> {
+        let mut lex = Lexer::default();
+        match lex.line(1, src).as_deref() {
+            Ok([tok]) if tok.tok_type().is_literal() => Some(Self::from_token(tok)),
+            Ok([minus, tok])
+                if minus.tok_type() == TokType::Minus
+                    && matches!(
+                        tok.tok_type(),
+                        TokType::IntegerLiteral | TokType::HexIntegerLiteral
+                    ) =>
+            {
+                Some(Self::from_neg_token(tok))
+            }
+            _ => None,
+        }
+    }
 
-                Ok(U64(val))
+    /// An integer literal preceded by a unary minus. This is folded in to the literal before
+    /// the range check so that the minimum of each signed type can be written, eg. `-128i8`.
+    pub fn from_neg_token(tok: &Token) -> Result<Self, Error> {
+        let v = tok.val();
+        match tok.tok_type() {
+            TokType::IntegerLiteral => Self::int_literal(&v, 10, true),
+            TokType::HexIntegerLiteral => {
+                Self::int_literal(v.strip_prefix("0x").unwrap(), 16, true)
             }
             _ => unreachable!(),
         }
     }
 
+    /// Integer literals are u64 unless they have a type suffix, eg. `5u16` or `0xffi32`. Negated
+    /// ones take the signed type of the same width, just as with the unary minus operator.
+    fn int_literal(v: &str, radix: u32, neg: bool) -> Result<Self, Error> {
+        let (digits, mut typ) = match v.find(['u', 'i']) {
+            Some(pos) => (
+                &v[..pos],
+                ValType::from_suffix(&v[pos..]).ok_or(ParseError)?,
+            ),
+            None => (v, ValType::U64),
+        };
+
+        let mut val = u64::from_str_radix(digits, radix).or(Err(ParseError))? as i128;
+        if neg {
+            typ = ValType::int(true, typ.int_bits().unwrap()).unwrap();
+            val = -val;
+        }
+
+        Self::from_int(typ, val).map_err(|err| {
+            let sign = if neg { "-" } else { "" };
+            err.with_msg(format!("Literal {}{} out of range for {}", sign, v, typ))
+        })
+    }
+
+    /// The value of any integer type, this doesn't include bools
+    pub fn as_int(&self) -> Option<i128> {
+        match self {
+            Val::U8(u) => Some(*u as i128),
+            Val::U16(u) => Some(*u as i128),
+            Val::U32(u) => Some(*u as i128),
+            Val::U64(u) => Some(*u as i128),
+            Val::I8(i) => Some(*i as i128),
+            Val::I16(i) => Some(*i as i128),
+            Val::I32(i) => Some(*i as i128),
+            Val::I64(i) => Some(*i as i128),
+            _ => None,
+        }
+    }
+
+    /// Construct an integer of the given type, so long as it fits
+    pub fn from_int(typ: ValType, val: i128) -> Result<Self, Error> {
+        let ret = match typ {
+            ValType::U8 => u8::try_from(val).map(Val::U8).ok(),
+            ValType::U16 => u16::try_from(val).map(Val::U16).ok(),
+            ValType::U32 => u32::try_from(val).map(Val::U32).ok(),
+            ValType::U64 => u64::try_from(val).map(Val::U64).ok(),
+            ValType::I8 => i8::try_from(val).map(Val::I8).ok(),
+            ValType::I16 => i16::try_from(val).map(Val::I16).ok(),
+            ValType::I32 => i32::try_from(val).map(Val::I32).ok(),
+            ValType::I64 => i64::try_from(val).map(Val::I64).ok(),
+            _ => unreachable!(),
+        };
+        ret.ok_or(OverflowError)
+    }
+
+    /// Convert an integer or bool to the given integer type, failing if the value doesn't fit
+    pub fn cast(&self, typ: ValType) -> Result<Self, Error> {
+        let val = match self {
+            Val::Bool(b) => *b as i128,
+            _ => self.as_int().ok_or(TypeError)?,
+        };
+
+        Self::from_int(typ, val)
+    }
+
     pub fn method_lookup(&self, name: &str) -> Result<Self, Error> {
         let obj = match self {
             Val::Obj(obj) => obj,
             _ => {
-                println!("no methods for non-object: {:?}", self.val_type());
-                return Err(TypeError);
+                return Err(TypeError.with_msg(format!(
+                    "{} has no method {:?}",
+                    self.val_type(),
+                    name
+                )));
             }
         };
 
-        let sym = obj.lookup_symbol(name).ok_or(NameError)?;
+        let sym = obj.lookup_symbol(name).ok_or_else(|| {
+            NameError.with_msg(format!(
+                "{} has no method {:?}",
+                obj.borrow().class_name(),
+                name
+            ))
+        })?;
 
         match sym {
             Symbol::Func(fndef) => Ok(Val::Method(obj.clone(), fndef)),
-            _ => {
-                println!("calling non-func symbol: {}: {:?}", name, sym);
-                Err(TypeError)
-            }
+            _ => Err(TypeError.with_msg(format!(
+                "{}.{} is not a method",
+                obj.borrow().class_name(),
+                name
+            ))),
         }
     }
 
     pub fn lookup_symbol(&self, name: &str) -> Result<Symbol, Error> {
         match self {
             Val::Obj(obj) => obj.lookup_symbol(name).ok_or(NameError),
-            _ => {
-                println!("no symbols for non-object: {:?}", self.val_type());
-                Err(TypeError)
-            }
+            _ => Err(TypeError.with_msg(format!("no symbols for non-object: {}", self.val_type()))),
         }
     }
 }
@@ -607,6 +776,12 @@ impl From<Packet> for Val {
     }
 }
 
+impl From<Vec<Val>> for Val {
+    fn from(items: Vec<Val>) -> Self {
+        Self::List(items.into())
+    }
+}
+
 impl From<Vec<Packet>> for Val {
     fn from(pkts: Vec<Packet>) -> Self {
         Self::PktGen(pkts.into())